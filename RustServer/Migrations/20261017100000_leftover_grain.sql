@@ -0,0 +1,6 @@
+-- Grain direction of a leftover, relative to its own width/height
+-- 'horizontal': grain runs along width_mm
+-- 'vertical':   grain runs along height_mm
+-- NULL:         no visible grain (MDF, chipboard...), free rotation
+
+ALTER TABLE leftovers ADD COLUMN grain TEXT CHECK(grain IS NULL OR grain IN ('horizontal', 'vertical'));
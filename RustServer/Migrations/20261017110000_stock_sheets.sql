@@ -0,0 +1,17 @@
+-- Catalog of standard sheets that can be bought when leftovers run out
+-- One row per (material, thickness, size); price is per sheet
+CREATE TABLE IF NOT EXISTS stock_sheets (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  material TEXT NOT NULL,
+  thickness_mm INTEGER NOT NULL CHECK(thickness_mm > 0),
+  width_mm INTEGER NOT NULL CHECK(width_mm > 0),
+  height_mm INTEGER NOT NULL CHECK(height_mm > 0),
+  price REAL CHECK(price IS NULL OR price >= 0),
+  in_stock BOOLEAN NOT NULL DEFAULT 1,
+  grain TEXT CHECK(grain IS NULL OR grain IN ('horizontal', 'vertical')),
+  notes TEXT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_stock_sheets_material ON stock_sheets(material, thickness_mm);
+CREATE INDEX IF NOT EXISTS idx_stock_sheets_in_stock ON stock_sheets(in_stock);
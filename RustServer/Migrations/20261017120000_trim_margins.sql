@@ -0,0 +1,4 @@
+-- Edge trim taken off every side before the first cut (chipped edges)
+-- NULL falls back to the trim given in the optimization request
+ALTER TABLE leftovers ADD COLUMN trim_mm INTEGER CHECK(trim_mm IS NULL OR trim_mm >= 0);
+ALTER TABLE stock_sheets ADD COLUMN trim_mm INTEGER CHECK(trim_mm IS NULL OR trim_mm >= 0);
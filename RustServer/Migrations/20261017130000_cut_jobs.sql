@@ -0,0 +1,31 @@
+-- Stored /optimize_cuts runs so plans survive the client session
+-- request_json/result_json hold the OptimizeCutsRequest/Response as sent
+CREATE TABLE IF NOT EXISTS cut_jobs (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  created_by TEXT,
+  status TEXT NOT NULL DEFAULT 'draft' CHECK(status IN ('draft', 'approved', 'cut', 'cancelled')),
+  inventory_hash TEXT NOT NULL,
+  rerun_of INTEGER REFERENCES cut_jobs(id) ON DELETE SET NULL,
+  efficiency_percent REAL NOT NULL,
+  total_cuts_placed INTEGER NOT NULL,
+  total_cuts_requested INTEGER NOT NULL,
+  request_json TEXT NOT NULL,
+  result_json TEXT NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_cut_jobs_status ON cut_jobs(status);
+CREATE INDEX IF NOT EXISTS idx_cut_jobs_created_at ON cut_jobs(created_at);
+
+-- Status history of each job, oldest first
+CREATE TABLE IF NOT EXISTS cut_job_events (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  job_id INTEGER NOT NULL REFERENCES cut_jobs(id) ON DELETE CASCADE,
+  status TEXT NOT NULL,
+  changed_by TEXT,
+  note TEXT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_cut_job_events_job ON cut_job_events(job_id);
@@ -0,0 +1,13 @@
+-- Leftovers held by a pending cut job so concurrent plans do not share them
+-- At most one reservation per leftover; expired rows are ignored and reused
+CREATE TABLE IF NOT EXISTS leftover_reservations (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  leftover_id INTEGER NOT NULL UNIQUE REFERENCES leftovers(id) ON DELETE CASCADE,
+  cut_job_id INTEGER NOT NULL REFERENCES cut_jobs(id) ON DELETE CASCADE,
+  reserved_by TEXT,
+  expires_at TIMESTAMP NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_leftover_reservations_job ON leftover_reservations(cut_job_id);
+CREATE INDEX IF NOT EXISTS idx_leftover_reservations_expires ON leftover_reservations(expires_at);
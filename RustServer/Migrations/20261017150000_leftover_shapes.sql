@@ -0,0 +1,4 @@
+-- Outline of non-rectangular leftovers as JSON: {"rects": [{x, y, width_mm, height_mm}, ...]}
+-- with disjoint rectangles; width_mm/height_mm hold the bounding box
+-- NULL means the leftover is a plain rectangle
+ALTER TABLE leftovers ADD COLUMN shape TEXT CHECK(shape IS NULL OR json_valid(shape));
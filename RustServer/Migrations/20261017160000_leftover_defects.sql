@@ -0,0 +1,14 @@
+-- Areas of a leftover that must not end up in a piece (knots, screw holes, water damage)
+-- Coordinates are in the leftover's own frame, like piece placements
+CREATE TABLE IF NOT EXISTS leftover_defects (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  leftover_id INTEGER NOT NULL REFERENCES leftovers(id) ON DELETE CASCADE,
+  x INTEGER NOT NULL CHECK(x >= 0),
+  y INTEGER NOT NULL CHECK(y >= 0),
+  width_mm INTEGER NOT NULL CHECK(width_mm > 0),
+  height_mm INTEGER NOT NULL CHECK(height_mm > 0),
+  description TEXT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_leftover_defects_leftover ON leftover_defects(leftover_id);
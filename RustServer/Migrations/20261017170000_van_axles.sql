@@ -0,0 +1,9 @@
+-- Axle positions and limits for the load balance report
+-- Positions are measured like wheel_well_start_x_mm: from the back door towards the cab.
+-- The front axle usually sits under the cab, beyond length_mm.
+
+ALTER TABLE vans ADD COLUMN rear_axle_x_mm INTEGER CHECK(rear_axle_x_mm IS NULL OR rear_axle_x_mm >= 0);
+ALTER TABLE vans ADD COLUMN front_axle_x_mm INTEGER CHECK(front_axle_x_mm IS NULL OR front_axle_x_mm >= 0);
+ALTER TABLE vans ADD COLUMN max_rear_axle_kg INTEGER CHECK(max_rear_axle_kg IS NULL OR max_rear_axle_kg > 0);
+ALTER TABLE vans ADD COLUMN max_front_axle_kg INTEGER CHECK(max_front_axle_kg IS NULL OR max_front_axle_kg > 0);
+ALTER TABLE vans ADD COLUMN max_side_imbalance_percent REAL CHECK(max_side_imbalance_percent IS NULL OR (max_side_imbalance_percent > 0 AND max_side_imbalance_percent <= 100));
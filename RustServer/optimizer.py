@@ -169,6 +169,25 @@ def solve_packing(data):
 
             model.AddBoolOr([left, right, below, above, front, back, b1['is_packed'].Not(), b2['is_packed'].Not()])
 
+    # --- DELIVERY STOPS ---
+    # Stop 1 comes off first through the rear doors at x = 0. An item for a later stop
+    # must be deeper in the van, beside or below an item for an earlier stop.
+    def stop_rank(b):
+        stop = b['wrapper']['data'].get('stop_sequence')
+        return to_int(stop) if stop is not None else 2 ** 32
+
+    loads = [b for b in boxes if not b['is_obstacle']]
+    if any(b['wrapper']['data'].get('stop_sequence') is not None for b in loads):
+        for e in loads:
+            for l in loads:
+                if stop_rank(l) <= stop_rank(e): continue
+                deeper, beside_l, beside_r, below = (model.NewBoolVar(f"{l['idx']}{k}{e['idx']}") for k in 'DLRU')
+                model.Add(l['x'] >= e['xe']).OnlyEnforceIf(deeper)
+                model.Add(l['ze'] <= e['z']).OnlyEnforceIf(beside_l)
+                model.Add(e['ze'] <= l['z']).OnlyEnforceIf(beside_r)
+                model.Add(l['ye'] <= e['y']).OnlyEnforceIf(below)
+                model.AddBoolOr([deeper, beside_l, beside_r, below, e['is_packed'].Not(), l['is_packed'].Not()])
+
     # --- PHYSICAL STABILITY: CENTER OF GRAVITY SUPPORT ---
     # For every item i, if it is NOT on the floor (y > 0), it MUST be supported by another item j.
     # Support definition:
@@ -237,7 +256,7 @@ def solve_packing(data):
     # --- SOLVE ---
     solver = cp_model.CpSolver()
     # Increased time limit for stability calculations
-    solver.parameters.max_time_in_seconds = 30.0 
+    solver.parameters.max_time_in_seconds = float(data.get('time_limit_s', 30))
     solver.parameters.num_search_workers = 8
     
     with suppress_stdout():
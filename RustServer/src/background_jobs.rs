@@ -0,0 +1,393 @@
+use crate::cut_jobs::run_and_store;
+use crate::cutting_optimizer::{validate_optimize_request, OptimizeCutsRequest};
+use crate::{plan_van_load, AppError, AppState, OptimizeRequest};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Json,
+};
+use futures_util::{stream, Stream};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use tokio::sync::watch;
+use tokio::task::AbortHandle;
+use uuid::Uuid;
+
+/// Finished jobs are forgotten after this long; cut plans stay available as
+/// cut jobs.
+const JOB_RETENTION: Duration = Duration::from_secs(60 * 60);
+const MAX_ACTIVE_JOBS: usize = 8;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    Cuts,
+    Loading,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    fn is_finished(self) -> bool {
+        matches!(
+            self,
+            JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+}
+
+/// Live state of a job, pushed to SSE subscribers on every change. The cut
+/// fields stay zero for van loads.
+#[derive(Serialize, Clone)]
+pub struct JobProgress {
+    pub status: JobStatus,
+    /// Solver passes finished so far; each new catalog sheet adds one.
+    pub pass: u32,
+    pub step: usize,
+    pub steps_total: usize,
+    pub cuts_placed: usize,
+    pub cuts_total: usize,
+    /// Highest efficiency any finished pass reached.
+    pub best_efficiency_percent: Option<f64>,
+    pub error: Option<String>,
+}
+
+struct Job {
+    kind: JobKind,
+    created_at: String,
+    started: Instant,
+    finished: Mutex<Option<Instant>>,
+    progress: watch::Sender<JobProgress>,
+    cancel: AtomicBool,
+    abort: Mutex<Option<AbortHandle>>,
+    result: Mutex<Option<serde_json::Value>>,
+}
+
+#[derive(Serialize)]
+pub struct JobView {
+    pub id: Uuid,
+    pub kind: JobKind,
+    pub created_at: String,
+    pub elapsed_ms: u64,
+    #[serde(flatten)]
+    pub progress: JobProgress,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+}
+
+impl Job {
+    fn view(&self, id: Uuid, with_result: bool) -> JobView {
+        let finished = *self.finished.lock().unwrap();
+        JobView {
+            id,
+            kind: self.kind,
+            created_at: self.created_at.clone(),
+            elapsed_ms: finished
+                .unwrap_or_else(Instant::now)
+                .duration_since(self.started)
+                .as_millis() as u64,
+            progress: self.progress.borrow().clone(),
+            result: if with_result {
+                self.result.lock().unwrap().clone()
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Records the final state; a cancelled job keeps its status. The result
+    /// is stored before the status changes, since subscribers read it as soon
+    /// as they see the job finished.
+    fn finish(&self, outcome: Result<serde_json::Value, String>) {
+        if self.progress.borrow().status.is_finished() {
+            return;
+        }
+        let (result, mut error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(e) => (None, Some(e)),
+        };
+        let stored = result.is_some();
+        *self.finished.lock().unwrap() = Some(Instant::now());
+        *self.result.lock().unwrap() = result;
+
+        let mut applied = false;
+        self.progress.send_modify(|p| {
+            if p.status.is_finished() {
+                return;
+            }
+            applied = true;
+            match error.take() {
+                None => p.status = JobStatus::Completed,
+                Some(e) => {
+                    p.status = JobStatus::Failed;
+                    p.error = Some(e);
+                }
+            }
+        });
+        // Cancelled in the meantime
+        if !applied && stored {
+            *self.result.lock().unwrap() = None;
+        }
+    }
+}
+
+/// Handle a solver uses to report progress and notice cancellation.
+#[derive(Clone)]
+pub struct JobMonitor {
+    job: Arc<Job>,
+}
+
+impl JobMonitor {
+    pub fn is_cancelled(&self) -> bool {
+        self.job.cancel.load(Ordering::Relaxed)
+    }
+
+    pub fn step(&self, step: usize, steps_total: usize) {
+        self.job.progress.send_if_modified(|p| {
+            let changed = p.step != step || p.steps_total != steps_total;
+            p.step = step;
+            p.steps_total = steps_total;
+            changed
+        });
+    }
+
+    pub fn pass_done(&self, cuts_placed: usize, cuts_total: usize, efficiency_percent: f64) {
+        self.job.progress.send_modify(|p| {
+            p.pass += 1;
+            p.cuts_placed = cuts_placed;
+            p.cuts_total = cuts_total;
+            p.best_efficiency_percent = Some(
+                p.best_efficiency_percent
+                    .map_or(efficiency_percent, |best| best.max(efficiency_percent)),
+            );
+        });
+    }
+
+    fn start(&self) {
+        self.job.progress.send_modify(|p| {
+            if p.status == JobStatus::Queued {
+                p.status = JobStatus::Running;
+            }
+        });
+    }
+}
+
+/// In-memory registry of background optimizations, shared through
+/// `AppState`. Jobs do not survive a restart.
+#[derive(Default)]
+pub struct JobRegistry {
+    jobs: Mutex<HashMap<Uuid, Arc<Job>>>,
+}
+
+impl JobRegistry {
+    fn create(&self, kind: JobKind) -> Result<(Uuid, Arc<Job>), AppError> {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.retain(|_, job| match *job.finished.lock().unwrap() {
+            Some(at) => at.elapsed() < JOB_RETENTION,
+            None => true,
+        });
+        let active = jobs
+            .values()
+            .filter(|j| !j.progress.borrow().status.is_finished())
+            .count();
+        if active >= MAX_ACTIVE_JOBS {
+            return Err(AppError::Conflict(format!(
+                "{} optimizations are already running, try again later",
+                active
+            )));
+        }
+
+        let (progress, _) = watch::channel(JobProgress {
+            status: JobStatus::Queued,
+            pass: 0,
+            step: 0,
+            steps_total: 0,
+            cuts_placed: 0,
+            cuts_total: 0,
+            best_efficiency_percent: None,
+            error: None,
+        });
+        let job = Arc::new(Job {
+            kind,
+            created_at: OffsetDateTime::now_utc()
+                .format(&time::format_description::well_known::Rfc3339)
+                .unwrap_or_default(),
+            started: Instant::now(),
+            finished: Mutex::new(None),
+            progress,
+            cancel: AtomicBool::new(false),
+            abort: Mutex::new(None),
+            result: Mutex::new(None),
+        });
+        let id = Uuid::new_v4();
+        jobs.insert(id, job.clone());
+        Ok((id, job))
+    }
+
+    fn get(&self, id: Uuid) -> Result<Arc<Job>, AppError> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| AppError::NotFound(format!("Job {} not found", id)))
+    }
+}
+
+fn accepted(view: JobView) -> impl IntoResponse {
+    (StatusCode::ACCEPTED, Json(view))
+}
+
+/// Starts `/optimize_cuts` in the background. The finished plan is stored as
+/// a cut job like a direct call.
+pub async fn start_cut_job(
+    State(state): State<AppState>,
+    Json(req): Json<OptimizeCutsRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    validate_optimize_request(&req)?;
+    Ok(accepted(queue_cut_job(&state, req)?))
+}
+
+/// Queues an already validated cut optimization.
+pub fn queue_cut_job(state: &AppState, req: OptimizeCutsRequest) -> Result<JobView, AppError> {
+    let (id, job) = state.jobs.create(JobKind::Cuts)?;
+    let monitor = JobMonitor { job: job.clone() };
+    let db = state.db.clone();
+
+    tracing::info!(job = %id, cuts = %req.cuts.len(), "Queued background cut optimization");
+
+    let handle = tokio::spawn(async move {
+        monitor.start();
+        let outcome = run_and_store(&db, req, None, Some(monitor.clone()))
+            .await
+            .and_then(|response| {
+                serde_json::to_value(response)
+                    .map_err(|e| AppError::Internal(format!("Failed to encode cut plan: {}", e)))
+            });
+        monitor.job.finish(outcome.map_err(|e| e.to_string()));
+    });
+    *job.abort.lock().unwrap() = Some(handle.abort_handle());
+
+    Ok(job.view(id, false))
+}
+
+/// Starts `/optimize` (van loading) in the background.
+pub async fn start_loading_job(
+    State(state): State<AppState>,
+    Json(req): Json<OptimizeRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let (id, job) = state.jobs.create(JobKind::Loading)?;
+    let monitor = JobMonitor { job: job.clone() };
+    let db = state.db.clone();
+
+    tracing::info!(job = %id, van_id = %req.van_id, "Queued background van loading");
+
+    // Aborting this task drops the sidecar process, which kills it.
+    let handle = tokio::spawn(async move {
+        monitor.start();
+        let outcome = plan_van_load(&db, req).await.and_then(|response| {
+            serde_json::to_value(response)
+                .map_err(|e| AppError::Internal(format!("Failed to encode loading plan: {}", e)))
+        });
+        monitor.job.finish(outcome.map_err(|e| e.to_string()));
+    });
+    *job.abort.lock().unwrap() = Some(handle.abort_handle());
+
+    Ok(accepted(job.view(id, false)))
+}
+
+pub async fn list_jobs(State(state): State<AppState>) -> Result<impl IntoResponse, AppError> {
+    let jobs = state.jobs.jobs.lock().unwrap();
+    let mut views: Vec<JobView> = jobs.iter().map(|(id, job)| job.view(*id, false)).collect();
+    views.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(Json(views))
+}
+
+pub async fn get_job(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(Json(state.jobs.get(id)?.view(id, true)))
+}
+
+pub async fn cancel_job(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    let job = state.jobs.get(id)?;
+
+    let mut cancelled = false;
+    job.progress.send_if_modified(|p| {
+        if p.status.is_finished() {
+            return false;
+        }
+        p.status = JobStatus::Cancelled;
+        cancelled = true;
+        true
+    });
+    if !cancelled {
+        return Err(AppError::Conflict(format!("Job {} already finished", id)));
+    }
+
+    job.cancel.store(true, Ordering::Relaxed);
+    *job.finished.lock().unwrap() = Some(Instant::now());
+    // The cut solver runs on a blocking thread and stops on the flag; van
+    // loads stop when their task is aborted.
+    if job.kind == JobKind::Loading {
+        if let Some(handle) = job.abort.lock().unwrap().take() {
+            handle.abort();
+        }
+    }
+
+    tracing::info!(job = %id, "Cancelled background job");
+    Ok(Json(job.view(id, false)))
+}
+
+/// Server-sent events: `progress` on every change, then one `result` event
+/// with the final job (including the plan) before the stream closes.
+pub async fn job_events(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, AppError> {
+    let job = state.jobs.get(id)?;
+    let rx = job.progress.subscribe();
+
+    let events = stream::unfold(
+        (job, rx, true, false),
+        move |(job, mut rx, first, done)| async move {
+            if done {
+                return None;
+            }
+            if !first && rx.changed().await.is_err() {
+                return None;
+            }
+            let progress = rx.borrow_and_update().clone();
+            if progress.status.is_finished() {
+                let event = Event::default()
+                    .event("result")
+                    .json_data(job.view(id, true));
+                return Some((event, (job, rx, false, true)));
+            }
+            let event = Event::default().event("progress").json_data(progress);
+            Some((event, (job, rx, false, false)))
+        },
+    );
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
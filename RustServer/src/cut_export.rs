@@ -0,0 +1,412 @@
+use crate::cut_jobs::fetch_plan;
+use crate::cutting_optimizer::{OptimizeCutsResponse, UsedPlank};
+use crate::{AppError, AppState};
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write as _;
+
+const SVG_MARGIN: i64 = 80;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Svg,
+    Dxf,
+    Zip,
+}
+
+#[derive(Deserialize)]
+pub struct ExportCutsRequest {
+    /// Plan to render; alternatively `cut_job_id` names a stored one.
+    pub plan: Option<OptimizeCutsResponse>,
+    pub cut_job_id: Option<i64>,
+    pub format: ExportFormat,
+    /// Plank to render for `svg`/`dxf`; optional when the plan has one plank.
+    pub resto_id: Option<i64>,
+    /// Overrides the kerf stored in the plan.
+    pub kerf_width_mm: Option<i64>,
+}
+
+pub async fn export_cuts(
+    State(state): State<AppState>,
+    Json(req): Json<ExportCutsRequest>,
+) -> Result<Response, AppError> {
+    let plan = match (req.plan, req.cut_job_id) {
+        (Some(plan), None) => plan,
+        (None, Some(id)) => fetch_plan(&state.db, id).await?.1,
+        _ => {
+            return Err(AppError::Validation(
+                "Send either plan or cut_job_id".to_string(),
+            ))
+        }
+    };
+    let kerf = req.kerf_width_mm.unwrap_or(plan.kerf_width_mm).max(0);
+    export_plan(&plan, req.format, req.resto_id, kerf)
+}
+
+/// Renders `plan` as a single SVG/DXF file for one plank, or a zip with both
+/// files for every plank.
+pub fn export_plan(
+    plan: &OptimizeCutsResponse,
+    format: ExportFormat,
+    resto_id: Option<i64>,
+    kerf: i64,
+) -> Result<Response, AppError> {
+    if plan.used_planks.is_empty() {
+        return Err(AppError::Validation("Plan has no used planks".to_string()));
+    }
+
+    if format == ExportFormat::Zip {
+        let bytes = render_zip(&plan.used_planks, kerf)?;
+        return Ok(attachment("application/zip", "cut_plan.zip", bytes));
+    }
+
+    let plank = match resto_id {
+        Some(id) => plan
+            .used_planks
+            .iter()
+            .find(|p| p.resto_id == id)
+            .ok_or_else(|| AppError::NotFound(format!("Plank {} is not in the plan", id)))?,
+        None if plan.used_planks.len() == 1 => &plan.used_planks[0],
+        None => {
+            return Err(AppError::Validation(
+                "resto_id is required when the plan has several planks".to_string(),
+            ))
+        }
+    };
+
+    let stem = file_stem(plank);
+    Ok(match format {
+        ExportFormat::Svg => attachment(
+            "image/svg+xml",
+            &format!("{}.svg", stem),
+            render_svg(plank, kerf).into_bytes(),
+        ),
+        _ => attachment(
+            "application/dxf",
+            &format!("{}.dxf", stem),
+            render_dxf(plank, kerf).into_bytes(),
+        ),
+    })
+}
+
+fn attachment(content_type: &str, filename: &str, body: Vec<u8>) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+fn file_stem(plank: &UsedPlank) -> String {
+    if plank.resto_id < 0 {
+        format!("new_sheet_{}", -plank.resto_id)
+    } else {
+        format!("resto_{}", plank.resto_id)
+    }
+}
+
+fn piece_label(original_index: usize) -> String {
+    format!("P{}", original_index + 1)
+}
+
+/// Saw kerf strips to the right of and below each piece, clipped to the plank.
+fn kerf_strips(plank: &UsedPlank, kerf: i64) -> Vec<(i64, i64, i64, i64)> {
+    let mut strips = Vec::new();
+    if kerf == 0 {
+        return strips;
+    }
+    for cut in &plank.cuts {
+        let right = cut.x + cut.width;
+        let bottom = cut.y + cut.height;
+        if right < plank.width_mm {
+            let w = kerf.min(plank.width_mm - right);
+            let h = (cut.height + kerf).min(plank.height_mm - cut.y);
+            strips.push((right, cut.y, w, h));
+        }
+        if bottom < plank.height_mm {
+            let w = cut.width.min(plank.width_mm - cut.x);
+            let h = kerf.min(plank.height_mm - bottom);
+            strips.push((cut.x, bottom, w, h));
+        }
+    }
+    strips
+}
+
+fn render_svg(plank: &UsedPlank, kerf: i64) -> String {
+    let w = plank.width_mm;
+    let h = plank.height_mm;
+    let m = SVG_MARGIN;
+    let mut svg = String::new();
+
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}mm" height="{}mm" viewBox="{} {} {} {}">"#,
+        w + 2 * m,
+        h + 2 * m,
+        -m,
+        -m,
+        w + 2 * m,
+        h + 2 * m
+    );
+    let _ = writeln!(
+        svg,
+        r#"<title>{} {} {}mm - {}x{}</title>"#,
+        file_stem(plank),
+        xml_escape(&plank.material),
+        plank.thickness_mm,
+        w,
+        h
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect id="outline" x="0" y="0" width="{}" height="{}" fill="#f4e7d3" stroke="#000" stroke-width="2"/>"##,
+        w, h
+    );
+
+    if plank.trim_mm > 0 {
+        let t = plank.trim_mm;
+        let _ = writeln!(
+            svg,
+            r##"<rect id="trim" x="{}" y="{}" width="{}" height="{}" fill="none" stroke="#d33" stroke-width="1" stroke-dasharray="10 6"/>"##,
+            t,
+            t,
+            w - 2 * t,
+            h - 2 * t
+        );
+    }
+
+    svg.push_str("<g id=\"kerf\" fill=\"#d33\" fill-opacity=\"0.5\">\n");
+    for (x, y, kw, kh) in kerf_strips(plank, kerf) {
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}"/>"#,
+            x, y, kw, kh
+        );
+    }
+    svg.push_str("</g>\n");
+
+    svg.push_str("<g id=\"cuts\" fill=\"#9cc3e6\" stroke=\"#1f4e79\" stroke-width=\"1.5\">\n");
+    for cut in &plank.cuts {
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}"/>"#,
+            cut.x, cut.y, cut.width, cut.height
+        );
+    }
+    svg.push_str("</g>\n");
+
+    svg.push_str("<g id=\"labels\" font-family=\"sans-serif\" text-anchor=\"middle\">\n");
+    for cut in &plank.cuts {
+        let size = (cut.width.min(cut.height) / 5).clamp(8, 60);
+        let cx = cut.x + cut.width / 2;
+        let cy = cut.y + cut.height / 2;
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" font-size="{}" font-weight="bold">{}</text>"#,
+            cx,
+            cy,
+            size,
+            piece_label(cut.original_index)
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" font-size="{}">{} x {}{}</text>"#,
+            cx,
+            cy + size,
+            size * 3 / 4,
+            cut.width,
+            cut.height,
+            if cut.rotated { " (R)" } else { "" }
+        );
+    }
+    svg.push_str("</g>\n");
+
+    // Overall dimensions above and left of the plank
+    let _ = writeln!(
+        svg,
+        r##"<g id="dimensions" stroke="#555" font-family="sans-serif" font-size="28" fill="#555" text-anchor="middle">
+<line x1="0" y1="{d}" x2="{w}" y2="{d}"/>
+<line x1="0" y1="{t1}" x2="0" y2="{t2}"/><line x1="{w}" y1="{t1}" x2="{w}" y2="{t2}"/>
+<text x="{cx}" y="{ty}" stroke="none">{w}</text>
+<line x1="{d}" y1="0" x2="{d}" y2="{h}"/>
+<line x1="{t1}" y1="0" x2="{t2}" y2="0"/><line x1="{t1}" y1="{h}" x2="{t2}" y2="{h}"/>
+<text x="{tx}" y="{cy}" stroke="none" transform="rotate(-90 {tx} {cy})">{h}</text>
+</g>"##,
+        d = -m / 2,
+        t1 = -m / 2 - 10,
+        t2 = -m / 2 + 10,
+        w = w,
+        h = h,
+        cx = w / 2,
+        cy = h / 2,
+        ty = -m / 2 - 12,
+        tx = -m / 2 - 12,
+    );
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Minimal AutoCAD R12 ASCII DXF. DXF has Y pointing up, so the plank frame
+/// (Y down from the reference corner) is flipped.
+fn render_dxf(plank: &UsedPlank, kerf: i64) -> String {
+    let h = plank.height_mm;
+    let flip = |y: i64| h - y;
+    let mut dxf = String::new();
+
+    dxf.push_str("0\nSECTION\n2\nHEADER\n9\n$INSUNITS\n70\n4\n0\nENDSEC\n");
+    dxf.push_str("0\nSECTION\n2\nTABLES\n0\nTABLE\n2\nLAYER\n70\n6\n");
+    for (name, color) in [
+        ("OUTLINE", 7),
+        ("TRIM", 1),
+        ("CUTS", 5),
+        ("KERF", 1),
+        ("LABELS", 3),
+        ("DIMENSIONS", 8),
+    ] {
+        let _ = write!(
+            dxf,
+            "0\nLAYER\n2\n{}\n70\n0\n62\n{}\n6\nCONTINUOUS\n",
+            name, color
+        );
+    }
+    dxf.push_str("0\nENDTAB\n0\nENDSEC\n");
+
+    dxf.push_str("0\nSECTION\n2\nENTITIES\n");
+    dxf_rect(&mut dxf, "OUTLINE", 0, flip(0), plank.width_mm, -h);
+    if plank.trim_mm > 0 {
+        let t = plank.trim_mm;
+        dxf_rect(
+            &mut dxf,
+            "TRIM",
+            t,
+            flip(t),
+            plank.width_mm - 2 * t,
+            -(h - 2 * t),
+        );
+    }
+    for (x, y, kw, kh) in kerf_strips(plank, kerf) {
+        dxf_rect(&mut dxf, "KERF", x, flip(y), kw, -kh);
+    }
+    for cut in &plank.cuts {
+        dxf_rect(&mut dxf, "CUTS", cut.x, flip(cut.y), cut.width, -cut.height);
+
+        let size = (cut.width.min(cut.height) / 5).clamp(8, 60);
+        let cx = cut.x + cut.width / 2;
+        let cy = flip(cut.y + cut.height / 2);
+        dxf_text(
+            &mut dxf,
+            "LABELS",
+            cx,
+            cy,
+            size,
+            &piece_label(cut.original_index),
+        );
+        dxf_text(
+            &mut dxf,
+            "DIMENSIONS",
+            cx,
+            cy - size * 3 / 2,
+            size * 3 / 4,
+            &format!("{} x {}", cut.width, cut.height),
+        );
+    }
+
+    // Overall dimensions above and left of the plank
+    let gap = SVG_MARGIN / 2;
+    dxf_line(&mut dxf, "DIMENSIONS", 0, h + gap, plank.width_mm, h + gap);
+    dxf_text(
+        &mut dxf,
+        "DIMENSIONS",
+        plank.width_mm / 2,
+        h + gap + 12,
+        28,
+        &plank.width_mm.to_string(),
+    );
+    dxf_line(&mut dxf, "DIMENSIONS", -gap, 0, -gap, h);
+    dxf_text(&mut dxf, "DIMENSIONS", -gap - 12, h / 2, 28, &h.to_string());
+
+    dxf.push_str("0\nENDSEC\n0\nEOF\n");
+    dxf
+}
+
+fn dxf_line(dxf: &mut String, layer: &str, x1: i64, y1: i64, x2: i64, y2: i64) {
+    let _ = write!(
+        dxf,
+        "0\nLINE\n8\n{}\n10\n{}\n20\n{}\n30\n0\n11\n{}\n21\n{}\n31\n0\n",
+        layer, x1, y1, x2, y2
+    );
+}
+
+fn dxf_rect(dxf: &mut String, layer: &str, x: i64, y: i64, w: i64, h: i64) {
+    dxf_line(dxf, layer, x, y, x + w, y);
+    dxf_line(dxf, layer, x + w, y, x + w, y + h);
+    dxf_line(dxf, layer, x + w, y + h, x, y + h);
+    dxf_line(dxf, layer, x, y + h, x, y);
+}
+
+/// Centred TEXT entity (72=1 horizontal centre, 73=2 vertical middle).
+fn dxf_text(dxf: &mut String, layer: &str, x: i64, y: i64, height: i64, text: &str) {
+    let _ = write!(
+        dxf,
+        "0\nTEXT\n8\n{l}\n10\n{x}\n20\n{y}\n30\n0\n40\n{h}\n1\n{t}\n72\n1\n73\n2\n11\n{x}\n21\n{y}\n31\n0\n",
+        l = layer,
+        x = x,
+        y = y,
+        h = height,
+        t = text
+    );
+}
+
+fn render_zip(planks: &[UsedPlank], kerf: i64) -> Result<Vec<u8>, AppError> {
+    let mut buffer = std::io::Cursor::new(Vec::new());
+    let mut zip = zip::ZipWriter::new(&mut buffer);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    let zip_err = |e: zip::result::ZipError| AppError::Internal(format!("Zip error: {}", e));
+    let io_err = |e: std::io::Error| AppError::Internal(format!("Zip error: {}", e));
+
+    // A plan may list the same plank twice; later copies get a suffix so
+    // every file name stays unique.
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for plank in planks {
+        let mut stem = file_stem(plank);
+        let copies = seen.entry(stem.clone()).or_insert(0);
+        *copies += 1;
+        if *copies > 1 {
+            stem = format!("{}_{}", stem, copies);
+        }
+        zip.start_file(format!("{}.svg", stem), options)
+            .map_err(zip_err)?;
+        zip.write_all(render_svg(plank, kerf).as_bytes())
+            .map_err(io_err)?;
+        zip.start_file(format!("{}.dxf", stem), options)
+            .map_err(zip_err)?;
+        zip.write_all(render_dxf(plank, kerf).as_bytes())
+            .map_err(io_err)?;
+    }
+
+    zip.finish().map_err(zip_err)?;
+    Ok(buffer.into_inner())
+}
@@ -0,0 +1,584 @@
+use crate::background_jobs::{queue_cut_job, JobView};
+use crate::cut_jobs::run_and_store;
+use crate::cutting_optimizer::{
+    cut_limit_error, validate_optimize_request, CutRequest, Grain, OptimizeCutsRequest,
+    OptimizeCutsResponse,
+};
+use crate::{validate_material, AppError, AppState};
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const MAX_IMPORT_ROWS: usize = 5000;
+
+/// A CSV column, by header name (case and punctuation are ignored) or by
+/// 0-based position.
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ColumnRef {
+    Index(usize),
+    Name(String),
+}
+
+/// Column of each `CutRequest` field. Unmapped fields are looked up by the
+/// usual header names (`Width`, `Length`, `Qty`, `Thick`, ...).
+#[derive(Deserialize, Default)]
+pub struct CutColumns {
+    pub width_mm: Option<ColumnRef>,
+    pub height_mm: Option<ColumnRef>,
+    pub thickness_mm: Option<ColumnRef>,
+    pub material: Option<ColumnRef>,
+    pub quantity: Option<ColumnRef>,
+    pub grain: Option<ColumnRef>,
+    pub rotation_allowed: Option<ColumnRef>,
+}
+
+/// Values for fields without a column or with an empty cell.
+#[derive(Deserialize, Default)]
+pub struct CutDefaults {
+    pub thickness_mm: Option<i64>,
+    pub material: Option<String>,
+    pub quantity: Option<i32>,
+    pub grain: Option<Grain>,
+    pub rotation_allowed: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct ImportCutsRequest {
+    pub csv: String,
+    /// `,` or `;`; guessed from the first line when omitted.
+    pub delimiter: Option<String>,
+    /// Numbers are written `1.200,5`; defaults to on for `;` files.
+    pub decimal_comma: Option<bool>,
+    #[serde(default = "crate::default_true")]
+    pub has_header: bool,
+    #[serde(default)]
+    pub columns: CutColumns,
+    #[serde(default)]
+    pub defaults: CutDefaults,
+    /// `/optimize_cuts` options (everything but `cuts`). When set and every
+    /// row is valid, the imported cuts are optimized and stored as a cut job.
+    pub optimize: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Queue the optimization as a background job instead of waiting for it.
+    #[serde(default)]
+    pub background: bool,
+}
+
+#[derive(Serialize)]
+pub struct ImportRowReport {
+    /// 1-based line in the CSV.
+    pub line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cut: Option<CutRequest>,
+    pub errors: Vec<String>,
+    /// Values that were changed on the way in, such as rounded millimetres.
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct ImportCutsResponse {
+    pub delimiter: String,
+    pub decimal_comma: bool,
+    /// Column each field was read from.
+    pub columns: BTreeMap<&'static str, String>,
+    pub rows: Vec<ImportRowReport>,
+    pub valid_rows: usize,
+    pub invalid_rows: usize,
+    pub total_pieces: i64,
+    /// The valid rows, ready for `/optimize_cuts`.
+    pub cuts: Vec<CutRequest>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plan: Option<OptimizeCutsResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job: Option<JobView>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Width,
+    Height,
+    Thickness,
+    Material,
+    Quantity,
+    Grain,
+    Rotation,
+}
+
+impl Field {
+    const ALL: [Field; 7] = [
+        Field::Width,
+        Field::Height,
+        Field::Thickness,
+        Field::Material,
+        Field::Quantity,
+        Field::Grain,
+        Field::Rotation,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Width => "width_mm",
+            Field::Height => "height_mm",
+            Field::Thickness => "thickness_mm",
+            Field::Material => "material",
+            Field::Quantity => "quantity",
+            Field::Grain => "grain",
+            Field::Rotation => "rotation_allowed",
+        }
+    }
+
+    /// Header names used by spreadsheets and cut list programs, normalized.
+    fn aliases(self) -> &'static [&'static str] {
+        match self {
+            Field::Width => &["widthmm", "width", "w"],
+            Field::Height => &["heightmm", "height", "h", "lengthmm", "length", "l"],
+            Field::Thickness => &["thicknessmm", "thickness", "thick", "t"],
+            Field::Material => &["material", "materialname", "board", "stock"],
+            Field::Quantity => &["quantity", "qty", "copies", "count", "pcs", "pieces"],
+            Field::Grain => &["grain", "graindirection"],
+            Field::Rotation => &["rotationallowed", "rotation", "rotate", "canrotate"],
+        }
+    }
+
+    fn mapped(self, columns: &CutColumns) -> Option<&ColumnRef> {
+        match self {
+            Field::Width => columns.width_mm.as_ref(),
+            Field::Height => columns.height_mm.as_ref(),
+            Field::Thickness => columns.thickness_mm.as_ref(),
+            Field::Material => columns.material.as_ref(),
+            Field::Quantity => columns.quantity.as_ref(),
+            Field::Grain => columns.grain.as_ref(),
+            Field::Rotation => columns.rotation_allowed.as_ref(),
+        }
+    }
+
+    /// Whether a missing column is covered by `defaults`.
+    fn has_default(self, defaults: &CutDefaults) -> bool {
+        match self {
+            Field::Width | Field::Height => false,
+            Field::Thickness => defaults.thickness_mm.is_some(),
+            Field::Material => defaults.material.is_some(),
+            Field::Quantity | Field::Grain | Field::Rotation => true,
+        }
+    }
+}
+
+fn normalize_header(header: &str) -> String {
+    header
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Picks whichever of `;` and `,` appears more often on the first line.
+fn guess_delimiter(csv: &str) -> u8 {
+    let first = csv.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+    let semicolons = first.matches(';').count();
+    let commas = first.matches(',').count();
+    if semicolons > commas {
+        b';'
+    } else {
+        b','
+    }
+}
+
+/// Parses a millimetre value as written. The other separator is only read
+/// as thousands grouping in front of a decimal (`1.200,5` with decimal
+/// commas), in groups of three digits; anywhere else it is ambiguous, since
+/// `18.5` may be a decimal point in a file that uses `;` for other reasons.
+fn parse_mm(raw: &str, decimal_comma: bool) -> Result<f64, String> {
+    let cleaned: String = raw.chars().filter(|c| !c.is_whitespace()).collect();
+    let unit = cleaned.len().saturating_sub(2);
+    let value = match cleaned.get(unit..) {
+        Some(suffix) if suffix.eq_ignore_ascii_case("mm") => &cleaned[..unit],
+        _ => cleaned.as_str(),
+    };
+    let (decimal, grouping) = if decimal_comma {
+        (',', '.')
+    } else {
+        ('.', ',')
+    };
+
+    let (whole, fraction) = match value.split_once(decimal) {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (value, None),
+    };
+    let mut groups = whole.split(grouping);
+    let lead = groups.next().unwrap_or_default();
+    let rest: Vec<&str> = groups.collect();
+    if !rest.is_empty() {
+        let grouped = fraction.is_some()
+            && !lead.trim_start_matches('-').is_empty()
+            && rest
+                .iter()
+                .all(|g| g.len() == 3 && g.bytes().all(|b| b.is_ascii_digit()));
+        if !grouped {
+            return Err(format!(
+                "'{}' is ambiguous: '{}' is only read as thousands grouping before a '{}' decimal (set decimal_comma to change)",
+                raw.trim(),
+                grouping,
+                decimal
+            ));
+        }
+    }
+
+    let mut normalized = format!("{}{}", lead, rest.concat());
+    if let Some(fraction) = fraction {
+        normalized.push('.');
+        normalized.push_str(fraction);
+    }
+    match normalized.parse::<f64>() {
+        Ok(v) if v.is_finite() && v.abs() < 1e12 => Ok(v),
+        _ => Err(format!("'{}' is not a number", raw.trim())),
+    }
+}
+
+/// Parses a millimetre value and rounds it to whole millimetres, noting in
+/// `warnings` when that changed it.
+fn parse_whole_mm(
+    field: Field,
+    raw: &str,
+    decimal_comma: bool,
+    warnings: &mut Vec<String>,
+) -> Result<i64, String> {
+    let value = parse_mm(raw, decimal_comma)?;
+    let rounded = value.round();
+    if rounded != value {
+        warnings.push(format!(
+            "{}: '{}' rounded to {} mm",
+            field.name(),
+            raw,
+            rounded
+        ));
+    }
+    Ok(rounded as i64)
+}
+
+fn parse_quantity(raw: &str) -> Result<i32, String> {
+    raw.trim()
+        .parse::<i32>()
+        .map_err(|_| format!("'{}' is not a whole number", raw.trim()))
+}
+
+fn parse_grain(raw: &str) -> Result<Option<Grain>, String> {
+    match raw.trim().to_lowercase().as_str() {
+        "" | "none" | "-" | "no" | "n" => Ok(None),
+        "horizontal" | "h" | "width" | "w" => Ok(Some(Grain::Horizontal)),
+        "vertical" | "v" | "length" | "l" | "height" => Ok(Some(Grain::Vertical)),
+        other => Err(format!("'{}' must be horizontal, vertical or none", other)),
+    }
+}
+
+fn parse_bool(raw: &str) -> Result<bool, String> {
+    match raw.trim().to_lowercase().as_str() {
+        "true" | "yes" | "y" | "1" | "x" => Ok(true),
+        "false" | "no" | "n" | "0" => Ok(false),
+        other => Err(format!("'{}' must be yes or no", other)),
+    }
+}
+
+/// Resolves each field to a column index; `None` means "use the default".
+fn resolve_columns(
+    headers: Option<&csv::StringRecord>,
+    width: usize,
+    req: &ImportCutsRequest,
+) -> Result<Vec<(Field, Option<usize>, String)>, AppError> {
+    let normalized: Vec<String> = headers
+        .map(|h| h.iter().map(normalize_header).collect())
+        .unwrap_or_default();
+    let header_name = |idx: usize| {
+        headers
+            .and_then(|h| h.get(idx))
+            .map(|h| h.trim().to_string())
+            .unwrap_or_else(|| format!("column {}", idx))
+    };
+
+    let mut resolved = Vec::with_capacity(Field::ALL.len());
+    for field in Field::ALL {
+        let idx = match field.mapped(&req.columns) {
+            Some(ColumnRef::Index(idx)) => {
+                if *idx >= width {
+                    return Err(AppError::Validation(format!(
+                        "Column {} for {} does not exist; the file has {} columns",
+                        idx,
+                        field.name(),
+                        width
+                    )));
+                }
+                Some(*idx)
+            }
+            Some(ColumnRef::Name(name)) => {
+                if headers.is_none() {
+                    return Err(AppError::Validation(format!(
+                        "Column '{}' for {} needs a header row; map it by position instead",
+                        name,
+                        field.name()
+                    )));
+                }
+                let wanted = normalize_header(name);
+                Some(
+                    normalized
+                        .iter()
+                        .position(|h| *h == wanted)
+                        .ok_or_else(|| {
+                            AppError::Validation(format!(
+                                "Column '{}' for {} is not in the header",
+                                name,
+                                field.name()
+                            ))
+                        })?,
+                )
+            }
+            None => field
+                .aliases()
+                .iter()
+                .find_map(|alias| normalized.iter().position(|h| h == alias)),
+        };
+        if idx.is_none() && !field.has_default(&req.defaults) {
+            return Err(AppError::Validation(format!(
+                "No column for {}; map it in columns{}",
+                field.name(),
+                if matches!(field, Field::Thickness | Field::Material) {
+                    " or set it in defaults"
+                } else {
+                    ""
+                }
+            )));
+        }
+        let label = idx
+            .map(header_name)
+            .unwrap_or_else(|| "default".to_string());
+        resolved.push((field, idx, label));
+    }
+    Ok(resolved)
+}
+
+fn parse_row(
+    record: &csv::StringRecord,
+    columns: &[(Field, Option<usize>, String)],
+    defaults: &CutDefaults,
+    decimal_comma: bool,
+) -> ImportRowReport {
+    let line = record.position().map(|p| p.line() as usize).unwrap_or(0);
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut cut = CutRequest {
+        width_mm: 0,
+        height_mm: 0,
+        thickness_mm: defaults.thickness_mm.unwrap_or(0),
+        material: defaults.material.clone().unwrap_or_default(),
+        quantity: defaults.quantity.unwrap_or(1),
+        grain: defaults.grain,
+        rotation_allowed: defaults.rotation_allowed.unwrap_or(true),
+    };
+
+    for (field, idx, _) in columns {
+        let raw = idx.and_then(|i| record.get(i)).unwrap_or("").trim();
+        if raw.is_empty() {
+            match field {
+                Field::Width | Field::Height => {
+                    errors.push(format!("{} is empty", field.name()));
+                }
+                Field::Thickness if defaults.thickness_mm.is_none() => {
+                    errors.push("thickness_mm is empty".to_string());
+                }
+                _ => {}
+            }
+            continue;
+        }
+        let parsed =
+            match field {
+                Field::Width => parse_whole_mm(*field, raw, decimal_comma, &mut warnings)
+                    .map(|v| cut.width_mm = v),
+                Field::Height => parse_whole_mm(*field, raw, decimal_comma, &mut warnings)
+                    .map(|v| cut.height_mm = v),
+                Field::Thickness => parse_whole_mm(*field, raw, decimal_comma, &mut warnings)
+                    .map(|v| cut.thickness_mm = v),
+                Field::Material => {
+                    cut.material = raw.to_string();
+                    Ok(())
+                }
+                Field::Quantity => parse_quantity(raw).map(|v| cut.quantity = v),
+                Field::Grain => parse_grain(raw).map(|v| cut.grain = v),
+                Field::Rotation => parse_bool(raw).map(|v| cut.rotation_allowed = v),
+            };
+        if let Err(e) = parsed {
+            errors.push(format!("{}: {}", field.name(), e));
+        }
+    }
+
+    if errors.is_empty() {
+        if let Err(e) = validate_material(&cut.material) {
+            errors.push(e.to_string());
+        }
+        if let Some(e) = cut_limit_error(&cut) {
+            errors.push(e);
+        }
+    }
+
+    ImportRowReport {
+        line,
+        cut: errors.is_empty().then_some(cut),
+        errors,
+        warnings,
+    }
+}
+
+/// Converts a CSV cut list into `CutRequest`s with a per-row report, and
+/// optionally optimizes the result.
+pub async fn import_cuts(
+    State(state): State<AppState>,
+    Json(req): Json<ImportCutsRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let delimiter = match req.delimiter.as_deref() {
+        None => guess_delimiter(&req.csv),
+        Some(",") => b',',
+        Some(";") => b';',
+        Some(other) => {
+            return Err(AppError::Validation(format!(
+                "delimiter '{}' must be ',' or ';'",
+                other
+            )))
+        }
+    };
+    let decimal_comma = req.decimal_comma.unwrap_or(delimiter == b';');
+    if decimal_comma && delimiter == b',' {
+        return Err(AppError::Validation(
+            "Decimal commas need ';' as the delimiter".to_string(),
+        ));
+    }
+    if let Some(material) = &req.defaults.material {
+        validate_material(material)?;
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(req.has_header)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(req.csv.as_bytes());
+    let headers = if req.has_header {
+        Some(
+            reader
+                .headers()
+                .map_err(|e| AppError::Validation(format!("Invalid CSV header: {}", e)))?
+                .clone(),
+        )
+    } else {
+        None
+    };
+
+    let mut records = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| AppError::Validation(format!("Invalid CSV: {}", e)))?;
+        if record.iter().all(|f| f.is_empty()) {
+            continue;
+        }
+        records.push(record);
+        if records.len() > MAX_IMPORT_ROWS {
+            return Err(AppError::Validation(format!(
+                "At most {} rows per import",
+                MAX_IMPORT_ROWS
+            )));
+        }
+    }
+    let width = headers
+        .as_ref()
+        .map(|h| h.len())
+        .into_iter()
+        .chain(records.iter().map(|r| r.len()))
+        .max()
+        .unwrap_or(0);
+    if records.is_empty() {
+        return Err(AppError::Validation("The CSV has no rows".to_string()));
+    }
+
+    let columns = resolve_columns(headers.as_ref(), width, &req)?;
+    let rows: Vec<ImportRowReport> = records
+        .iter()
+        .map(|r| parse_row(r, &columns, &req.defaults, decimal_comma))
+        .collect();
+    let cuts: Vec<CutRequest> = rows.iter().filter_map(|r| r.cut.clone()).collect();
+    let invalid_rows = rows.len() - cuts.len();
+    let rounded_rows = rows.iter().filter(|r| !r.warnings.is_empty()).count();
+
+    let mut response = ImportCutsResponse {
+        delimiter: (delimiter as char).to_string(),
+        decimal_comma,
+        columns: columns
+            .iter()
+            .map(|(field, _, label)| (field.name(), label.clone()))
+            .collect(),
+        valid_rows: cuts.len(),
+        invalid_rows,
+        total_pieces: cuts.iter().map(|c| c.quantity as i64).sum(),
+        rows,
+        cuts,
+        plan: None,
+        job: None,
+        warnings: Vec::new(),
+    };
+    if rounded_rows > 0 {
+        response.warnings.push(format!(
+            "{} row(s) had sizes rounded to whole millimetres",
+            rounded_rows
+        ));
+    }
+
+    tracing::info!(
+        rows = response.rows.len(),
+        invalid = invalid_rows,
+        "Imported cut list"
+    );
+
+    if let Some(mut options) = req.optimize {
+        if invalid_rows > 0 {
+            response.warnings.push(format!(
+                "Not optimized: {} row(s) failed validation",
+                invalid_rows
+            ));
+            return Ok(Json(response));
+        }
+        options.insert(
+            "cuts".to_string(),
+            serde_json::to_value(&response.cuts)
+                .map_err(|e| AppError::Internal(format!("Failed to encode cuts: {}", e)))?,
+        );
+        let optimize: OptimizeCutsRequest =
+            serde_json::from_value(serde_json::Value::Object(options))
+                .map_err(|e| AppError::Validation(format!("Invalid optimize options: {}", e)))?;
+        validate_optimize_request(&optimize)?;
+        if req.background {
+            response.job = Some(queue_cut_job(&state, optimize)?);
+        } else {
+            response.plan = Some(run_and_store(&state.db, optimize, None, None).await?);
+        }
+    } else if req.background {
+        response
+            .warnings
+            .push("background has no effect without optimize".to_string());
+    }
+
+    Ok(Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mm_reads_separators_strictly() {
+        assert_eq!(parse_mm("600,5", true), Ok(600.5));
+        assert_eq!(parse_mm("1.200,5", true), Ok(1200.5));
+        assert_eq!(parse_mm("18 Mm", true), Ok(18.0));
+        assert_eq!(parse_mm("1,200.5", false), Ok(1200.5));
+        assert_eq!(parse_mm("600.5", false), Ok(600.5));
+        assert!(parse_mm("600.5", true).is_err());
+        assert!(parse_mm("1.200", true).is_err());
+        assert!(parse_mm("1.20,5", true).is_err());
+        assert!(parse_mm("18,5", false).is_err());
+        assert!(parse_mm("1,2,3", true).is_err());
+    }
+}
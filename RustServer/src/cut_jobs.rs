@@ -0,0 +1,550 @@
+use crate::background_jobs::JobMonitor;
+use crate::cutting_optimizer::{
+    inventory_hash, load_inventory, run_cut_optimization, validate_optimize_request,
+    OptimizeCutsRequest, OptimizeCutsResponse, UsedPlank,
+};
+use crate::reservations::{exclude_reserved, release_job, reserve_leftovers, Reservation};
+use crate::stock_sheets::load_catalog;
+use crate::{validate_notes, AppError, AppState};
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite};
+use std::time::Instant;
+use time::OffsetDateTime;
+
+const DEFAULT_LIST_LIMIT: i64 = 50;
+const MAX_LIST_LIMIT: i64 = 500;
+const MAX_OPERATOR_LEN: usize = 64;
+/// Optimizer runs per request when other plans keep reserving its leftovers.
+const MAX_PLAN_ATTEMPTS: u32 = 2;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum CutJobStatus {
+    Draft,
+    Approved,
+    Cut,
+    Cancelled,
+}
+
+impl CutJobStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            CutJobStatus::Draft => "draft",
+            CutJobStatus::Approved => "approved",
+            CutJobStatus::Cut => "cut",
+            CutJobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Drafts get approved or dropped; an approved job can go back to draft
+    /// until it is cut. `Cut` and `Cancelled` are final.
+    fn can_become(self, next: CutJobStatus) -> bool {
+        use CutJobStatus::*;
+        matches!(
+            (self, next),
+            (Draft, Approved)
+                | (Draft, Cancelled)
+                | (Approved, Draft)
+                | (Approved, Cut)
+                | (Approved, Cancelled)
+        )
+    }
+}
+
+#[derive(Serialize, FromRow)]
+pub struct CutJobSummary {
+    pub id: i64,
+    pub created_by: Option<String>,
+    pub status: CutJobStatus,
+    pub inventory_hash: String,
+    pub rerun_of: Option<i64>,
+    pub efficiency_percent: f64,
+    pub total_cuts_placed: i64,
+    pub total_cuts_requested: i64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Serialize, FromRow)]
+pub struct CutJobEvent {
+    pub status: CutJobStatus,
+    pub changed_by: Option<String>,
+    pub note: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Serialize)]
+pub struct CutJobDetail {
+    #[serde(flatten)]
+    pub job: CutJobSummary,
+    /// The leftovers table no longer matches the snapshot the plan was
+    /// computed from.
+    pub inventory_changed: bool,
+    pub request: OptimizeCutsRequest,
+    pub result: OptimizeCutsResponse,
+    pub events: Vec<CutJobEvent>,
+    /// Leftovers the job still holds.
+    pub reservations: Vec<Reservation>,
+}
+
+#[derive(Deserialize)]
+pub struct CutJobQuery {
+    status: Option<CutJobStatus>,
+    created_by: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateCutJobStatusRequest {
+    status: CutJobStatus,
+    changed_by: Option<String>,
+    note: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RerunCutJobRequest {
+    /// Operator for the new job; the original's when omitted.
+    #[serde(default)]
+    created_by: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PlanDiff {
+    pub from_job: i64,
+    pub to_job: i64,
+    pub inventory_changed: bool,
+    pub efficiency_delta: f64,
+    pub cost_delta: f64,
+    pub cuts_placed_delta: i64,
+    pub new_sheets_delta: i64,
+    /// Leftovers only the second plan cuts from.
+    pub planks_added: Vec<i64>,
+    /// Leftovers only the first plan cuts from.
+    pub planks_removed: Vec<i64>,
+    pub planks_changed: Vec<PlankDiff>,
+    pub planks_unchanged: usize,
+}
+
+#[derive(Serialize)]
+pub struct PlankDiff {
+    pub resto_id: i64,
+    pub pieces_before: usize,
+    pub pieces_after: usize,
+    pub waste_percent_before: f64,
+    pub waste_percent_after: f64,
+}
+
+/// Operator names are optional but must not be blank when given.
+pub fn validate_operator(field: &str, name: &Option<String>) -> Result<(), AppError> {
+    if let Some(n) = name {
+        if n.trim().is_empty() || n.len() > MAX_OPERATOR_LEN {
+            return Err(AppError::Validation(format!(
+                "{} must be 1-{} chars",
+                field, MAX_OPERATOR_LEN
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn now() -> String {
+    OffsetDateTime::now_utc()
+        .format(&time::format_description::well_known::Rfc3339)
+        .unwrap_or_default()
+}
+
+/// Leftovers another plan reserved while this one was being computed.
+struct ReservedMeanwhile(Vec<i64>);
+
+/// Optimizes a validated request against the current inventory and stores
+/// the plan as a new draft job. Leftovers reserved by other jobs are skipped
+/// unless the request includes them; a re-run may use its original's. When
+/// another plan reserves a leftover while this one is computed, the plan is
+/// made once more from what is left, within the same time limit, and the
+/// request fails if that happens again.
+pub async fn run_and_store(
+    db: &Pool<Sqlite>,
+    mut req: OptimizeCutsRequest,
+    rerun_of: Option<i64>,
+    monitor: Option<JobMonitor>,
+) -> Result<OptimizeCutsResponse, AppError> {
+    let started = Instant::now();
+    let time_limit_ms = req.time_limit_ms;
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let mut inventory = load_inventory(db).await?;
+        let hash = inventory_hash(&inventory);
+        let available = inventory.len();
+        if !req.include_reserved {
+            exclude_reserved(db, &mut inventory, rerun_of).await?;
+        }
+        let skipped = available - inventory.len();
+        let catalog = load_catalog(db).await?;
+
+        let mut response =
+            run_cut_optimization(req.clone(), inventory, catalog, monitor.clone()).await?;
+        if monitor.as_ref().is_some_and(|m| m.is_cancelled()) {
+            return Err(AppError::Conflict("Optimization was cancelled".to_string()));
+        }
+        if skipped > 0 && !response.unplaced_cuts.is_empty() {
+            response.warnings.push(format!(
+                "{} leftover(s) reserved by other plans were skipped; set include_reserved to use them",
+                skipped
+            ));
+        }
+
+        match create_job(db, &req, &mut response, &hash, rerun_of).await? {
+            Ok(()) => return Ok(response),
+            Err(ReservedMeanwhile(ids)) => {
+                let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+                if attempts >= MAX_PLAN_ATTEMPTS {
+                    return Err(AppError::Conflict(format!(
+                        "Resto(s) {} were reserved by another plan while this one was computed; try again",
+                        ids.join(", ")
+                    )));
+                }
+                tracing::info!(restos = %ids.join(", "), "Leftovers reserved meanwhile, planning again");
+                let spent = started.elapsed().as_millis() as u64;
+                req.time_limit_ms = time_limit_ms.saturating_sub(spent).max(1);
+            }
+        }
+    }
+}
+
+/// Stores an optimizer run as a draft job and reserves the leftovers it cuts
+/// from. Fills in the job id and reservation of `response`. Nothing is stored
+/// when another plan reserved one of the leftovers in the meantime, unless
+/// the request includes reserved leftovers.
+async fn create_job(
+    db: &Pool<Sqlite>,
+    req: &OptimizeCutsRequest,
+    response: &mut OptimizeCutsResponse,
+    inventory_hash: &str,
+    rerun_of: Option<i64>,
+) -> Result<Result<(), ReservedMeanwhile>, AppError> {
+    let request_json = serde_json::to_string(req)
+        .map_err(|e| AppError::Internal(format!("Failed to encode cut request: {}", e)))?;
+    let created_at = now();
+
+    let mut tx = db.begin().await.map_err(AppError::Database)?;
+
+    let id = sqlx::query("INSERT INTO cut_jobs (created_by, status, inventory_hash, rerun_of, efficiency_percent, total_cuts_placed, total_cuts_requested, request_json, result_json, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, '', ?9, ?9)")
+        .bind(&req.created_by).bind(CutJobStatus::Draft).bind(inventory_hash).bind(rerun_of)
+        .bind(response.efficiency_percent).bind(response.total_cuts_placed as i64).bind(response.total_cuts_requested as i64)
+        .bind(&request_json).bind(&created_at)
+        .execute(&mut *tx).await.map_err(AppError::Database)?
+        .last_insert_rowid();
+
+    sqlx::query("INSERT INTO cut_job_events (job_id, status, changed_by, note, created_at) VALUES (?1, ?2, ?3, ?4, ?5)")
+        .bind(id).bind(CutJobStatus::Draft).bind(&req.created_by)
+        .bind(rerun_of.map(|orig| format!("Re-run of job #{}", orig))).bind(&created_at)
+        .execute(&mut *tx).await.map_err(AppError::Database)?;
+
+    let leftover_ids: Vec<i64> = response
+        .used_planks
+        .iter()
+        .map(|p| p.resto_id)
+        .filter(|&id| id > 0)
+        .collect();
+    if req.reservation_minutes > 0 && !leftover_ids.is_empty() {
+        let reserved = reserve_leftovers(
+            &mut tx,
+            id,
+            &leftover_ids,
+            req.reservation_minutes,
+            req.created_by.as_deref(),
+            rerun_of,
+        )
+        .await?;
+        if !reserved.held_elsewhere.is_empty() && !req.include_reserved {
+            return Ok(Err(ReservedMeanwhile(reserved.held_elsewhere)));
+        }
+        for leftover_id in reserved.held_elsewhere {
+            response.warnings.push(format!(
+                "Resto {} is reserved by another plan and was not reserved for this one",
+                leftover_id
+            ));
+        }
+        if let Some(orig) = rerun_of.filter(|_| !reserved.taken_over.is_empty()) {
+            let (status,): (CutJobStatus,) =
+                sqlx::query_as("SELECT status FROM cut_jobs WHERE id = ?")
+                    .bind(orig)
+                    .fetch_one(&mut *tx)
+                    .await
+                    .map_err(AppError::Database)?;
+            let restos: Vec<String> = reserved.taken_over.iter().map(|r| r.to_string()).collect();
+            sqlx::query("INSERT INTO cut_job_events (job_id, status, changed_by, note, created_at) VALUES (?1, ?2, ?3, ?4, ?5)")
+                .bind(orig).bind(status).bind(&req.created_by)
+                .bind(format!("Reservation of resto(s) {} moved to re-run job #{}", restos.join(", "), id))
+                .bind(&created_at)
+                .execute(&mut *tx).await.map_err(AppError::Database)?;
+        }
+        response.reserved_until = Some(reserved.expires_at);
+    }
+
+    // The plan is stored as answered, reservation warnings and job id included.
+    response.job_id = Some(id);
+    let result_json = serde_json::to_string(response)
+        .map_err(|e| AppError::Internal(format!("Failed to encode cut plan: {}", e)))?;
+    sqlx::query("UPDATE cut_jobs SET result_json = ? WHERE id = ?")
+        .bind(&result_json)
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(AppError::Database)?;
+
+    tx.commit().await.map_err(AppError::Database)?;
+
+    tracing::info!(id = id, "Stored cut job");
+    Ok(Ok(()))
+}
+
+async fn fetch_summary(db: &Pool<Sqlite>, id: i64) -> Result<CutJobSummary, AppError> {
+    sqlx::query_as::<_, CutJobSummary>("SELECT id, created_by, status, inventory_hash, rerun_of, efficiency_percent, total_cuts_placed, total_cuts_requested, created_at, updated_at FROM cut_jobs WHERE id = ?")
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .map_err(AppError::Database)?
+        .ok_or_else(|| AppError::NotFound(format!("Cut job {} not found", id)))
+}
+
+/// The stored request and plan of a job.
+pub async fn fetch_plan(
+    db: &Pool<Sqlite>,
+    id: i64,
+) -> Result<(OptimizeCutsRequest, OptimizeCutsResponse), AppError> {
+    let (request_json, result_json): (String, String) =
+        sqlx::query_as("SELECT request_json, result_json FROM cut_jobs WHERE id = ?")
+            .bind(id)
+            .fetch_optional(db)
+            .await
+            .map_err(AppError::Database)?
+            .ok_or_else(|| AppError::NotFound(format!("Cut job {} not found", id)))?;
+
+    let request = serde_json::from_str(&request_json)
+        .map_err(|e| AppError::Internal(format!("Cut job {} has a corrupt request: {}", id, e)))?;
+    let mut result: OptimizeCutsResponse = serde_json::from_str(&result_json)
+        .map_err(|e| AppError::Internal(format!("Cut job {} has a corrupt plan: {}", id, e)))?;
+    result.job_id = Some(id);
+    Ok((request, result))
+}
+
+async fn current_inventory_hash(db: &Pool<Sqlite>) -> Result<String, AppError> {
+    Ok(inventory_hash(&load_inventory(db).await?))
+}
+
+pub async fn list_cut_jobs(
+    State(state): State<AppState>,
+    Query(params): Query<CutJobQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+
+    let jobs = sqlx::query_as::<_, CutJobSummary>("SELECT id, created_by, status, inventory_hash, rerun_of, efficiency_percent, total_cuts_placed, total_cuts_requested, created_at, updated_at FROM cut_jobs WHERE (?1 IS NULL OR status = ?1) AND (?2 IS NULL OR created_by = ?2) ORDER BY created_at DESC, id DESC LIMIT ?3")
+        .bind(params.status).bind(&params.created_by).bind(limit)
+        .fetch_all(&state.db)
+        .await
+        .map_err(AppError::Database)?;
+
+    Ok(Json(jobs))
+}
+
+pub async fn get_cut_job(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let job = fetch_summary(&state.db, id).await?;
+    let (request, result) = fetch_plan(&state.db, id).await?;
+    let events = sqlx::query_as::<_, CutJobEvent>(
+        "SELECT status, changed_by, note, created_at FROM cut_job_events WHERE job_id = ? ORDER BY id",
+    )
+    .bind(id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(AppError::Database)?;
+    let reservations = sqlx::query_as::<_, Reservation>("SELECT leftover_id, cut_job_id, reserved_by, expires_at, created_at FROM leftover_reservations WHERE cut_job_id = ? AND expires_at > datetime('now') ORDER BY leftover_id")
+        .bind(id)
+        .fetch_all(&state.db)
+        .await
+        .map_err(AppError::Database)?;
+    let inventory_changed = current_inventory_hash(&state.db).await? != job.inventory_hash;
+
+    Ok(Json(CutJobDetail {
+        job,
+        inventory_changed,
+        request,
+        result,
+        events,
+        reservations,
+    }))
+}
+
+pub async fn update_cut_job_status(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<UpdateCutJobStatusRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    validate_notes(&req.note)?;
+    validate_operator("changed_by", &req.changed_by)?;
+    let job = fetch_summary(&state.db, id).await?;
+
+    if !job.status.can_become(req.status) {
+        return Err(AppError::Conflict(format!(
+            "Cut job {} is {} and cannot become {}",
+            id,
+            job.status.as_str(),
+            req.status.as_str()
+        )));
+    }
+
+    let changed_at = now();
+    let mut tx = state.db.begin().await.map_err(AppError::Database)?;
+
+    // Guard on the old status so two operators cannot both move the job.
+    let res = sqlx::query(
+        "UPDATE cut_jobs SET status = ?1, updated_at = ?2 WHERE id = ?3 AND status = ?4",
+    )
+    .bind(req.status)
+    .bind(&changed_at)
+    .bind(id)
+    .bind(job.status)
+    .execute(&mut *tx)
+    .await
+    .map_err(AppError::Database)?;
+    if res.rows_affected() == 0 {
+        return Err(AppError::Conflict(format!(
+            "Cut job {} changed status meanwhile",
+            id
+        )));
+    }
+
+    sqlx::query("INSERT INTO cut_job_events (job_id, status, changed_by, note, created_at) VALUES (?1, ?2, ?3, ?4, ?5)")
+        .bind(id).bind(req.status).bind(&req.changed_by).bind(&req.note).bind(&changed_at)
+        .execute(&mut *tx).await.map_err(AppError::Database)?;
+
+    if matches!(req.status, CutJobStatus::Cut | CutJobStatus::Cancelled) {
+        release_job(&mut tx, id).await?;
+    }
+
+    tx.commit().await.map_err(AppError::Database)?;
+
+    tracing::info!(
+        id = id,
+        status = req.status.as_str(),
+        "Cut job status changed"
+    );
+    Ok(Json(
+        serde_json::json!({ "success": true, "id": id, "status": req.status }),
+    ))
+}
+
+/// Runs a stored request again against the current inventory and stores the
+/// result as a new draft job.
+pub async fn rerun_cut_job(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<RerunCutJobRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let (mut request, _) = fetch_plan(&state.db, id).await?;
+    if req.created_by.is_some() {
+        request.created_by = req.created_by;
+    }
+    validate_optimize_request(&request)?;
+
+    tracing::info!(id = id, "Re-running cut job");
+
+    Ok(Json(
+        run_and_store(&state.db, request, Some(id), None).await?,
+    ))
+}
+
+pub async fn diff_cut_jobs(
+    State(state): State<AppState>,
+    Path((from_id, to_id)): Path<(i64, i64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let from_job = fetch_summary(&state.db, from_id).await?;
+    let to_job = fetch_summary(&state.db, to_id).await?;
+    let (_, before) = fetch_plan(&state.db, from_id).await?;
+    let (_, after) = fetch_plan(&state.db, to_id).await?;
+
+    let mut diff = diff_plans(&before, &after);
+    diff.from_job = from_id;
+    diff.to_job = to_id;
+    diff.inventory_changed = from_job.inventory_hash != to_job.inventory_hash;
+
+    Ok(Json(diff))
+}
+
+/// Compares two plans plank by plank. New sheets have run-specific ids, so
+/// they are only compared by count.
+fn diff_plans(before: &OptimizeCutsResponse, after: &OptimizeCutsResponse) -> PlanDiff {
+    let leftovers = |plan: &OptimizeCutsResponse| -> Vec<UsedPlank> {
+        plan.used_planks
+            .iter()
+            .filter(|p| p.resto_id > 0)
+            .cloned()
+            .collect()
+    };
+    let new_sheets =
+        |plan: &OptimizeCutsResponse| plan.used_planks.iter().filter(|p| p.resto_id < 0).count();
+    let before_planks = leftovers(before);
+    let after_planks = leftovers(after);
+
+    let mut diff = PlanDiff {
+        from_job: 0,
+        to_job: 0,
+        inventory_changed: false,
+        efficiency_delta: after.efficiency_percent - before.efficiency_percent,
+        cost_delta: after.cost.total - before.cost.total,
+        cuts_placed_delta: after.total_cuts_placed as i64 - before.total_cuts_placed as i64,
+        new_sheets_delta: new_sheets(after) as i64 - new_sheets(before) as i64,
+        planks_added: Vec::new(),
+        planks_removed: Vec::new(),
+        planks_changed: Vec::new(),
+        planks_unchanged: 0,
+    };
+
+    for old in &before_planks {
+        let Some(new) = after_planks.iter().find(|p| p.resto_id == old.resto_id) else {
+            diff.planks_removed.push(old.resto_id);
+            continue;
+        };
+        if layout(old) == layout(new) {
+            diff.planks_unchanged += 1;
+        } else {
+            diff.planks_changed.push(PlankDiff {
+                resto_id: old.resto_id,
+                pieces_before: old.cuts.len(),
+                pieces_after: new.cuts.len(),
+                waste_percent_before: old.waste_percent,
+                waste_percent_after: new.waste_percent,
+            });
+        }
+    }
+    for new in &after_planks {
+        if !before_planks.iter().any(|p| p.resto_id == new.resto_id) {
+            diff.planks_added.push(new.resto_id);
+        }
+    }
+
+    diff
+}
+
+fn layout(plank: &UsedPlank) -> Vec<(usize, i64, i64, i64, i64, bool)> {
+    let mut pieces: Vec<_> = plank
+        .cuts
+        .iter()
+        .map(|c| (c.original_index, c.x, c.y, c.width, c.height, c.rotated))
+        .collect();
+    pieces.sort_unstable();
+    pieces
+}
@@ -0,0 +1,545 @@
+use crate::cutting_optimizer::{OptimizeCutsResponse, PlacedCut, UsedPlank};
+use crate::AppError;
+use axum::{
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+const MAX_JOB_REFERENCE_LEN: usize = 64;
+const PRINTER_TIMEOUT: Duration = Duration::from_secs(5);
+
+// A4 sheet, 2 x 7 labels of 105 x 42.4 mm
+const MM: f64 = 72.0 / 25.4;
+const PAGE_W: f64 = 210.0 * MM;
+const PAGE_H: f64 = 297.0 * MM;
+const LABEL_COLS: usize = 2;
+const LABEL_ROWS: usize = 7;
+const LABEL_W: f64 = 105.0 * MM;
+const LABEL_H: f64 = 42.4 * MM;
+const SHEET_TOP: f64 = 0.9 * MM;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelFormat {
+    Zpl,
+    Pdf,
+}
+
+#[derive(Deserialize)]
+pub struct CutLabelsRequest {
+    pub plan: OptimizeCutsResponse,
+    pub job_reference: String,
+    pub format: LabelFormat,
+}
+
+#[derive(Deserialize)]
+pub struct PrintLabelsRequest {
+    pub plan: OptimizeCutsResponse,
+    pub job_reference: String,
+    /// Raw TCP address of the Zebra printer (`host:port`, usually port 9100).
+    /// One of the printers in `LABEL_PRINTER_ADDR`; the first one when omitted.
+    pub printer_address: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PrintLabelsResponse {
+    pub success: bool,
+    pub printer_address: String,
+    pub labels: usize,
+    pub bytes_sent: usize,
+}
+
+/// Everything printed on one piece label.
+struct PieceLabel {
+    job_reference: String,
+    sequence: usize,
+    total: usize,
+    original_index: usize,
+    width_mm: i64,
+    height_mm: i64,
+    thickness_mm: i64,
+    material: String,
+    resto_id: i64,
+    barcode: String,
+}
+
+impl PieceLabel {
+    fn source(&self) -> String {
+        if self.resto_id < 0 {
+            format!("New sheet #{}", -self.resto_id)
+        } else {
+            format!("Resto #{}", self.resto_id)
+        }
+    }
+
+    fn dimensions(&self) -> String {
+        format!(
+            "{} x {} x {} mm",
+            self.width_mm, self.height_mm, self.thickness_mm
+        )
+    }
+}
+
+fn validate_job_reference(job_reference: &str) -> Result<(), AppError> {
+    if job_reference.trim().is_empty() || job_reference.len() > MAX_JOB_REFERENCE_LEN {
+        return Err(AppError::Validation(format!(
+            "job_reference must be 1-{} chars",
+            MAX_JOB_REFERENCE_LEN
+        )));
+    }
+    Ok(())
+}
+
+fn collect_labels(plan: &OptimizeCutsResponse, job_reference: &str) -> Vec<PieceLabel> {
+    let total: usize = plan.used_planks.iter().map(|p| p.cuts.len()).sum();
+    let barcode_job: String = job_reference
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .take(20)
+        .collect();
+
+    let mut labels = Vec::with_capacity(total);
+    for plank in &plan.used_planks {
+        for cut in &plank.cuts {
+            let sequence = labels.len() + 1;
+            labels.push(piece_label(
+                plank,
+                cut,
+                job_reference,
+                &barcode_job,
+                sequence,
+                total,
+            ));
+        }
+    }
+    labels
+}
+
+fn piece_label(
+    plank: &UsedPlank,
+    cut: &PlacedCut,
+    job_reference: &str,
+    barcode_job: &str,
+    sequence: usize,
+    total: usize,
+) -> PieceLabel {
+    // Labels show the piece as it was requested, not as it lies on the plank
+    let (width_mm, height_mm) = if cut.rotated {
+        (cut.height, cut.width)
+    } else {
+        (cut.width, cut.height)
+    };
+    PieceLabel {
+        job_reference: job_reference.to_string(),
+        sequence,
+        total,
+        original_index: cut.original_index,
+        width_mm,
+        height_mm,
+        thickness_mm: cut.thickness_mm,
+        material: cut.material.clone(),
+        resto_id: plank.resto_id,
+        barcode: format!("{}-{}-{}", barcode_job, plank.resto_id, sequence),
+    }
+}
+
+pub async fn cut_labels(Json(req): Json<CutLabelsRequest>) -> Result<Response, AppError> {
+    validate_job_reference(&req.job_reference)?;
+    let labels = collect_labels(&req.plan, &req.job_reference);
+    if labels.is_empty() {
+        return Err(AppError::Validation("Plan has no placed cuts".to_string()));
+    }
+
+    let (content_type, filename, body) = match req.format {
+        LabelFormat::Zpl => (
+            "text/plain; charset=utf-8",
+            "labels.zpl",
+            render_zpl(&labels).into_bytes(),
+        ),
+        LabelFormat::Pdf => ("application/pdf", "labels.pdf", render_pdf(&labels)),
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+pub async fn print_cut_labels(
+    Json(req): Json<PrintLabelsRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    validate_job_reference(&req.job_reference)?;
+    let configured = std::env::var("LABEL_PRINTER_ADDR").unwrap_or_default();
+    let printer_address = resolve_printer(req.printer_address, &configured)?;
+
+    let labels = collect_labels(&req.plan, &req.job_reference);
+    if labels.is_empty() {
+        return Err(AppError::Validation("Plan has no placed cuts".to_string()));
+    }
+    let zpl = render_zpl(&labels);
+
+    tracing::info!(printer = %printer_address, labels = %labels.len(), "Sending labels to printer");
+    send_raw(&printer_address, zpl.as_bytes())
+        .await
+        .map_err(|e| AppError::Internal(format!("Printer {}: {}", printer_address, e)))?;
+
+    Ok(Json(PrintLabelsResponse {
+        success: true,
+        printer_address,
+        labels: labels.len(),
+        bytes_sent: zpl.len(),
+    }))
+}
+
+/// Picks the printer to send to. Only the printers listed in
+/// `LABEL_PRINTER_ADDR` (comma-separated `host:port`) may be used, so the
+/// server never connects to an arbitrary address on a caller's behalf.
+fn resolve_printer(requested: Option<String>, configured: &str) -> Result<String, AppError> {
+    let allowed: Vec<&str> = configured
+        .split(',')
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+        .collect();
+    let Some(default) = allowed.first() else {
+        return Err(AppError::Validation(
+            "No label printer configured (set LABEL_PRINTER_ADDR)".to_string(),
+        ));
+    };
+    match requested {
+        None => Ok(default.to_string()),
+        Some(address) if allowed.contains(&address.trim()) => Ok(address.trim().to_string()),
+        Some(address) => Err(AppError::Validation(format!(
+            "Printer {} is not configured; use one of: {}",
+            address,
+            allowed.join(", ")
+        ))),
+    }
+}
+
+async fn send_raw(address: &str, data: &[u8]) -> std::io::Result<()> {
+    let timed_out = || std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out");
+    let mut stream = tokio::time::timeout(PRINTER_TIMEOUT, tokio::net::TcpStream::connect(address))
+        .await
+        .map_err(|_| timed_out())??;
+    tokio::time::timeout(PRINTER_TIMEOUT, async {
+        stream.write_all(data).await?;
+        stream.shutdown().await
+    })
+    .await
+    .map_err(|_| timed_out())?
+}
+
+/// ZPL field data cannot contain the `^` and `~` control prefixes.
+fn zpl_text(s: &str) -> String {
+    s.chars().filter(|c| *c != '^' && *c != '~').collect()
+}
+
+/// One 4 x 2 inch label per piece at 203 dpi.
+fn render_zpl(labels: &[PieceLabel]) -> String {
+    let mut zpl = String::new();
+    for label in labels {
+        let _ = write!(
+            zpl,
+            "^XA\n^CI28\n^PW812\n^LL406\n\
+             ^FO30,25^A0N,36,36^FD{job}^FS\n\
+             ^FO600,25^A0N,30,30^FD{seq}/{total}^FS\n\
+             ^FO30,70^A0N,52,52^FD{dims}^FS\n\
+             ^FO30,130^A0N,30,30^FD{material} {thickness}mm^FS\n\
+             ^FO30,168^A0N,30,30^FDP{piece} - {source}^FS\n\
+             ^FO30,215^BY2^BCN,120,Y,N,N^FD{barcode}^FS\n\
+             ^XZ\n",
+            job = zpl_text(&label.job_reference),
+            seq = label.sequence,
+            total = label.total,
+            dims = label.dimensions(),
+            material = zpl_text(&label.material),
+            thickness = label.thickness_mm,
+            piece = label.original_index + 1,
+            source = label.source(),
+            barcode = label.barcode,
+        );
+    }
+    zpl
+}
+
+/// Code 128 module widths (bar, space, bar, space, bar, space) for values
+/// 0-105; the stop pattern is kept separately.
+const CODE128_PATTERNS: [&str; 106] = [
+    "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312", "132212",
+    "221213", "221312", "231212", "112232", "122132", "122231", "113222", "123122", "123221",
+    "223211", "221132", "221231", "213212", "223112", "312131", "311222", "321122", "321221",
+    "312212", "322112", "322211", "212123", "212321", "232121", "111323", "131123", "131321",
+    "112313", "132113", "132311", "211313", "231113", "231311", "112133", "112331", "132131",
+    "113123", "113321", "133121", "313121", "211331", "231131", "213113", "213311", "213131",
+    "311123", "311321", "331121", "312113", "312311", "332111", "314111", "221411", "431111",
+    "111224", "111422", "121124", "121421", "141122", "141221", "112214", "112412", "122114",
+    "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111", "111242",
+    "121142", "121241", "114212", "124112", "124211", "411212", "421112", "421211", "212141",
+    "214121", "412121", "111143", "111341", "131141", "114113", "114311", "411113", "411311",
+    "113141", "114131", "311141", "411131", "211412", "211214", "211232",
+];
+const CODE128_STOP: &str = "2331112";
+const CODE128_START_B: usize = 104;
+
+/// Code 128 subset B encoding of printable ASCII as alternating bar/space
+/// widths in modules, including start, checksum and stop symbols.
+fn code128b_modules(data: &str) -> Vec<u8> {
+    let values: Vec<usize> = data
+        .bytes()
+        .filter(|b| (32..127).contains(b))
+        .map(|b| (b - 32) as usize)
+        .collect();
+    let checksum = values
+        .iter()
+        .enumerate()
+        .fold(CODE128_START_B, |acc, (i, v)| acc + v * (i + 1))
+        % 103;
+
+    let mut symbols = vec![CODE128_PATTERNS[CODE128_START_B]];
+    symbols.extend(values.iter().map(|&v| CODE128_PATTERNS[v]));
+    symbols.push(CODE128_PATTERNS[checksum]);
+    symbols.push(CODE128_STOP);
+
+    symbols
+        .iter()
+        .flat_map(|p| p.bytes().map(|b| b - b'0'))
+        .collect()
+}
+
+/// PDF string literal in WinAnsiEncoding; characters outside Latin-1 become `?`.
+fn pdf_text(s: &str) -> String {
+    let mut out = String::from("(");
+    for c in s.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            '\u{a0}'..='\u{ff}' => {
+                let _ = write!(out, "\\{:03o}", c as u32);
+            }
+            _ => out.push('?'),
+        }
+    }
+    out.push(')');
+    out
+}
+
+fn pdf_text_at(content: &mut String, size: f64, bold: bool, x: f64, y: f64, text: &str) {
+    let _ = writeln!(
+        content,
+        "BT /{} {:.1} Tf {:.2} {:.2} Td {} Tj ET",
+        if bold { "F2" } else { "F1" },
+        size,
+        x,
+        y,
+        pdf_text(text)
+    );
+}
+
+fn pdf_label_content(content: &mut String, label: &PieceLabel, x: f64, y_top: f64) {
+    let pad = 4.0 * MM;
+    let left = x + pad;
+
+    // Helvetica digits are 0.556 em wide, enough to right-align the counter
+    let counter = format!("{}/{}", label.sequence, label.total);
+    let counter_w = counter.len() as f64 * 8.0 * 0.556;
+    pdf_text_at(
+        content,
+        8.0,
+        false,
+        x + LABEL_W - pad - counter_w,
+        y_top - pad - 8.0,
+        &counter,
+    );
+    pdf_text_at(
+        content,
+        10.0,
+        true,
+        left,
+        y_top - pad - 8.0,
+        &label.job_reference,
+    );
+    pdf_text_at(
+        content,
+        14.0,
+        true,
+        left,
+        y_top - pad - 24.0,
+        &label.dimensions(),
+    );
+    pdf_text_at(
+        content,
+        9.0,
+        false,
+        left,
+        y_top - pad - 36.0,
+        &format!("{} {}mm", label.material, label.thickness_mm),
+    );
+    pdf_text_at(
+        content,
+        9.0,
+        false,
+        left,
+        y_top - pad - 47.0,
+        &format!("P{} - {}", label.original_index + 1, label.source()),
+    );
+
+    // Barcode along the bottom of the label
+    let modules = code128b_modules(&label.barcode);
+    let total_modules: u32 = modules.iter().map(|&m| m as u32).sum();
+    let module_w = ((LABEL_W - 2.0 * pad) / total_modules as f64).min(0.8);
+    let bar_h = 12.0 * MM;
+    let bar_y = y_top - LABEL_H + pad + 8.0;
+    let mut cursor = left;
+    for (i, &m) in modules.iter().enumerate() {
+        let w = m as f64 * module_w;
+        if i % 2 == 0 {
+            let _ = writeln!(
+                content,
+                "{:.3} {:.2} {:.3} {:.2} re f",
+                cursor, bar_y, w, bar_h
+            );
+        }
+        cursor += w;
+    }
+    pdf_text_at(content, 7.0, false, left, bar_y - 8.0, &label.barcode);
+}
+
+/// A4 PDF with the labels laid out 2 x 7 per page.
+fn render_pdf(labels: &[PieceLabel]) -> Vec<u8> {
+    let per_page = LABEL_COLS * LABEL_ROWS;
+    let pages: Vec<&[PieceLabel]> = labels.chunks(per_page).collect();
+
+    // Object numbers: 1 catalog, 2 pages, 3-4 fonts, then (page, content) pairs
+    let mut objects: Vec<String> = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        String::new(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+    ];
+
+    let mut kids = Vec::new();
+    for page in &pages {
+        let page_obj = objects.len() + 1;
+        let content_obj = page_obj + 1;
+        kids.push(format!("{} 0 R", page_obj));
+
+        let mut content = String::new();
+        for (i, label) in page.iter().enumerate() {
+            let col = i % LABEL_COLS;
+            let row = i / LABEL_COLS;
+            let x = col as f64 * LABEL_W;
+            let y_top = PAGE_H - SHEET_TOP - row as f64 * LABEL_H;
+            pdf_label_content(&mut content, label, x, y_top);
+        }
+
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+            PAGE_W, PAGE_H, content_obj
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            content.len(),
+            content
+        ));
+    }
+    objects[1] = format!(
+        "<< /Type /Pages /Kids [{}] /Count {} >>",
+        kids.join(" "),
+        kids.len()
+    );
+
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, obj) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        let _ = write!(pdf, "{} 0 obj\n{}\nendobj\n", i + 1, obj);
+    }
+    let xref_start = pdf.len();
+    let _ = write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(pdf, "{:010} 00000 n ", offset);
+    }
+    let _ = write!(
+        pdf,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref_start
+    );
+    pdf.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn send_raw_delivers_the_zpl_unchanged() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let received = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            socket.read_to_end(&mut buf).await.unwrap();
+            buf
+        });
+
+        let zpl = render_zpl(&[PieceLabel {
+            job_reference: "Kitchen ^12".to_string(),
+            sequence: 1,
+            total: 2,
+            original_index: 0,
+            width_mm: 600,
+            height_mm: 400,
+            thickness_mm: 18,
+            material: "MDF".to_string(),
+            resto_id: 7,
+            barcode: "K12-P1".to_string(),
+        }]);
+        send_raw(&address, zpl.as_bytes()).await.unwrap();
+
+        let expected = "^XA\n^CI28\n^PW812\n^LL406\n\
+                        ^FO30,25^A0N,36,36^FDKitchen 12^FS\n\
+                        ^FO600,25^A0N,30,30^FD1/2^FS\n\
+                        ^FO30,70^A0N,52,52^FD600 x 400 x 18 mm^FS\n\
+                        ^FO30,130^A0N,30,30^FDMDF 18mm^FS\n\
+                        ^FO30,168^A0N,30,30^FDP1 - Resto #7^FS\n\
+                        ^FO30,215^BY2^BCN,120,Y,N,N^FDK12-P1^FS\n\
+                        ^XZ\n";
+        assert_eq!(received.await.unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn only_configured_printers_are_used() {
+        let configured = "10.0.0.5:9100, 10.0.0.6:9100";
+        assert_eq!(
+            resolve_printer(None, configured).ok().as_deref(),
+            Some("10.0.0.5:9100")
+        );
+        assert_eq!(
+            resolve_printer(Some("10.0.0.6:9100".to_string()), configured)
+                .ok()
+                .as_deref(),
+            Some("10.0.0.6:9100")
+        );
+        assert!(resolve_printer(Some("169.254.169.254:80".to_string()), configured).is_err());
+        assert!(resolve_printer(None, "").is_err());
+    }
+}
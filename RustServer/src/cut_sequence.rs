@@ -0,0 +1,523 @@
+use crate::cutting_optimizer::{OptimizeCutsResponse, PlacedCut, UsedPlank};
+use crate::AppError;
+use axum::{
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SequenceFormat {
+    #[default]
+    Json,
+    Text,
+}
+
+#[derive(Deserialize)]
+pub struct CutSequenceRequest {
+    pub plan: OptimizeCutsResponse,
+    /// Only sequence this plank.
+    pub resto_id: Option<i64>,
+    /// Overrides the kerf stored in the plan.
+    pub kerf_width_mm: Option<i64>,
+    #[serde(default)]
+    pub format: SequenceFormat,
+}
+
+/// Direction of a saw pass in the plank frame: `Horizontal` runs along
+/// `width_mm` at a fixed `y`, `Vertical` along `height_mm` at a fixed `x`.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CutDirection {
+    Horizontal,
+    Vertical,
+}
+
+impl CutDirection {
+    fn other(self) -> Self {
+        match self {
+            CutDirection::Horizontal => CutDirection::Vertical,
+            CutDirection::Vertical => CutDirection::Horizontal,
+        }
+    }
+}
+
+/// Part of the plank a pass is made on, in plank coordinates.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Part {
+    pub x: i64,
+    pub y: i64,
+    pub width_mm: i64,
+    pub height_mm: i64,
+}
+
+impl Part {
+    /// (start, end) along the axis a `direction` pass is measured on.
+    fn span(&self, direction: CutDirection) -> (i64, i64) {
+        match direction {
+            CutDirection::Horizontal => (self.y, self.y + self.height_mm),
+            CutDirection::Vertical => (self.x, self.x + self.width_mm),
+        }
+    }
+
+    fn slice(&self, direction: CutDirection, start: i64, end: i64) -> Part {
+        match direction {
+            CutDirection::Horizontal => Part {
+                y: start,
+                height_mm: end - start,
+                ..*self
+            },
+            CutDirection::Vertical => Part {
+                x: start,
+                width_mm: end - start,
+                ..*self
+            },
+        }
+    }
+
+    fn contains(&self, piece: &PlacedCut) -> bool {
+        piece.x >= self.x
+            && piece.y >= self.y
+            && piece.x + piece.width <= self.x + self.width_mm
+            && piece.y + piece.height <= self.y + self.height_mm
+    }
+
+    fn is(&self, piece: &PlacedCut) -> bool {
+        piece.x == self.x
+            && piece.y == self.y
+            && piece.width == self.width_mm
+            && piece.height == self.height_mm
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct SawPass {
+    /// 1-based position in the plank's sequence.
+    pub step: usize,
+    /// Guillotine level: stage 1 cuts the whole plank, stage 2 the strips it
+    /// produced, and so on.
+    pub stage: usize,
+    pub direction: CutDirection,
+    /// Where the blade enters, in plank coordinates.
+    pub position_mm: i64,
+    /// Fence setting: distance from the part's top (horizontal) or left
+    /// (vertical) edge to the blade.
+    pub offset_mm: i64,
+    pub length_mm: i64,
+    pub part: Part,
+    /// Positions in the plank's `cuts` list of the pieces this pass frees.
+    /// Empty when it only cuts off waste or opens a strip for later passes.
+    pub releases: Vec<usize>,
+    pub rotate_before: bool,
+    pub fence_reset: bool,
+}
+
+#[derive(Serialize)]
+pub struct PlankSequence {
+    pub resto_id: i64,
+    pub stock_sheet_id: Option<i64>,
+    pub width_mm: i64,
+    pub height_mm: i64,
+    pub trim_mm: i64,
+    /// False when the pieces cannot be separated by edge-to-edge passes,
+    /// e.g. some MaxRects layouts; such planks get no passes.
+    pub guillotine: bool,
+    pub passes: Vec<SawPass>,
+    pub fence_resets: usize,
+    pub rotations: usize,
+}
+
+#[derive(Serialize)]
+pub struct CutSequenceResponse {
+    pub kerf_width_mm: i64,
+    pub planks: Vec<PlankSequence>,
+    pub total_passes: usize,
+    pub total_fence_resets: usize,
+    pub total_rotations: usize,
+    pub warnings: Vec<String>,
+}
+
+pub async fn cut_sequence(Json(req): Json<CutSequenceRequest>) -> Result<Response, AppError> {
+    let plan = &req.plan;
+    if plan.used_planks.is_empty() {
+        return Err(AppError::Validation("Plan has no used planks".to_string()));
+    }
+    let kerf = req.kerf_width_mm.unwrap_or(plan.kerf_width_mm).max(0);
+
+    let planks: Vec<&UsedPlank> = match req.resto_id {
+        Some(id) => vec![plan
+            .used_planks
+            .iter()
+            .find(|p| p.resto_id == id)
+            .ok_or_else(|| AppError::NotFound(format!("Plank {} is not in the plan", id)))?],
+        None => plan.used_planks.iter().collect(),
+    };
+
+    let mut response = CutSequenceResponse {
+        kerf_width_mm: kerf,
+        planks: Vec::with_capacity(planks.len()),
+        total_passes: 0,
+        total_fence_resets: 0,
+        total_rotations: 0,
+        warnings: Vec::new(),
+    };
+    for plank in planks {
+        let sequence = sequence_plank(plank, kerf);
+        if !sequence.guillotine {
+            response.warnings.push(format!(
+                "Plank {} cannot be cut with edge-to-edge saw passes; cut it from the drawing",
+                plank.resto_id
+            ));
+        }
+        response.total_passes += sequence.passes.len();
+        response.total_fence_resets += sequence.fence_resets;
+        response.total_rotations += sequence.rotations;
+        response.planks.push(sequence);
+    }
+
+    Ok(match req.format {
+        SequenceFormat::Json => Json(response).into_response(),
+        SequenceFormat::Text => (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            render_text(&response, &plan.used_planks),
+        )
+            .into_response(),
+    })
+}
+
+/// Passes cutting one part from its reference edge onwards, in order.
+struct Chain {
+    stage: usize,
+    direction: CutDirection,
+    passes: Vec<SawPass>,
+}
+
+/// Chains of one stage and direction, cut together.
+struct StageGroup {
+    stage: usize,
+    direction: CutDirection,
+    queues: Vec<VecDeque<SawPass>>,
+}
+
+/// Rebuilds the guillotine tree of a plank from its piece rectangles, so plans
+/// edited by hand or stored before this existed get a sequence as well, then
+/// orders the passes stage by stage.
+fn sequence_plank(plank: &UsedPlank, kerf: i64) -> PlankSequence {
+    let trim = plank.trim_mm.max(0);
+    let usable = Part {
+        x: trim,
+        y: trim,
+        width_mm: plank.width_mm - 2 * trim,
+        height_mm: plank.height_mm - 2 * trim,
+    };
+    let pieces: Vec<usize> = (0..plank.cuts.len()).collect();
+
+    let mut chains = Vec::new();
+    let guillotine = decompose(usable, pieces, &plank.cuts, kerf, 1, None, &mut chains).is_ok();
+    let passes = if guillotine {
+        schedule(chains)
+    } else {
+        Vec::new()
+    };
+
+    PlankSequence {
+        resto_id: plank.resto_id,
+        stock_sheet_id: plank.stock_sheet_id,
+        width_mm: plank.width_mm,
+        height_mm: plank.height_mm,
+        trim_mm: trim,
+        guillotine,
+        fence_resets: passes.iter().filter(|p| p.fence_reset).count(),
+        rotations: passes.iter().filter(|p| p.rotate_before).count(),
+        passes,
+    }
+}
+
+/// Splits `part` with every edge-to-edge pass one direction allows and
+/// recurses into the strips, preferring the direction its parent did not use.
+/// Fails when two or more pieces remain that no single pass can separate.
+fn decompose(
+    part: Part,
+    pieces: Vec<usize>,
+    all: &[PlacedCut],
+    kerf: i64,
+    stage: usize,
+    prefer: Option<CutDirection>,
+    chains: &mut Vec<Chain>,
+) -> Result<(), ()> {
+    if pieces.is_empty() || (pieces.len() == 1 && part.is(&all[pieces[0]])) {
+        return Ok(());
+    }
+
+    let horizontal = cut_lines(&part, &pieces, all, kerf, CutDirection::Horizontal);
+    let vertical = cut_lines(&part, &pieces, all, kerf, CutDirection::Vertical);
+    let first = prefer.unwrap_or(
+        // Rip along the long side unless the other way frees more strips.
+        match vertical.len().cmp(&horizontal.len()) {
+            std::cmp::Ordering::Greater => CutDirection::Vertical,
+            std::cmp::Ordering::Less => CutDirection::Horizontal,
+            std::cmp::Ordering::Equal if part.width_mm >= part.height_mm => {
+                CutDirection::Horizontal
+            }
+            std::cmp::Ordering::Equal => CutDirection::Vertical,
+        },
+    );
+    let (direction, lines) = match first {
+        CutDirection::Horizontal if !horizontal.is_empty() => (first, horizontal),
+        CutDirection::Vertical if !vertical.is_empty() => (first, vertical),
+        CutDirection::Horizontal if !vertical.is_empty() => (CutDirection::Vertical, vertical),
+        CutDirection::Vertical if !horizontal.is_empty() => (CutDirection::Horizontal, horizontal),
+        // A piece with less than a kerf of waste around it is as good as free.
+        _ if pieces.len() == 1 => return Ok(()),
+        _ => return Err(()),
+    };
+
+    let (start, end) = part.span(direction);
+    let length = match direction {
+        CutDirection::Horizontal => part.width_mm,
+        CutDirection::Vertical => part.height_mm,
+    };
+    let mut strips = Vec::with_capacity(lines.len() + 1);
+    let mut passes = Vec::with_capacity(lines.len());
+    let mut from = start;
+    for &line in &lines {
+        passes.push(SawPass {
+            step: 0,
+            stage,
+            direction,
+            position_mm: line,
+            offset_mm: line - from,
+            length_mm: length,
+            part: part.slice(direction, from, end),
+            releases: Vec::new(),
+            rotate_before: false,
+            fence_reset: false,
+        });
+        strips.push(part.slice(direction, from, line));
+        from = line + kerf;
+    }
+    strips.push(part.slice(direction, from, end));
+
+    let chain_idx = chains.len();
+    chains.push(Chain {
+        stage,
+        direction,
+        passes,
+    });
+
+    for (i, strip) in strips.into_iter().enumerate() {
+        let inside: Vec<usize> = pieces
+            .iter()
+            .copied()
+            .filter(|&p| strip.contains(&all[p]))
+            .collect();
+        let before = chains.len();
+        let single = (inside.len() == 1).then(|| inside[0]);
+        decompose(
+            strip,
+            inside,
+            all,
+            kerf,
+            stage + 1,
+            Some(direction.other()),
+            chains,
+        )?;
+        // A strip that needs no further passes is freed by the pass that
+        // cut it off; the last strip by the chain's final pass.
+        if let (Some(piece), true) = (single, chains.len() == before) {
+            let pass = i.min(lines.len() - 1);
+            chains[chain_idx].passes[pass].releases.push(piece);
+        }
+    }
+    Ok(())
+}
+
+/// Blade positions that cross `part` edge to edge without touching a piece,
+/// at least a kerf apart. Candidates sit right after a piece or a kerf before
+/// one, so offcuts between pieces are cut away too.
+fn cut_lines(
+    part: &Part,
+    pieces: &[usize],
+    all: &[PlacedCut],
+    kerf: i64,
+    direction: CutDirection,
+) -> Vec<i64> {
+    let (start, end) = part.span(direction);
+    let spans: Vec<(i64, i64)> = pieces
+        .iter()
+        .map(|&p| {
+            let piece = &all[p];
+            match direction {
+                CutDirection::Horizontal => (piece.y, piece.y + piece.height),
+                CutDirection::Vertical => (piece.x, piece.x + piece.width),
+            }
+        })
+        .collect();
+
+    let mut candidates: Vec<i64> = spans
+        .iter()
+        .flat_map(|&(s, e)| [e, s - kerf])
+        .filter(|&c| c > start && c + kerf < end)
+        .filter(|&c| spans.iter().all(|&(s, e)| e <= c || s >= c + kerf))
+        .collect();
+    candidates.sort_unstable();
+    candidates.dedup();
+
+    let mut lines: Vec<i64> = Vec::with_capacity(candidates.len());
+    for c in candidates {
+        if lines.last().map_or(true, |&last| c >= last + kerf.max(1)) {
+            lines.push(c);
+        }
+    }
+    lines
+}
+
+/// Orders passes stage by stage so the sheet is turned once per stage, and
+/// within a stage interleaves the parts' chains to reuse the fence setting.
+fn schedule(chains: Vec<Chain>) -> Vec<SawPass> {
+    let mut remaining: Vec<StageGroup> = Vec::new();
+    for chain in chains {
+        let queue = VecDeque::from(chain.passes);
+        match remaining
+            .iter_mut()
+            .find(|g| g.stage == chain.stage && g.direction == chain.direction)
+        {
+            Some(group) => group.queues.push(queue),
+            None => remaining.push(StageGroup {
+                stage: chain.stage,
+                direction: chain.direction,
+                queues: vec![queue],
+            }),
+        }
+    }
+
+    let mut passes: Vec<SawPass> = Vec::new();
+    while !remaining.is_empty() {
+        // Lowest stage first; stay in the current direction when a stage has
+        // parts going both ways.
+        let direction = passes.last().map(|p| p.direction);
+        let next = (0..remaining.len())
+            .min_by_key(|&i| {
+                (
+                    remaining[i].stage,
+                    Some(remaining[i].direction) != direction,
+                )
+            })
+            .unwrap_or(0);
+        let mut queues = remaining.remove(next).queues;
+
+        while queues.iter().any(|q| !q.is_empty()) {
+            let fence = passes.last().map(|p| p.offset_mm);
+            let pick = queues
+                .iter()
+                .position(|q| q.front().map(|p| p.offset_mm) == fence && fence.is_some())
+                .unwrap_or_else(|| most_common_head(&queues));
+            let mut pass = queues[pick].pop_front().unwrap();
+            let previous = passes.last();
+            pass.step = passes.len() + 1;
+            pass.rotate_before = previous.is_some_and(|p| p.direction != pass.direction);
+            pass.fence_reset = previous.map_or(true, |p| p.offset_mm != pass.offset_mm);
+            passes.push(pass);
+        }
+    }
+    passes
+}
+
+/// Queue whose next pass shares its offset with the most other queue heads,
+/// so the new fence setting serves as many parts as possible.
+fn most_common_head(queues: &[VecDeque<SawPass>]) -> usize {
+    let mut counts: HashMap<i64, usize> = HashMap::new();
+    for pass in queues.iter().filter_map(|q| q.front()) {
+        *counts.entry(pass.offset_mm).or_default() += 1;
+    }
+    queues
+        .iter()
+        .enumerate()
+        .filter_map(|(i, q)| q.front().map(|p| (i, p)))
+        .max_by_key(|(i, p)| (counts[&p.offset_mm], p.offset_mm, std::cmp::Reverse(*i)))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+/// Printable step list for the saw operator.
+fn render_text(response: &CutSequenceResponse, planks: &[UsedPlank]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "Cut sequence - {} passes, {} fence settings, {} rotations (kerf {} mm)",
+        response.total_passes,
+        response.total_fence_resets,
+        response.total_rotations,
+        response.kerf_width_mm
+    );
+
+    for sequence in &response.planks {
+        let Some(plank) = planks.iter().find(|p| p.resto_id == sequence.resto_id) else {
+            continue;
+        };
+        let source = if plank.resto_id < 0 {
+            format!("New sheet #{}", -plank.resto_id)
+        } else {
+            format!("Resto #{}", plank.resto_id)
+        };
+        let _ = writeln!(
+            out,
+            "\n{} - {} {}mm, {} x {} mm",
+            source, plank.material, plank.thickness_mm, plank.width_mm, plank.height_mm
+        );
+        if !sequence.guillotine {
+            let _ = writeln!(out, "  Not guillotine-cuttable; follow the drawing.");
+            continue;
+        }
+        if sequence.trim_mm > 0 {
+            let _ = writeln!(out, "  Trim {} mm off every edge.", sequence.trim_mm);
+        }
+        if sequence.passes.is_empty() {
+            let _ = writeln!(out, "  No saw passes needed.");
+        }
+
+        for pass in &sequence.passes {
+            if pass.rotate_before {
+                let _ = writeln!(out, "  -- Turn the parts 90 degrees --");
+            }
+            let (cut, edge) = match pass.direction {
+                CutDirection::Horizontal => ("Horizontal", "top"),
+                CutDirection::Vertical => ("Vertical", "left"),
+            };
+            let _ = write!(
+                out,
+                "  {:>3}. {} cut at {} mm from the {} edge of the {} x {} part{}",
+                pass.step,
+                cut,
+                pass.offset_mm,
+                edge,
+                pass.part.width_mm,
+                pass.part.height_mm,
+                if pass.fence_reset { " [set fence]" } else { "" }
+            );
+            if pass.releases.is_empty() {
+                let _ = writeln!(out);
+                continue;
+            }
+            let freed: Vec<String> = pass
+                .releases
+                .iter()
+                .map(|&i| {
+                    let piece = &plank.cuts[i];
+                    format!(
+                        "P{} {} x {}",
+                        piece.original_index + 1,
+                        piece.width,
+                        piece.height
+                    )
+                })
+                .collect();
+            let _ = writeln!(out, " -> frees {}", freed.join(", "));
+        }
+    }
+    out
+}
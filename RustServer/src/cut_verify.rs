@@ -0,0 +1,407 @@
+use crate::cutting_optimizer::{
+    allowed_rotations, virtual_sheet, CutRequest, OptimizeCutsResponse, PlacedCut, UsedPlank,
+};
+use crate::defects::attach_defects;
+use crate::leftover_shape::ShapeRect;
+use crate::stock_sheets::StockSheet;
+use crate::{AppError, AppState, Leftover};
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Deserialize)]
+pub struct VerifyCutsRequest {
+    pub plan: OptimizeCutsResponse,
+    /// Pieces the plan was made for. Dimensions, rotation rules and
+    /// quantities are only checked when given.
+    #[serde(default)]
+    pub cuts: Vec<CutRequest>,
+    /// Overrides the kerf stored in the plan.
+    pub kerf_width_mm: Option<i64>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    MissingPlank,
+    DuplicatePlank,
+    PlankMismatch,
+    OutOfBounds,
+    Overlap,
+    DefectOverlap,
+    MaterialMismatch,
+    ThicknessMismatch,
+    IllegalRotation,
+    DimensionMismatch,
+    UnknownPiece,
+    QuantityMismatch,
+}
+
+#[derive(Serialize)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    pub resto_id: Option<i64>,
+    /// Positions in the plank's `cuts` list.
+    pub pieces: Vec<usize>,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct VerifyCutsResponse {
+    pub valid: bool,
+    pub planks_checked: usize,
+    pub pieces_checked: usize,
+    pub violations: Vec<Violation>,
+}
+
+/// Checks a plan, typically edited by hand, against the live inventory and
+/// reports every violation instead of stopping at the first one.
+pub async fn verify_cuts(
+    State(state): State<AppState>,
+    Json(req): Json<VerifyCutsRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let plan = &req.plan;
+    let kerf = req.kerf_width_mm.unwrap_or(plan.kerf_width_mm).max(0);
+    let mut violations = Vec::new();
+    let mut seen = HashSet::new();
+
+    for used in &plan.used_planks {
+        if !seen.insert(used.resto_id) {
+            violations.push(Violation {
+                kind: ViolationKind::DuplicatePlank,
+                resto_id: Some(used.resto_id),
+                pieces: Vec::new(),
+                message: format!("Plank {} appears more than once", used.resto_id),
+            });
+            continue;
+        }
+
+        let Some(plank) = resolve_plank(&state, used).await? else {
+            violations.push(Violation {
+                kind: ViolationKind::MissingPlank,
+                resto_id: Some(used.resto_id),
+                pieces: Vec::new(),
+                message: match used.stock_sheet_id {
+                    Some(sheet_id) if used.resto_id < 0 => {
+                        format!("Catalog sheet {} does not exist", sheet_id)
+                    }
+                    _ => format!("Resto {} does not exist", used.resto_id),
+                },
+            });
+            continue;
+        };
+
+        check_plank(used, &plank, &mut violations);
+        check_pieces(used, &plank, &req.cuts, &mut violations);
+        check_overlaps(used, kerf, &mut violations);
+    }
+
+    if !req.cuts.is_empty() {
+        check_quantities(plan, &req.cuts, &mut violations);
+    }
+
+    Ok(Json(VerifyCutsResponse {
+        valid: violations.is_empty(),
+        planks_checked: plan.used_planks.len(),
+        pieces_checked: plan.used_planks.iter().map(|p| p.cuts.len()).sum(),
+        violations,
+    }))
+}
+
+/// The inventory row behind a plank. New sheets are checked against their
+/// catalog entry, or taken as described when the plan names none.
+async fn resolve_plank(state: &AppState, used: &UsedPlank) -> Result<Option<Leftover>, AppError> {
+    if used.resto_id > 0 {
+        let Some(mut plank) = sqlx::query_as::<_, Leftover>("SELECT * FROM leftovers WHERE id = ?")
+            .bind(used.resto_id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(AppError::Database)?
+        else {
+            return Ok(None);
+        };
+        attach_defects(&state.db, std::slice::from_mut(&mut plank)).await?;
+        return Ok(Some(plank));
+    }
+
+    match used.stock_sheet_id {
+        Some(sheet_id) => {
+            let sheet = sqlx::query_as::<_, StockSheet>("SELECT * FROM stock_sheets WHERE id = ?")
+                .bind(sheet_id)
+                .fetch_optional(&state.db)
+                .await
+                .map_err(AppError::Database)?;
+            Ok(sheet.map(|s| virtual_sheet(used.resto_id, &s)))
+        }
+        None => Ok(Some(Leftover {
+            id: used.resto_id,
+            width_mm: used.width_mm,
+            height_mm: used.height_mm,
+            thickness_mm: used.thickness_mm,
+            material: used.material.clone(),
+            notes: None,
+            grain: used.grain,
+            trim_mm: Some(used.trim_mm),
+            created_at: String::new(),
+            shape: None,
+            defects: Vec::new(),
+        })),
+    }
+}
+
+fn check_plank(used: &UsedPlank, plank: &Leftover, violations: &mut Vec<Violation>) {
+    if used.width_mm != plank.width_mm
+        || used.height_mm != plank.height_mm
+        || used.thickness_mm != plank.thickness_mm
+        || !used.material.eq_ignore_ascii_case(&plank.material)
+        || used.grain != plank.grain
+    {
+        violations.push(Violation {
+            kind: ViolationKind::PlankMismatch,
+            resto_id: Some(used.resto_id),
+            pieces: Vec::new(),
+            message: format!(
+                "Plank {} is {}x{}x{} {} in the plan but {}x{}x{} {} in stock",
+                used.resto_id,
+                used.width_mm,
+                used.height_mm,
+                used.thickness_mm,
+                used.material,
+                plank.width_mm,
+                plank.height_mm,
+                plank.thickness_mm,
+                plank.material
+            ),
+        });
+    }
+}
+
+fn check_pieces(
+    used: &UsedPlank,
+    plank: &Leftover,
+    cuts: &[CutRequest],
+    violations: &mut Vec<Violation>,
+) {
+    // The plank's own trim wins, as in the optimizer.
+    let trim = plank.trim_mm.unwrap_or(used.trim_mm).max(0);
+
+    for (idx, piece) in used.cuts.iter().enumerate() {
+        let mut report = |kind, message| {
+            violations.push(Violation {
+                kind,
+                resto_id: Some(used.resto_id),
+                pieces: vec![idx],
+                message,
+            })
+        };
+        let label = format!("Piece {} on plank {}", idx, used.resto_id);
+
+        let rect = ShapeRect {
+            x: piece.x,
+            y: piece.y,
+            width_mm: piece.width,
+            height_mm: piece.height,
+        };
+        if !plank.covers(&rect, trim) {
+            report(
+                ViolationKind::OutOfBounds,
+                format!(
+                    "{} ({}x{} at {},{}) lies outside the {}{}x{} plank less its {}mm trim",
+                    label,
+                    piece.width,
+                    piece.height,
+                    piece.x,
+                    piece.y,
+                    if plank.shape.is_some() { "shaped " } else { "" },
+                    plank.width_mm,
+                    plank.height_mm,
+                    trim
+                ),
+            );
+        }
+        for defect in plank.defects.iter().filter(|d| d.overlaps(piece)) {
+            report(
+                ViolationKind::DefectOverlap,
+                format!(
+                    "{} covers defect {}{}",
+                    label,
+                    defect.id,
+                    defect
+                        .description
+                        .as_ref()
+                        .map(|d| format!(" ({})", d))
+                        .unwrap_or_default()
+                ),
+            );
+        }
+        if !piece.material.eq_ignore_ascii_case(&plank.material) {
+            report(
+                ViolationKind::MaterialMismatch,
+                format!(
+                    "{} is {} but the plank is {}",
+                    label, piece.material, plank.material
+                ),
+            );
+        }
+        if piece.thickness_mm != plank.thickness_mm {
+            report(
+                ViolationKind::ThicknessMismatch,
+                format!(
+                    "{} is {}mm but the plank is {}mm",
+                    label, piece.thickness_mm, plank.thickness_mm
+                ),
+            );
+        }
+
+        let Some(cut) = cuts.get(piece.original_index) else {
+            if !cuts.is_empty() {
+                report(
+                    ViolationKind::UnknownPiece,
+                    format!(
+                        "{} refers to cut {}, which was not requested",
+                        label, piece.original_index
+                    ),
+                );
+            } else if let (Some(g), Some(board)) = (piece.grain, plank.grain) {
+                if g != board {
+                    report(
+                        ViolationKind::IllegalRotation,
+                        format!("{} runs against the plank grain", label),
+                    );
+                }
+            }
+            continue;
+        };
+
+        if !cut.material.eq_ignore_ascii_case(&piece.material)
+            || cut.thickness_mm != piece.thickness_mm
+        {
+            report(
+                ViolationKind::MaterialMismatch,
+                format!(
+                    "{} is {} {}mm but cut {} asks for {} {}mm",
+                    label,
+                    piece.material,
+                    piece.thickness_mm,
+                    piece.original_index,
+                    cut.material,
+                    cut.thickness_mm
+                ),
+            );
+        }
+        if !matches_request(piece, cut) {
+            report(
+                ViolationKind::DimensionMismatch,
+                format!(
+                    "{} is {}x{} ({}) but cut {} is {}x{}",
+                    label,
+                    piece.width,
+                    piece.height,
+                    if piece.rotated {
+                        "rotated"
+                    } else {
+                        "not rotated"
+                    },
+                    piece.original_index,
+                    cut.width_mm,
+                    cut.height_mm
+                ),
+            );
+        }
+        if !allowed_rotations(cut, plank).contains(&piece.rotated) {
+            report(
+                ViolationKind::IllegalRotation,
+                format!(
+                    "{} is {} but cut {} {}",
+                    label,
+                    if piece.rotated {
+                        "rotated"
+                    } else {
+                        "not rotated"
+                    },
+                    piece.original_index,
+                    if cut.grain.is_some() && plank.grain.is_some() {
+                        "must follow the plank grain"
+                    } else {
+                        "may not be rotated"
+                    }
+                ),
+            );
+        }
+    }
+}
+
+fn matches_request(piece: &PlacedCut, cut: &CutRequest) -> bool {
+    let (w, h) = if piece.rotated {
+        (cut.height_mm, cut.width_mm)
+    } else {
+        (cut.width_mm, cut.height_mm)
+    };
+    piece.width == w && piece.height == h
+}
+
+/// Pieces closer than one kerf to each other cannot both be cut.
+fn check_overlaps(used: &UsedPlank, kerf: i64, violations: &mut Vec<Violation>) {
+    for (i, a) in used.cuts.iter().enumerate() {
+        for (j, b) in used.cuts.iter().enumerate().skip(i + 1) {
+            let overlaps = a.x < b.x + b.width
+                && b.x < a.x + a.width
+                && a.y < b.y + b.height
+                && b.y < a.y + a.height;
+            let too_close = a.x < b.x + b.width + kerf
+                && b.x < a.x + a.width + kerf
+                && a.y < b.y + b.height + kerf
+                && b.y < a.y + a.height + kerf;
+            if !too_close {
+                continue;
+            }
+            violations.push(Violation {
+                kind: ViolationKind::Overlap,
+                resto_id: Some(used.resto_id),
+                pieces: vec![i, j],
+                message: if overlaps {
+                    format!("Pieces {} and {} on plank {} overlap", i, j, used.resto_id)
+                } else {
+                    format!(
+                        "Pieces {} and {} on plank {} are less than the {}mm kerf apart",
+                        i, j, used.resto_id, kerf
+                    )
+                },
+            });
+        }
+    }
+}
+
+/// Every requested piece must be placed once or listed as unplaced.
+fn check_quantities(
+    plan: &OptimizeCutsResponse,
+    cuts: &[CutRequest],
+    violations: &mut Vec<Violation>,
+) {
+    let mut placed: HashMap<usize, i64> = HashMap::new();
+    for piece in plan.used_planks.iter().flat_map(|p| &p.cuts) {
+        *placed.entry(piece.original_index).or_default() += 1;
+    }
+    let mut unplaced: HashMap<usize, i64> = HashMap::new();
+    for (idx, _) in &plan.unplaced_cuts {
+        *unplaced.entry(*idx).or_default() += 1;
+    }
+
+    for (idx, cut) in cuts.iter().enumerate() {
+        let placed = placed.get(&idx).copied().unwrap_or(0);
+        let unplaced = unplaced.get(&idx).copied().unwrap_or(0);
+        let requested = cut.quantity as i64;
+        if placed + unplaced == requested {
+            continue;
+        }
+        violations.push(Violation {
+            kind: ViolationKind::QuantityMismatch,
+            resto_id: None,
+            pieces: Vec::new(),
+            message: format!(
+                "Cut {} ({}x{} {}): {} requested, {} placed and {} unplaced",
+                idx, cut.width_mm, cut.height_mm, cut.material, requested, placed, unplaced
+            ),
+        });
+    }
+}
@@ -1,12 +1,48 @@
-use crate::{AppError, AppState, Leftover, MAX_DIMENSION, MAX_THICKNESS};
+use crate::background_jobs::JobMonitor;
+use crate::cut_jobs::{fetch_plan, run_and_store, validate_operator};
+use crate::defects::{attach_defects, Defect};
+use crate::leftover_shape::ShapeRect;
+use crate::reservations::{release_job, DEFAULT_RESERVATION_MINUTES, MAX_RESERVATION_MINUTES};
+use crate::stock_sheets::StockSheet;
+use crate::{AppError, AppState, Leftover, MAX_DIMENSION, MAX_THICKNESS, MAX_TRIM_MM};
 use axum::{extract::State, response::IntoResponse, Json};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Sqlite};
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use time::macros::format_description;
+use time::OffsetDateTime;
 
 const BEAM_WIDTH: usize = 5;
-const FULL_SHEET_W: i64 = 2800;
-const FULL_SHEET_H: i64 = 3000;
+const CANDIDATES_PER_STEP: usize = 3;
+const MAX_BEAM_WIDTH: usize = 200;
+const MAX_CANDIDATES_PER_STEP: usize = 50;
+// Stays below the 120 s request timeout so a plan always makes it back.
+const MAX_TIME_LIMIT_MS: u64 = 110_000;
+const DEFAULT_TIME_LIMIT_MS: u64 = 30_000;
+const MAX_EXTRA_SHEETS: usize = 10;
+const DEFAULT_LEFTOVER_DISCOUNT_PERCENT: f64 = 50.0;
+
+/// Grain direction in the frame of a piece or plank: `Horizontal` runs along
+/// `width_mm`, `Vertical` along `height_mm`.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Grain {
+    Horizontal,
+    Vertical,
+}
+
+impl Grain {
+    fn rotated(self) -> Self {
+        match self {
+            Grain::Horizontal => Grain::Vertical,
+            Grain::Vertical => Grain::Horizontal,
+        }
+    }
+}
 
 #[derive(Deserialize, Serialize, Clone)]
 pub struct CutRequest {
@@ -15,9 +51,13 @@ pub struct CutRequest {
     pub thickness_mm: i64,
     pub material: String,
     pub quantity: i32,
+    #[serde(default)]
+    pub grain: Option<Grain>,
+    #[serde(default = "crate::default_true")]
+    pub rotation_allowed: bool,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct OptimizeCutsRequest {
     pub cuts: Vec<CutRequest>,
     #[serde(default = "default_kerf")]
@@ -26,6 +66,100 @@ pub struct OptimizeCutsRequest {
     pub min_remainder_width_mm: i64,
     #[serde(default = "default_min_remainder")]
     pub min_remainder_height_mm: i64,
+    #[serde(default)]
+    pub strategy: PlacementStrategy,
+    /// Edge trim for planks that have no trim of their own (leftover or
+    /// catalog sheet setting).
+    #[serde(default)]
+    pub trim_mm: i64,
+    #[serde(default = "default_beam_width")]
+    pub beam_width: usize,
+    #[serde(default = "default_candidates_per_step")]
+    pub candidates_per_step: usize,
+    #[serde(default = "default_time_limit_ms")]
+    pub time_limit_ms: u64,
+    /// Overrides the per-m² prices derived from the stock sheet catalog.
+    #[serde(default)]
+    pub material_prices: Vec<MaterialPrice>,
+    /// Leftovers are valued at this much less than new material of the same
+    /// kind.
+    #[serde(default = "default_leftover_discount_percent")]
+    pub leftover_discount_percent: f64,
+    #[serde(default)]
+    pub objective: ObjectiveSpec,
+    /// Operator name stored with the cut job.
+    #[serde(default)]
+    pub created_by: Option<String>,
+    /// Also cut from leftovers reserved by other pending plans.
+    #[serde(default)]
+    pub include_reserved: bool,
+    /// How long the plan holds the leftovers it uses; 0 reserves nothing.
+    #[serde(default = "default_reservation_minutes")]
+    pub reservation_minutes: i64,
+}
+
+/// Price of a material; without `thickness_mm` it applies to every thickness
+/// that has no price of its own.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct MaterialPrice {
+    pub material: String,
+    #[serde(default)]
+    pub thickness_mm: Option<i64>,
+    pub price_per_m2: f64,
+}
+
+/// How free space on a plank is tracked after each placement.
+///
+/// `Guillotine` splits the used free rectangle into two children, so every
+/// plan can be cut with through-cuts on the panel saw. `MaxRects` keeps all
+/// maximal free rectangles (they may overlap) and packs tighter, but the
+/// result may need a CNC or a track saw.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlacementStrategy {
+    #[default]
+    Guillotine,
+    MaxRects,
+}
+
+/// What the search minimises once the most pieces are placed: one built-in
+/// objective by name, or a weighted sum such as
+/// `[{"kind": "material_cost", "weight": 1}, {"kind": "oldest_first", "weight": 5}]`.
+/// Scores are in the units of each objective, so weights also scale units.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum ObjectiveSpec {
+    Single(ObjectiveKind),
+    Weighted(Vec<WeightedObjective>),
+}
+
+impl Default for ObjectiveSpec {
+    fn default() -> Self {
+        ObjectiveSpec::Single(ObjectiveKind::MaterialCost)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct WeightedObjective {
+    pub kind: ObjectiveKind,
+    pub weight: f64,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectiveKind {
+    /// Total material cost (see `material_prices`).
+    MaterialCost,
+    /// Number of planks opened.
+    FewestSheets,
+    /// Unused area of the opened planks, in m².
+    LeastWaste,
+    /// Area of the largest free rectangle left on an opened plank, in m²
+    /// (larger is better).
+    LargestOffcut,
+    /// One point per opened plank plus up to one more the newer it is, so
+    /// the oldest leftovers are used first. New sheets count as newest.
+    OldestFirst,
 }
 
 #[derive(Clone)]
@@ -33,7 +167,6 @@ struct OptimizationState {
     used_planks: Vec<UsedPlank>,
     free_rects_by_plank: HashMap<i64, Vec<FreeRect>>,
     unplaced_cuts: Vec<(usize, CutRequest)>,
-    score: i64,
 }
 
 fn default_kerf() -> i64 {
@@ -42,8 +175,23 @@ fn default_kerf() -> i64 {
 fn default_min_remainder() -> i64 {
     100
 }
+fn default_beam_width() -> usize {
+    BEAM_WIDTH
+}
+fn default_candidates_per_step() -> usize {
+    CANDIDATES_PER_STEP
+}
+fn default_time_limit_ms() -> u64 {
+    DEFAULT_TIME_LIMIT_MS
+}
+fn default_reservation_minutes() -> i64 {
+    DEFAULT_RESERVATION_MINUTES
+}
+fn default_leftover_discount_percent() -> f64 {
+    DEFAULT_LEFTOVER_DISCOUNT_PERCENT
+}
 
-#[derive(Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct PlacedCut {
     pub original_index: usize,
     pub x: i64,
@@ -53,30 +201,142 @@ pub struct PlacedCut {
     pub rotated: bool,
     pub material: String,
     pub thickness_mm: i64,
+    #[serde(default)]
+    pub grain: Option<Grain>,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct UsedPlank {
     pub resto_id: i64,
     pub width_mm: i64,
     pub height_mm: i64,
     pub thickness_mm: i64,
     pub material: String,
+    #[serde(default)]
+    pub grain: Option<Grain>,
+    #[serde(default)]
+    pub stock_sheet_id: Option<i64>,
+    #[serde(default)]
+    pub trim_mm: i64,
+    /// Fingerprint of the leftover row the plan was computed from;
+    /// `/commit_cuts` refuses the plank once it no longer matches.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resto_hash: Option<String>,
     pub cuts: Vec<PlacedCut>,
     pub waste_percent: f64,
     pub total_area_mm2: i64,
     pub used_area_mm2: i64,
+    #[serde(default)]
+    pub remainders: Vec<Remainder>,
+}
+
+/// Free area left on a plank after cutting, kept only when it is at least
+/// `min_remainder_width_mm` x `min_remainder_height_mm` in some orientation.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Remainder {
+    pub x: i64,
+    pub y: i64,
+    pub width_mm: i64,
+    pub height_mm: i64,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct OptimizeCutsResponse {
     pub success: bool,
     pub efficiency_percent: f64,
     pub total_cuts_placed: usize,
     pub total_cuts_requested: usize,
 
+    #[serde(default)]
+    pub kerf_width_mm: i64,
     pub used_planks: Vec<UsedPlank>,
     pub unplaced_cuts: Vec<(usize, CutRequest)>,
+    #[serde(default)]
+    pub sheets_to_buy: Vec<SheetPurchase>,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    #[serde(default)]
+    pub search_stats: SearchStats,
+    #[serde(default)]
+    pub cost: CostBreakdown,
+    /// Cut job the plan was stored as.
+    #[serde(default)]
+    pub job_id: Option<i64>,
+    /// When the reservation on the plan's leftovers runs out.
+    #[serde(default)]
+    pub reserved_until: Option<String>,
+}
+
+/// Material cost of a plan. Planks without a known price are listed with a
+/// `cost` of `None` and left out of the totals.
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct CostBreakdown {
+    pub total: f64,
+    pub leftovers_total: f64,
+    pub new_sheets_total: f64,
+    pub planks: Vec<PlankCost>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct PlankCost {
+    pub resto_id: i64,
+    pub stock_sheet_id: Option<i64>,
+    pub area_m2: f64,
+    pub price_per_m2: Option<f64>,
+    pub discount_percent: f64,
+    pub cost: Option<f64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct SearchStats {
+    pub elapsed_ms: u64,
+    pub states_explored: u64,
+    /// The time limit ran out and the remaining cuts were placed greedily.
+    pub timed_out: bool,
+}
+
+/// Wall-clock budget shared by every solver pass of one request. Background
+/// jobs also report progress through it, and cancelling one expires it.
+struct SearchBudget {
+    deadline: Instant,
+    states_explored: u64,
+    timed_out: bool,
+    monitor: Option<JobMonitor>,
+}
+
+impl SearchBudget {
+    fn new(time_limit_ms: u64, monitor: Option<JobMonitor>) -> Self {
+        Self {
+            deadline: Instant::now() + Duration::from_millis(time_limit_ms),
+            states_explored: 0,
+            timed_out: false,
+            monitor,
+        }
+    }
+
+    fn expired(&mut self) -> bool {
+        if !self.timed_out && (Instant::now() >= self.deadline || self.cancelled()) {
+            self.timed_out = true;
+        }
+        self.timed_out
+    }
+
+    fn cancelled(&self) -> bool {
+        self.monitor.as_ref().is_some_and(|m| m.is_cancelled())
+    }
+}
+
+/// New catalog sheets the plan opens, grouped per catalog entry.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SheetPurchase {
+    pub stock_sheet_id: i64,
+    pub material: String,
+    pub thickness_mm: i64,
+    pub width_mm: i64,
+    pub height_mm: i64,
+    pub quantity: usize,
+    pub unit_price: Option<f64>,
+    pub total_price: Option<f64>,
 }
 
 #[derive(Clone, Debug)]
@@ -87,79 +347,342 @@ struct FreeRect {
     height: i64,
 }
 
+impl FreeRect {
+    fn right(&self) -> i64 {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> i64 {
+        self.y + self.height
+    }
+
+    fn intersects(&self, other: &FreeRect) -> bool {
+        self.x < other.right()
+            && other.x < self.right()
+            && self.y < other.bottom()
+            && other.y < self.bottom()
+    }
+
+    fn contains(&self, other: &FreeRect) -> bool {
+        self.x <= other.x
+            && self.y <= other.y
+            && self.right() >= other.right()
+            && self.bottom() >= other.bottom()
+    }
+}
+
 pub async fn optimize_cuts(
     State(state): State<AppState>,
     Json(req): Json<OptimizeCutsRequest>,
 ) -> Result<impl IntoResponse, AppError> {
     tracing::info!(cuts = %req.cuts.len(), "Optimizing cuts");
+    validate_optimize_request(&req)?;
+
+    Ok(Json(run_and_store(&state.db, req, None, None).await?))
+}
+
+/// Per-piece limits shared by `/optimize_cuts` and the cut list import.
+pub fn cut_limit_error(cut_req: &CutRequest) -> Option<String> {
+    if !(10..=MAX_DIMENSION).contains(&cut_req.width_mm) {
+        return Some(format!("width must be 10-{}mm", MAX_DIMENSION));
+    }
+    if !(10..=MAX_DIMENSION).contains(&cut_req.height_mm) {
+        return Some(format!("height must be 10-{}mm", MAX_DIMENSION));
+    }
+    if !(1..=MAX_THICKNESS).contains(&cut_req.thickness_mm) {
+        return Some(format!("thickness must be 1-{}mm", MAX_THICKNESS));
+    }
+    if !(1..=1000).contains(&cut_req.quantity) {
+        return Some("quantity must be 1-1000".to_string());
+    }
+    None
+}
 
+pub fn validate_optimize_request(req: &OptimizeCutsRequest) -> Result<(), AppError> {
     for (idx, cut_req) in req.cuts.iter().enumerate() {
-        if cut_req.width_mm < 10 || cut_req.width_mm > MAX_DIMENSION {
-            return Err(AppError::Validation(format!(
-                "Cut {}: width must be 10-{}mm",
-                idx, MAX_DIMENSION
-            )));
+        if let Some(e) = cut_limit_error(cut_req) {
+            return Err(AppError::Validation(format!("Cut {}: {}", idx, e)));
         }
-        if cut_req.height_mm < 10 || cut_req.height_mm > MAX_DIMENSION {
-            return Err(AppError::Validation(format!(
-                "Cut {}: height must be 10-{}mm",
-                idx, MAX_DIMENSION
-            )));
+    }
+    if !(0..=MAX_TRIM_MM).contains(&req.trim_mm) {
+        return Err(AppError::Validation(format!(
+            "trim_mm must be 0-{}",
+            MAX_TRIM_MM
+        )));
+    }
+    if !(1..=MAX_BEAM_WIDTH).contains(&req.beam_width) {
+        return Err(AppError::Validation(format!(
+            "beam_width must be 1-{}",
+            MAX_BEAM_WIDTH
+        )));
+    }
+    if !(1..=MAX_CANDIDATES_PER_STEP).contains(&req.candidates_per_step) {
+        return Err(AppError::Validation(format!(
+            "candidates_per_step must be 1-{}",
+            MAX_CANDIDATES_PER_STEP
+        )));
+    }
+    if !(1..=MAX_TIME_LIMIT_MS).contains(&req.time_limit_ms) {
+        return Err(AppError::Validation(format!(
+            "time_limit_ms must be 1-{}",
+            MAX_TIME_LIMIT_MS
+        )));
+    }
+    if !(0..=MAX_RESERVATION_MINUTES).contains(&req.reservation_minutes) {
+        return Err(AppError::Validation(format!(
+            "reservation_minutes must be 0-{}",
+            MAX_RESERVATION_MINUTES
+        )));
+    }
+    validate_operator("created_by", &req.created_by)?;
+    if !(0.0..=100.0).contains(&req.leftover_discount_percent) {
+        return Err(AppError::Validation(
+            "leftover_discount_percent must be 0-100".to_string(),
+        ));
+    }
+    if let ObjectiveSpec::Weighted(parts) = &req.objective {
+        if parts.is_empty() {
+            return Err(AppError::Validation(
+                "objective must list at least one entry".to_string(),
+            ));
+        }
+        if parts
+            .iter()
+            .any(|p| !p.weight.is_finite() || p.weight < 0.0)
+        {
+            return Err(AppError::Validation(
+                "objective weights must be non-negative numbers".to_string(),
+            ));
         }
-        if cut_req.thickness_mm < 1 || cut_req.thickness_mm > MAX_THICKNESS {
+    }
+    for (idx, price) in req.material_prices.iter().enumerate() {
+        if price.material.trim().is_empty() {
             return Err(AppError::Validation(format!(
-                "Cut {}: thickness must be 1-{}mm",
-                idx, MAX_THICKNESS
+                "Material price {}: material cannot be empty",
+                idx
             )));
         }
-        if cut_req.quantity < 1 || cut_req.quantity > 1000 {
+        if !price.price_per_m2.is_finite() || price.price_per_m2 < 0.0 {
             return Err(AppError::Validation(format!(
-                "Cut {}: quantity must be 1-1000",
+                "Material price {}: price_per_m2 must be a non-negative number",
                 idx
             )));
         }
     }
+    Ok(())
+}
 
-    let inventory = sqlx::query_as::<_, Leftover>(
+/// Leftovers the optimizer may cut from, smallest first.
+pub async fn load_inventory(db: &Pool<Sqlite>) -> Result<Vec<Leftover>, AppError> {
+    let mut inventory = sqlx::query_as::<_, Leftover>(
         "SELECT * FROM leftovers WHERE width_mm >= 10 AND height_mm >= 10 ORDER BY width_mm * height_mm ASC"
     )
-    .fetch_all(&state.db)
+    .fetch_all(db)
     .await
     .map_err(AppError::Database)?;
+    attach_defects(db, &mut inventory).await?;
+    Ok(inventory)
+}
 
-    let result = tokio::task::spawn_blocking(move || {
-        let (req_material, req_thickness) = if let Some(first) = req.cuts.first() {
-            (first.material.clone(), first.thickness_mm)
-        } else {
-            ("Unknown".to_string(), 18)
-        };
-
-        let mut current_inventory = inventory.clone();
-        let mut response = run_optimization_sync(req.clone(), current_inventory.clone());
+/// Every field of a leftover row the optimizer reads, apart from its id and
+/// defects.
+fn plank_fields(p: &Leftover) -> String {
+    format!(
+        "{}|{}|{}|{}|{:?}|{:?}|{}",
+        p.width_mm,
+        p.height_mm,
+        p.thickness_mm,
+        p.material.to_lowercase(),
+        p.grain,
+        p.trim_mm,
+        p.shape
+            .as_ref()
+            .and_then(|s| serde_json::to_string(&s.0).ok())
+            .unwrap_or_default(),
+    )
+}
 
-        let mut extra_sheets = 0;
-        const MAX_EXTRA_SHEETS: i64 = 10;
+/// Fingerprint of the cuttable inventory, stored with each cut job so a plan
+/// can tell whether the racks changed since it was computed.
+pub fn inventory_hash(inventory: &[Leftover]) -> String {
+    let mut planks: Vec<&Leftover> = inventory.iter().collect();
+    planks.sort_by_key(|p| p.id);
+
+    let mut hasher = Sha256::new();
+    for p in planks {
+        hasher.update(format!(
+            "{}|{}|{:?}\n",
+            p.id,
+            plank_fields(p),
+            p.defects.iter().map(|d| d.rect()).collect::<Vec<_>>()
+        ));
+    }
+    format!("{:x}", hasher.finalize())
+}
 
-        while !response.unplaced_cuts.is_empty() && extra_sheets < MAX_EXTRA_SHEETS {
-            extra_sheets += 1;
+/// Fingerprint of one leftover as planned; defects are checked on their own.
+fn resto_hash(plank: &Leftover) -> String {
+    format!("{:x}", Sha256::digest(plank_fields(plank)))
+}
 
-            let new_sheet = Leftover {
-                id: -extra_sheets,
-                width_mm: FULL_SHEET_W,
-                height_mm: FULL_SHEET_H,
-                thickness_mm: req_thickness,
-                material: req_material.clone(),
-                notes: Some("New Full Sheet".to_string()),
-                created_at: String::new(),
-            };
+/// Runs the solver on a validated request, opening catalog sheets when the
+/// inventory runs out.
+pub async fn run_cut_optimization(
+    req: OptimizeCutsRequest,
+    inventory: Vec<Leftover>,
+    catalog: Vec<StockSheet>,
+    monitor: Option<JobMonitor>,
+) -> Result<OptimizeCutsResponse, AppError> {
+    let result = tokio::task::spawn_blocking(move || {
+        let started = Instant::now();
+        let mut budget = SearchBudget::new(req.time_limit_ms, monitor);
+        let mut current_inventory = inventory;
+        let mut sheet_sources: HashMap<i64, StockSheet> = HashMap::new();
+        let pricing = Pricing::new(&req, &catalog);
+        // Pieces larger than every in-stock sheet of their group would only
+        // keep the solver opening sheets they can never go on.
+        let fits_catalog: Vec<bool> = req
+            .cuts
+            .iter()
+            .map(|cut| {
+                catalog.iter().any(|sheet| {
+                    sheet.material.eq_ignore_ascii_case(&cut.material)
+                        && sheet.thickness_mm == cut.thickness_mm
+                        && fits_sheet(sheet, cut, req.trim_mm)
+                })
+            })
+            .collect();
+        let mut response = run_optimization_sync(
+            req.clone(),
+            current_inventory.clone(),
+            &pricing.plank_costs(&current_inventory, &sheet_sources),
+            &mut budget,
+        );
+
+        loop {
+            let pending: Vec<(usize, CutRequest)> = response
+                .unplaced_cuts
+                .iter()
+                .filter(|(idx, _)| fits_catalog[*idx])
+                .cloned()
+                .collect();
+            if pending.is_empty() || sheet_sources.len() >= MAX_EXTRA_SHEETS || budget.cancelled()
+            {
+                break;
+            }
+            let mut added = false;
 
-            current_inventory.push(new_sheet);
+            for ((material, thickness), cuts) in group_unplaced(&pending) {
+                if sheet_sources.len() >= MAX_EXTRA_SHEETS {
+                    break;
+                }
+                let Some(sheet) =
+                    pick_stock_sheet(&catalog, &material, thickness, &cuts, req.trim_mm)
+                else {
+                    continue;
+                };
+
+                let id = -(sheet_sources.len() as i64 + 1);
+                current_inventory.push(virtual_sheet(id, sheet));
+                sheet_sources.insert(id, sheet.clone());
+                added = true;
+
+                tracing::info!(
+                    "Unplaced items found. Adding Virtual Sheet #{} ({} {}mm, {}x{})",
+                    -id,
+                    sheet.material,
+                    sheet.thickness_mm,
+                    sheet.width_mm,
+                    sheet.height_mm
+                );
+            }
 
-            tracing::info!(
-                "Unplaced items found. Adding Virtual Sheet #{}",
-                extra_sheets
+            if !added {
+                break;
+            }
+            response = run_optimization_sync(
+                req.clone(),
+                current_inventory.clone(),
+                &pricing.plank_costs(&current_inventory, &sheet_sources),
+                &mut budget,
             );
-            response = run_optimization_sync(req.clone(), current_inventory.clone());
+        }
+
+        response.search_stats = SearchStats {
+            elapsed_ms: started.elapsed().as_millis() as u64,
+            states_explored: budget.states_explored,
+            timed_out: budget.timed_out,
+        };
+        if budget.timed_out {
+            response.warnings.push(format!(
+                "Time limit of {} ms reached, returning the best plan found",
+                req.time_limit_ms
+            ));
+        }
+
+        for plank in &mut response.used_planks {
+            plank.stock_sheet_id = sheet_sources.get(&plank.resto_id).map(|s| s.id);
+        }
+        response.sheets_to_buy = summarize_purchases(&response.used_planks, &sheet_sources);
+        response.cost =
+            pricing.breakdown(&response.used_planks, &current_inventory, &sheet_sources);
+
+        let mut unpriced: Vec<(String, i64)> = Vec::new();
+        for plank in &response.used_planks {
+            let priced = response
+                .cost
+                .planks
+                .iter()
+                .any(|c| c.resto_id == plank.resto_id && c.cost.is_some());
+            let key = (plank.material.clone(), plank.thickness_mm);
+            if !priced && !unpriced.contains(&key) {
+                unpriced.push(key);
+            }
+        }
+        for (material, thickness) in unpriced {
+            response.warnings.push(format!(
+                "No price known for {} {}mm; its planks are left out of the cost",
+                material, thickness
+            ));
+        }
+
+        let oversized: Vec<(usize, CutRequest)> = response
+            .unplaced_cuts
+            .iter()
+            .filter(|(idx, _)| !fits_catalog[*idx])
+            .cloned()
+            .collect();
+        for ((material, thickness), cuts) in group_unplaced(&response.unplaced_cuts) {
+            let in_catalog = catalog
+                .iter()
+                .any(|s| s.material.to_lowercase() == material && s.thickness_mm == thickness);
+            let too_large = oversized
+                .iter()
+                .filter(|(_, c)| c.material.to_lowercase() == material && c.thickness_mm == thickness)
+                .count();
+            response.warnings.push(if !in_catalog {
+                format!(
+                    "{} piece(s) of {} {}mm could not be placed: no in-stock catalog sheet",
+                    cuts.len(),
+                    cuts[0].material,
+                    thickness
+                )
+            } else if too_large > 0 {
+                format!(
+                    "{} piece(s) of {} {}mm could not be placed: {} larger than every in-stock sheet",
+                    cuts.len(),
+                    cuts[0].material,
+                    thickness,
+                    too_large
+                )
+            } else {
+                format!(
+                    "{} piece(s) of {} {}mm could not be placed",
+                    cuts.len(),
+                    cuts[0].material,
+                    thickness
+                )
+            });
         }
 
         response
@@ -167,262 +690,466 @@ pub async fn optimize_cuts(
     .await
     .map_err(|e| AppError::Validation(format!("Optimization task failed: {}", e)))?;
 
-    Ok(Json(result))
+    Ok(result)
 }
 
-fn run_optimization_sync_asd(
-    req: OptimizeCutsRequest,
-    inventory: Vec<Leftover>,
-) -> OptimizeCutsResponse {
-    let mut all_cuts = Vec::new();
-    for (idx, cut_req) in req.cuts.iter().enumerate() {
-        for _ in 0..cut_req.quantity {
-            all_cuts.push((idx, cut_req.clone()));
-        }
+/// Unplaced cuts grouped by (lowercase material, thickness).
+fn group_unplaced(unplaced: &[(usize, CutRequest)]) -> BTreeMap<(String, i64), Vec<&CutRequest>> {
+    let mut groups: BTreeMap<(String, i64), Vec<&CutRequest>> = BTreeMap::new();
+    for (_, cut) in unplaced {
+        groups
+            .entry((cut.material.to_lowercase(), cut.thickness_mm))
+            .or_default()
+            .push(cut);
     }
+    groups
+}
 
-    all_cuts.sort_by(|a, b| {
-        let area_a = a.1.width_mm * a.1.height_mm;
-        let area_b = b.1.width_mm * b.1.height_mm;
-        match area_b.cmp(&area_a) {
-            Ordering::Equal => {
-                let max_dim_a = a.1.width_mm.max(a.1.height_mm);
-                let max_dim_b = b.1.width_mm.max(b.1.height_mm);
-                max_dim_b.cmp(&max_dim_a)
-            }
-            other => other,
-        }
-    });
+/// Smallest in-stock sheet of the group that fits every pending cut, falling
+/// back to the largest one that fits at least one of them.
+fn pick_stock_sheet<'a>(
+    catalog: &'a [StockSheet],
+    material: &str,
+    thickness_mm: i64,
+    cuts: &[&CutRequest],
+    default_trim: i64,
+) -> Option<&'a StockSheet> {
+    let candidates: Vec<&StockSheet> = catalog
+        .iter()
+        .filter(|s| s.material.to_lowercase() == material && s.thickness_mm == thickness_mm)
+        .collect();
 
-    let mut used_planks: Vec<UsedPlank> = Vec::new();
-    let mut unplaced_cuts: Vec<(usize, CutRequest)> = Vec::new();
-
-    let mut plank_pools: HashMap<(String, i64), Vec<(Leftover, Vec<FreeRect>)>> = HashMap::new();
-
-    for plank in inventory.into_iter() {
-        let key = (plank.material.to_lowercase(), plank.thickness_mm);
-        let free_rects = vec![FreeRect {
-            x: 0,
-            y: 0,
-            width: plank.width_mm,
-            height: plank.height_mm,
-        }];
-        plank_pools
-            .entry(key)
-            .or_insert_with(Vec::new)
-            .push((plank, free_rects));
-    }
-
-    for (orig_idx, cut) in all_cuts {
-        let key = (cut.material.to_lowercase(), cut.thickness_mm);
-
-        if let Some(planks) = plank_pools.get_mut(&key) {
-            let mut placed = false;
-
-            planks.sort_by(|(plank_a, _), (plank_b, _)| {
-                let a_used = used_planks.iter().any(|p| p.resto_id == plank_a.id);
-                let b_used = used_planks.iter().any(|p| p.resto_id == plank_b.id);
-
-                match (a_used, b_used) {
-                    (true, false) => Ordering::Less,
-                    (false, true) => Ordering::Greater,
-                    _ => {
-                        let area_a = plank_a.width_mm * plank_a.height_mm;
-                        let area_b = plank_b.width_mm * plank_b.height_mm;
-                        area_a.cmp(&area_b)
-                    }
-                }
+    let fits = |sheet: &StockSheet, cut: &CutRequest| fits_sheet(sheet, cut, default_trim);
+
+    candidates
+        .iter()
+        .find(|s| cuts.iter().all(|c| fits(s, c)))
+        .or_else(|| {
+            candidates
+                .iter()
+                .rev()
+                .find(|s| cuts.iter().any(|c| fits(s, c)))
+        })
+        .copied()
+}
+
+/// Whether `cut` fits on a fresh catalog sheet once its edges are trimmed.
+fn fits_sheet(sheet: &StockSheet, cut: &CutRequest, default_trim: i64) -> bool {
+    let plank = virtual_sheet(0, sheet);
+    let usable = usable_rects(&plank, default_trim, PlacementStrategy::Guillotine);
+    allowed_rotations(cut, &plank).iter().any(|&rotated| {
+        let (w, h) = if rotated {
+            (cut.height_mm, cut.width_mm)
+        } else {
+            (cut.width_mm, cut.height_mm)
+        };
+        usable.iter().any(|r| w <= r.width && h <= r.height)
+    })
+}
+
+pub fn virtual_sheet(id: i64, sheet: &StockSheet) -> Leftover {
+    Leftover {
+        id,
+        width_mm: sheet.width_mm,
+        height_mm: sheet.height_mm,
+        thickness_mm: sheet.thickness_mm,
+        material: sheet.material.clone(),
+        notes: Some(format!("New sheet (catalog #{})", sheet.id)),
+        grain: sheet.grain,
+        trim_mm: sheet.trim_mm,
+        created_at: String::new(),
+        shape: None,
+        defects: Vec::new(),
+    }
+}
+
+/// Area of `plank` left after trimming its edges, seeding its free space; the
+/// trim of the plank itself wins over the request default. A shaped leftover
+/// gives several rectangles, disjoint unless the strategy allows overlap.
+/// Coordinates stay in the plank frame.
+fn usable_rects(plank: &Leftover, default_trim: i64, strategy: PlacementStrategy) -> Vec<FreeRect> {
+    let trim = effective_trim(plank, default_trim);
+    plank
+        .usable_rects(trim, strategy == PlacementStrategy::MaxRects)
+        .into_iter()
+        .map(|r| FreeRect {
+            x: r.x,
+            y: r.y,
+            width: r.width_mm,
+            height: r.height_mm,
+        })
+        .collect()
+}
+
+fn effective_trim(plank: &Leftover, default_trim: i64) -> i64 {
+    plank.trim_mm.unwrap_or(default_trim)
+}
+
+fn summarize_purchases(
+    used_planks: &[UsedPlank],
+    sheet_sources: &HashMap<i64, StockSheet>,
+) -> Vec<SheetPurchase> {
+    let mut purchases: Vec<SheetPurchase> = Vec::new();
+    for plank in used_planks {
+        let Some(sheet) = sheet_sources.get(&plank.resto_id) else {
+            continue;
+        };
+        if let Some(p) = purchases.iter_mut().find(|p| p.stock_sheet_id == sheet.id) {
+            p.quantity += 1;
+            p.total_price = sheet.price.map(|price| price * p.quantity as f64);
+        } else {
+            purchases.push(SheetPurchase {
+                stock_sheet_id: sheet.id,
+                material: sheet.material.clone(),
+                thickness_mm: sheet.thickness_mm,
+                width_mm: sheet.width_mm,
+                height_mm: sheet.height_mm,
+                quantity: 1,
+                unit_price: sheet.price,
+                total_price: sheet.price,
             });
+        }
+    }
+    purchases
+}
 
-            println!("Cut {}x{} - Sorted planks:", cut.width_mm, cut.height_mm);
-            for (i, (plank, _)) in planks.iter().take(5).enumerate() {
-                println!(
-                    "  {}. Plank ID={} {}x{}mm ({}mm²)",
-                    i + 1,
-                    plank.id,
-                    plank.width_mm,
-                    plank.height_mm,
-                    plank.width_mm * plank.height_mm
-                );
-            }
+/// Per-m² prices used to rank plans by material cost. Request prices win over
+/// the cheapest in-stock catalog sheet of the same material and thickness.
+struct Pricing {
+    requested: HashMap<(String, i64), f64>,
+    requested_any_thickness: HashMap<String, f64>,
+    catalog: HashMap<(String, i64), f64>,
+    leftover_discount_percent: f64,
+}
 
-            for (plank, free_rects) in planks.iter_mut() {
-                if free_rects.is_empty() {
-                    continue;
-                }
+impl Pricing {
+    fn new(req: &OptimizeCutsRequest, catalog: &[StockSheet]) -> Self {
+        let mut requested = HashMap::new();
+        let mut requested_any_thickness = HashMap::new();
+        for price in &req.material_prices {
+            let material = price.material.trim().to_lowercase();
+            match price.thickness_mm {
+                Some(thickness) => requested.insert((material, thickness), price.price_per_m2),
+                None => requested_any_thickness.insert(material, price.price_per_m2),
+            };
+        }
 
-                let orientations = [
-                    (cut.width_mm, cut.height_mm, false),
-                    (cut.height_mm, cut.width_mm, true),
-                ];
-
-                let mut best_rect_idx: Option<usize> = None;
-                let mut best_waste = i64::MAX;
-                let mut chosen_orientation = (0, 0, false);
-
-                for (w, h, rotated) in orientations {
-                    for (i, rect) in free_rects.iter().enumerate() {
-                        if rect.width >= w && rect.height >= h {
-                            let waste = (rect.width * rect.height) - (w * h);
-                            if waste < best_waste {
-                                best_waste = waste;
-                                best_rect_idx = Some(i);
-                                chosen_orientation = (w, h, rotated);
-                            }
-                        }
-                    }
-                }
+        let mut catalog_prices: HashMap<(String, i64), f64> = HashMap::new();
+        for sheet in catalog {
+            let Some(price) = sheet.price else {
+                continue;
+            };
+            let per_m2 = price / area_m2(sheet.width_mm, sheet.height_mm);
+            let entry = catalog_prices
+                .entry((sheet.material.to_lowercase(), sheet.thickness_mm))
+                .or_insert(per_m2);
+            *entry = entry.min(per_m2);
+        }
 
-                if let Some(rect_idx) = best_rect_idx {
-                    let (w, h, rotated) = chosen_orientation;
-                    let rect = free_rects.remove(rect_idx);
-
-                    let placed_cut = PlacedCut {
-                        original_index: orig_idx,
-                        x: rect.x,
-                        y: rect.y,
-                        width: w,
-                        height: h,
-                        rotated,
-                        material: cut.material.clone(),
-                        thickness_mm: cut.thickness_mm,
-                    };
+        Self {
+            requested,
+            requested_any_thickness,
+            catalog: catalog_prices,
+            leftover_discount_percent: req.leftover_discount_percent,
+        }
+    }
 
-                    let kerf = req.kerf_width_mm;
-                    let remain_w = rect.width.saturating_sub(w + kerf);
-                    let remain_h = rect.height.saturating_sub(h + kerf);
-
-                    let bottom_area_h_split = rect.width * remain_h;
-                    let right_area_v_split = remain_w * rect.height;
-
-                    let (new_right, new_bottom) = if bottom_area_h_split >= right_area_v_split {
-                        (
-                            FreeRect {
-                                x: rect.x + w + kerf,
-                                y: rect.y,
-                                width: remain_w,
-                                height: h,
-                            },
-                            FreeRect {
-                                x: rect.x,
-                                y: rect.y + h + kerf,
-                                width: rect.width,
-                                height: remain_h,
-                            },
-                        )
-                    } else {
-                        (
-                            FreeRect {
-                                x: rect.x + w + kerf,
-                                y: rect.y,
-                                width: remain_w,
-                                height: rect.height,
-                            },
-                            FreeRect {
-                                x: rect.x,
-                                y: rect.y + h + kerf,
-                                width: w,
-                                height: remain_h,
-                            },
-                        )
-                    };
+    fn price_per_m2(&self, material: &str, thickness_mm: i64) -> Option<f64> {
+        let key = (material.to_lowercase(), thickness_mm);
+        self.requested
+            .get(&key)
+            .or_else(|| self.requested_any_thickness.get(&key.0))
+            .or_else(|| self.catalog.get(&key))
+            .copied()
+    }
 
-                    let min_useful_size = 30;
-                    if new_right.width >= min_useful_size && new_right.height >= min_useful_size {
-                        free_rects.push(new_right);
-                    }
-                    if new_bottom.width >= min_useful_size && new_bottom.height >= min_useful_size {
-                        free_rects.push(new_bottom);
-                    }
+    /// New sheets cost their catalog price, falling back to the area price;
+    /// leftovers cost the area price less the leftover discount.
+    fn plank_cost(&self, plank: &Leftover, sheet: Option<&StockSheet>) -> PlankCost {
+        let area = plank.area_mm2() as f64 / 1_000_000.0;
+        let per_m2 = self.price_per_m2(&plank.material, plank.thickness_mm);
+        let (price_per_m2, discount_percent) = match sheet.and_then(|s| s.price) {
+            Some(price) => (Some(price / area), 0.0),
+            None if sheet.is_some() => (per_m2, 0.0),
+            None => (per_m2, self.leftover_discount_percent),
+        };
+        let cost = price_per_m2.map(|p| p * area * (1.0 - discount_percent / 100.0));
+
+        PlankCost {
+            resto_id: plank.id,
+            stock_sheet_id: sheet.map(|s| s.id),
+            area_m2: area,
+            price_per_m2,
+            discount_percent,
+            cost,
+        }
+    }
 
-                    if let Some(plank_entry) =
-                        used_planks.iter_mut().find(|p| p.resto_id == plank.id)
-                    {
-                        plank_entry.cuts.push(placed_cut);
-                    } else {
-                        used_planks.push(UsedPlank {
-                            resto_id: plank.id,
-                            width_mm: plank.width_mm,
-                            height_mm: plank.height_mm,
-                            thickness_mm: plank.thickness_mm,
-                            material: plank.material.clone(),
-                            cuts: vec![placed_cut],
-                            waste_percent: 0.0,
-                            total_area_mm2: plank.width_mm * plank.height_mm,
-                            used_area_mm2: 0,
-                        });
-                    }
+    /// Cost of opening each plank of `inventory`; unpriced planks count as
+    /// free so they are ranked by efficiency alone.
+    fn plank_costs(
+        &self,
+        inventory: &[Leftover],
+        sheet_sources: &HashMap<i64, StockSheet>,
+    ) -> HashMap<i64, f64> {
+        inventory
+            .iter()
+            .map(|plank| {
+                let cost = self.plank_cost(plank, sheet_sources.get(&plank.id));
+                (plank.id, cost.cost.unwrap_or(0.0))
+            })
+            .collect()
+    }
 
-                    placed = true;
-                    break;
+    fn breakdown(
+        &self,
+        used_planks: &[UsedPlank],
+        inventory: &[Leftover],
+        sheet_sources: &HashMap<i64, StockSheet>,
+    ) -> CostBreakdown {
+        let mut breakdown = CostBreakdown::default();
+        for used in used_planks {
+            let Some(plank) = inventory.iter().find(|p| p.id == used.resto_id) else {
+                continue;
+            };
+            let cost = self.plank_cost(plank, sheet_sources.get(&plank.id));
+            if let Some(value) = cost.cost {
+                breakdown.total += value;
+                if cost.stock_sheet_id.is_some() {
+                    breakdown.new_sheets_total += value;
+                } else {
+                    breakdown.leftovers_total += value;
                 }
             }
-
-            if !placed {
-                unplaced_cuts.push((orig_idx, cut));
-            }
-        } else {
-            unplaced_cuts.push((orig_idx, cut));
+            breakdown.planks.push(cost);
         }
+        breakdown
     }
+}
 
-    for plank in &mut used_planks {
-        let total = plank.total_area_mm2;
-        let mut used = 0i64;
+fn area_m2(width_mm: i64, height_mm: i64) -> f64 {
+    (width_mm * height_mm) as f64 / 1_000_000.0
+}
 
-        for cut in &plank.cuts {
-            used += cut.width * cut.height;
-            used += (cut.width + cut.height) * req.kerf_width_mm;
-        }
+/// Read-only inputs shared by every solver pass of one run.
+struct SolverContext<'a> {
+    req: &'a OptimizeCutsRequest,
+    inventory: &'a [Leftover],
+    plank_costs: &'a HashMap<i64, f64>,
+    objective: &'a dyn Objective,
+}
 
-        plank.used_area_mm2 = used;
-        plank.waste_percent = if total > 0 {
-            ((total - used) as f64 / total as f64) * 100.0
-        } else {
-            0.0
-        };
+impl SolverContext<'_> {
+    fn plank(&self, id: i64) -> &Leftover {
+        self.inventory.iter().find(|p| p.id == id).unwrap()
     }
 
-    let total_cuts_placed =
-        req.cuts.iter().map(|c| c.quantity as usize).sum::<usize>() - unplaced_cuts.len();
-    let total_area_used: i64 = used_planks.iter().map(|p| p.used_area_mm2).sum();
-    let total_area_available: i64 = used_planks.iter().map(|p| p.total_area_mm2).sum();
-    let efficiency = if total_area_available > 0 {
-        (total_area_used as f64 / total_area_available as f64) * 100.0
-    } else {
+    fn plank_cost(&self, id: i64) -> f64 {
+        self.plank_costs.get(&id).copied().unwrap_or(0.0)
+    }
+}
+
+/// Scores a partial plan; lower is better. Plans are compared on unplaced
+/// pieces first, so a score only decides between plans that place as many.
+trait Objective {
+    fn score(&self, state: &OptimizationState, ctx: &SolverContext) -> f64;
+
+    /// Score added by starting on a fresh plank, used to try the most
+    /// promising planks first.
+    fn plank_penalty(&self, _plank: &Leftover, _ctx: &SolverContext) -> f64 {
         0.0
-    };
+    }
+}
 
-    OptimizeCutsResponse {
-        success: true,
-        used_planks,
-        unplaced_cuts,
-        efficiency_percent: efficiency,
-        total_cuts_placed,
-        total_cuts_requested: req.cuts.iter().map(|c| c.quantity as usize).sum(),
+struct MaterialCost;
+
+impl Objective for MaterialCost {
+    fn score(&self, state: &OptimizationState, ctx: &SolverContext) -> f64 {
+        state
+            .used_planks
+            .iter()
+            .map(|p| ctx.plank_cost(p.resto_id))
+            .sum()
+    }
+
+    fn plank_penalty(&self, plank: &Leftover, ctx: &SolverContext) -> f64 {
+        ctx.plank_cost(plank.id)
+    }
+}
+
+struct FewestSheets;
+
+impl Objective for FewestSheets {
+    fn score(&self, state: &OptimizationState, _ctx: &SolverContext) -> f64 {
+        state.used_planks.len() as f64
+    }
+}
+
+struct LeastWaste;
+
+impl Objective for LeastWaste {
+    fn score(&self, state: &OptimizationState, _ctx: &SolverContext) -> f64 {
+        state
+            .used_planks
+            .iter()
+            .map(|p| (p.total_area_mm2 - p.used_area_mm2) as f64 / 1_000_000.0)
+            .sum()
+    }
+
+    fn plank_penalty(&self, plank: &Leftover, _ctx: &SolverContext) -> f64 {
+        plank.area_mm2() as f64 / 1_000_000.0
+    }
+}
+
+struct LargestOffcut;
+
+impl Objective for LargestOffcut {
+    fn score(&self, state: &OptimizationState, _ctx: &SolverContext) -> f64 {
+        let largest = state
+            .used_planks
+            .iter()
+            .filter_map(|p| state.free_rects_by_plank.get(&p.resto_id))
+            .flatten()
+            .map(|r| area_m2(r.width, r.height))
+            .fold(0.0, f64::max);
+        -largest
+    }
+
+    fn plank_penalty(&self, plank: &Leftover, _ctx: &SolverContext) -> f64 {
+        -(plank.area_mm2() as f64 / 1_000_000.0)
+    }
+}
+
+/// When a leftover was added. Rows written by the API carry RFC 3339 while
+/// older ones hold SQLite's `CURRENT_TIMESTAMP` (`YYYY-MM-DD HH:MM:SS`, UTC),
+/// and the two do not sort together as text.
+fn created_instant(created_at: &str) -> Option<OffsetDateTime> {
+    OffsetDateTime::parse(created_at, &time::format_description::well_known::Rfc3339)
+        .ok()
+        .or_else(|| {
+            time::PrimitiveDateTime::parse(
+                created_at,
+                format_description!("[year]-[month]-[day] [hour]:[minute]:[second]"),
+            )
+            .ok()
+            .map(|t| t.assume_utc())
+        })
+}
+
+/// Freshness of every plank from 0 (oldest leftover) to 1 (newest leftover
+/// or new sheet). Leftovers with an unreadable `created_at` count as newest.
+struct OldestFirst {
+    freshness: HashMap<i64, f64>,
+}
+
+impl OldestFirst {
+    fn new(inventory: &[Leftover]) -> Self {
+        let created: HashMap<i64, OffsetDateTime> = inventory
+            .iter()
+            .filter(|p| p.id > 0)
+            .filter_map(|p| Some((p.id, created_instant(&p.created_at)?)))
+            .collect();
+        let mut dates: Vec<OffsetDateTime> = created.values().copied().collect();
+        dates.sort_unstable();
+        dates.dedup();
+
+        let steps = dates.len().saturating_sub(1).max(1) as f64;
+        let freshness = inventory
+            .iter()
+            .map(|p| {
+                let rank = created.get(&p.id).and_then(|d| dates.binary_search(d).ok());
+                match rank {
+                    Some(rank) => (p.id, rank as f64 / steps),
+                    None => (p.id, 1.0),
+                }
+            })
+            .collect();
+        Self { freshness }
+    }
+}
+
+impl Objective for OldestFirst {
+    fn score(&self, state: &OptimizationState, ctx: &SolverContext) -> f64 {
+        state
+            .used_planks
+            .iter()
+            .map(|p| self.plank_penalty(ctx.plank(p.resto_id), ctx))
+            .sum()
+    }
+
+    fn plank_penalty(&self, plank: &Leftover, _ctx: &SolverContext) -> f64 {
+        1.0 + self.freshness.get(&plank.id).copied().unwrap_or(1.0)
+    }
+}
+
+struct Weighted(Vec<(f64, Box<dyn Objective>)>);
+
+impl Objective for Weighted {
+    fn score(&self, state: &OptimizationState, ctx: &SolverContext) -> f64 {
+        self.0.iter().map(|(w, o)| w * o.score(state, ctx)).sum()
+    }
+
+    fn plank_penalty(&self, plank: &Leftover, ctx: &SolverContext) -> f64 {
+        self.0
+            .iter()
+            .map(|(w, o)| w * o.plank_penalty(plank, ctx))
+            .sum()
+    }
+}
+
+impl ObjectiveKind {
+    fn build(self, inventory: &[Leftover]) -> Box<dyn Objective> {
+        match self {
+            ObjectiveKind::MaterialCost => Box::new(MaterialCost),
+            ObjectiveKind::FewestSheets => Box::new(FewestSheets),
+            ObjectiveKind::LeastWaste => Box::new(LeastWaste),
+            ObjectiveKind::LargestOffcut => Box::new(LargestOffcut),
+            ObjectiveKind::OldestFirst => Box::new(OldestFirst::new(inventory)),
+        }
+    }
+}
+
+impl ObjectiveSpec {
+    fn build(&self, inventory: &[Leftover]) -> Box<dyn Objective> {
+        match self {
+            ObjectiveSpec::Single(kind) => kind.build(inventory),
+            ObjectiveSpec::Weighted(parts) => Box::new(Weighted(
+                parts
+                    .iter()
+                    .map(|p| (p.weight, p.kind.build(inventory)))
+                    .collect(),
+            )),
+        }
+    }
+}
+
+/// Orientations `cut` may take on `plank` (`true` = turned 90°). When both have
+/// a grain, the piece must be turned so its grain follows the plank's.
+pub fn allowed_rotations(cut: &CutRequest, plank: &Leftover) -> &'static [bool] {
+    match (cut.grain, plank.grain) {
+        (Some(piece), Some(board)) if piece == board => &[false],
+        (Some(_), Some(_)) if cut.rotation_allowed => &[true],
+        (Some(_), Some(_)) => &[],
+        _ if cut.rotation_allowed => &[false, true],
+        _ => &[false],
     }
 }
 
+fn placed_grain(cut: &CutRequest, rotated: bool) -> Option<Grain> {
+    cut.grain.map(|g| if rotated { g.rotated() } else { g })
+}
+
 impl OptimizationState {
-    fn new(inventory: &Vec<Leftover>) -> Self {
+    fn new(inventory: &[Leftover], req: &OptimizeCutsRequest) -> Self {
         let mut free_rects = HashMap::new();
         for plank in inventory {
-            free_rects.insert(
-                plank.id,
-                vec![FreeRect {
-                    x: 0,
-                    y: 0,
-                    width: plank.width_mm,
-                    height: plank.height_mm,
-                }],
-            );
+            free_rects.insert(plank.id, usable_rects(plank, req.trim_mm, req.strategy));
         }
 
         Self {
             used_planks: Vec::new(),
             free_rects_by_plank: free_rects,
             unplaced_cuts: Vec::new(),
-            score: 0,
         }
     }
 
@@ -439,29 +1166,49 @@ impl OptimizationState {
 fn find_all_placements(
     state: &OptimizationState,
     cut: &CutRequest,
-    inventory: &Vec<Leftover>,
-    req: &OptimizeCutsRequest,
+    ctx: &SolverContext,
+    limit: usize,
 ) -> Vec<(i64, usize, bool)> {
     let mut options = Vec::new();
     let mat_key = cut.material.to_lowercase();
 
-    for plank in inventory {
+    for plank in ctx.inventory {
         if plank.material.to_lowercase() != mat_key || plank.thickness_mm != cut.thickness_mm {
             continue;
         }
 
+        let rotations = allowed_rotations(cut, plank);
         if let Some(free_rects) = state.free_rects_by_plank.get(&plank.id) {
             for (i, rect) in free_rects.iter().enumerate() {
-                if rect.width >= cut.width_mm && rect.height >= cut.height_mm {
-                    options.push((plank.id, i, false));
-                }
-                if rect.width >= cut.height_mm && rect.height >= cut.width_mm {
-                    options.push((plank.id, i, true));
+                for &rotated in rotations {
+                    let (w, h) = if rotated {
+                        (cut.height_mm, cut.width_mm)
+                    } else {
+                        (cut.width_mm, cut.height_mm)
+                    };
+                    if rect.width >= w && rect.height >= h {
+                        options.push((plank.id, i, rotated));
+                    }
                 }
             }
         }
     }
 
+    // MaxRects keeps many overlapping candidates per plank, so rank them by
+    // best short side fit instead of list order.
+    let short_side_fit = |(plank_id, rect_idx, rotated): &(i64, usize, bool)| {
+        if ctx.req.strategy != PlacementStrategy::MaxRects {
+            return 0;
+        }
+        let rect = &state.free_rects_by_plank[plank_id][*rect_idx];
+        let (w, h) = if *rotated {
+            (cut.height_mm, cut.width_mm)
+        } else {
+            (cut.width_mm, cut.height_mm)
+        };
+        (rect.width - w).min(rect.height - h)
+    };
+
     options.sort_by(|a, b| {
         let a_used = state.used_planks.iter().any(|p| p.resto_id == a.0);
         let b_used = state.used_planks.iter().any(|p| p.resto_id == b.0);
@@ -470,30 +1217,34 @@ fn find_all_placements(
             (true, false) => Ordering::Less,
             (false, true) => Ordering::Greater,
             _ => {
-                let a_plank = inventory.iter().find(|p| p.id == a.0).unwrap();
-                let b_plank = inventory.iter().find(|p| p.id == b.0).unwrap();
-                let area_a = a_plank.width_mm * a_plank.height_mm;
-                let area_b = b_plank.width_mm * b_plank.height_mm;
-                area_a.cmp(&area_b)
+                let a_plank = ctx.plank(a.0);
+                let b_plank = ctx.plank(b.0);
+                let area_a = a_plank.area_mm2();
+                let area_b = b_plank.area_mm2();
+                let penalty_a = ctx.objective.plank_penalty(a_plank, ctx);
+                let penalty_b = ctx.objective.plank_penalty(b_plank, ctx);
+                penalty_a
+                    .partial_cmp(&penalty_b)
+                    .unwrap_or(Ordering::Equal)
+                    .then_with(|| area_a.cmp(&area_b))
+                    .then_with(|| short_side_fit(a).cmp(&short_side_fit(b)))
             }
         }
     });
 
-    options.into_iter().take(3).collect()
+    options.into_iter().take(limit).collect()
 }
 
 fn apply_cut(
     state: &mut OptimizationState,
     cut: &CutRequest,
-    plank_id: i64,
-    rect_idx: usize,
-    rotated: bool,
-    req: &OptimizeCutsRequest,
-    inventory: &Vec<Leftover>,
+    (plank_id, rect_idx, rotated): (i64, usize, bool),
     orig_idx: usize,
+    ctx: &SolverContext,
 ) {
+    let req = ctx.req;
     let free_rects = state.free_rects_by_plank.get_mut(&plank_id).unwrap();
-    let rect = free_rects.remove(rect_idx);
+    let rect = free_rects[rect_idx].clone();
 
     let w = if rotated { cut.height_mm } else { cut.width_mm };
     let h = if rotated { cut.width_mm } else { cut.height_mm };
@@ -507,6 +1258,7 @@ fn apply_cut(
         rotated,
         material: cut.material.clone(),
         thickness_mm: cut.thickness_mm,
+        grain: placed_grain(cut, rotated),
     };
 
     if let Some(existing) = state
@@ -517,21 +1269,68 @@ fn apply_cut(
         existing.cuts.push(placed_cut);
         existing.used_area_mm2 += (w * h) + ((w + h) * req.kerf_width_mm);
     } else {
-        let raw_plank = inventory.iter().find(|p| p.id == plank_id).unwrap();
+        let raw_plank = ctx.plank(plank_id);
         state.used_planks.push(UsedPlank {
             resto_id: plank_id,
             width_mm: raw_plank.width_mm,
             height_mm: raw_plank.height_mm,
             thickness_mm: raw_plank.thickness_mm,
             material: raw_plank.material.clone(),
+            grain: raw_plank.grain,
+            stock_sheet_id: None,
+            trim_mm: effective_trim(raw_plank, req.trim_mm),
+            resto_hash: (plank_id > 0).then(|| resto_hash(raw_plank)),
             cuts: vec![placed_cut],
             waste_percent: 0.0,
-            total_area_mm2: raw_plank.width_mm * raw_plank.height_mm,
+            total_area_mm2: raw_plank.area_mm2(),
             used_area_mm2: (w * h) + ((w + h) * req.kerf_width_mm),
+            remainders: Vec::new(),
         });
     }
 
     let kerf = req.kerf_width_mm;
+    let min_size = min_useful_size(req);
+
+    match req.strategy {
+        PlacementStrategy::Guillotine => {
+            free_rects.remove(rect_idx);
+            split_guillotine(free_rects, &rect, w, h, kerf, min_size);
+        }
+        PlacementStrategy::MaxRects => {
+            // The kerf surrounds the piece on every side, so neighbours placed
+            // from any other free rectangle keep a saw gap.
+            let occupied = FreeRect {
+                x: rect.x - kerf,
+                y: rect.y - kerf,
+                width: w + 2 * kerf,
+                height: h + 2 * kerf,
+            };
+            split_maxrects(free_rects, &occupied, min_size);
+        }
+    }
+}
+
+/// Free rectangles narrower than the smallest requested piece side can never
+/// hold a cut and are dropped, unless they may still be kept as a remainder.
+fn min_useful_size(req: &OptimizeCutsRequest) -> i64 {
+    req.cuts
+        .iter()
+        .map(|c| c.width_mm.min(c.height_mm))
+        .min()
+        .unwrap_or(1)
+        .min(req.min_remainder_width_mm)
+        .min(req.min_remainder_height_mm)
+        .max(1)
+}
+
+fn split_guillotine(
+    free_rects: &mut Vec<FreeRect>,
+    rect: &FreeRect,
+    w: i64,
+    h: i64,
+    kerf: i64,
+    min_size: i64,
+) {
     let remain_right_w = rect.width - w - kerf;
     let remain_bottom_h = rect.height - h - kerf;
 
@@ -567,83 +1366,196 @@ fn apply_cut(
         )
     };
 
-    if new_right.width >= 30 && new_right.height >= 30 {
+    if new_right.width >= min_size && new_right.height >= min_size {
         free_rects.push(new_right);
     }
-    if new_bottom.width >= 30 && new_bottom.height >= 30 {
+    if new_bottom.width >= min_size && new_bottom.height >= min_size {
         free_rects.push(new_bottom);
     }
 }
 
-fn run_optimization_sync(
-    req: OptimizeCutsRequest,
-    inventory: Vec<Leftover>,
-) -> OptimizeCutsResponse {
-    let mut all_cuts = Vec::new();
-    for (idx, cut_req) in req.cuts.iter().enumerate() {
-        for _ in 0..cut_req.quantity {
-            all_cuts.push((idx, cut_req.clone()));
+/// Carves `occupied` out of every free rectangle it touches, keeping the
+/// maximal leftover strips on each side, then drops contained rectangles.
+fn split_maxrects(free_rects: &mut Vec<FreeRect>, occupied: &FreeRect, min_size: i64) {
+    let mut next = Vec::with_capacity(free_rects.len() + 4);
+
+    for rect in free_rects.drain(..) {
+        if !rect.intersects(occupied) {
+            next.push(rect);
+            continue;
+        }
+        if occupied.x > rect.x {
+            next.push(FreeRect {
+                x: rect.x,
+                y: rect.y,
+                width: occupied.x - rect.x,
+                height: rect.height,
+            });
+        }
+        if occupied.right() < rect.right() {
+            next.push(FreeRect {
+                x: occupied.right(),
+                y: rect.y,
+                width: rect.right() - occupied.right(),
+                height: rect.height,
+            });
+        }
+        if occupied.y > rect.y {
+            next.push(FreeRect {
+                x: rect.x,
+                y: rect.y,
+                width: rect.width,
+                height: occupied.y - rect.y,
+            });
+        }
+        if occupied.bottom() < rect.bottom() {
+            next.push(FreeRect {
+                x: rect.x,
+                y: occupied.bottom(),
+                width: rect.width,
+                height: rect.bottom() - occupied.bottom(),
+            });
+        }
+    }
+
+    next.retain(|r| r.width >= min_size && r.height >= min_size);
+
+    for i in 0..next.len() {
+        let contained = next.iter().enumerate().any(|(j, other)| {
+            j != i && other.contains(&next[i]) && (j < i || !next[i].contains(other))
+        });
+        if !contained {
+            free_rects.push(next[i].clone());
         }
     }
-    all_cuts.sort_by(|a, b| (b.1.width_mm * b.1.height_mm).cmp(&(a.1.width_mm * a.1.height_mm)));
+}
+
+/// Fewest unplaced pieces first, then lowest objective score, then most area
+/// used.
+fn compare_states(a: &OptimizationState, b: &OptimizationState, ctx: &SolverContext) -> Ordering {
+    let unplaced_cmp = a.unplaced_cuts.len().cmp(&b.unplaced_cuts.len());
+    if unplaced_cmp != Ordering::Equal {
+        return unplaced_cmp;
+    }
+    let score_a = ctx.objective.score(a, ctx);
+    let score_b = ctx.objective.score(b, ctx);
+    if (score_a - score_b).abs() > 1e-9 {
+        return score_a.partial_cmp(&score_b).unwrap_or(Ordering::Equal);
+    }
+    let eff_a = a.calculate_efficiency(1);
+    let eff_b = b.calculate_efficiency(1);
+    eff_b.partial_cmp(&eff_a).unwrap_or(Ordering::Equal)
+}
 
-    let initial_state = OptimizationState::new(&inventory);
+/// Places `all_cuts` in order, keeping the `width` best partial plans. Once the
+/// budget runs out the search narrows to a single greedy line so the plan is
+/// still completed.
+fn beam_search(
+    all_cuts: &[(usize, CutRequest)],
+    ctx: &SolverContext,
+    width: usize,
+    candidates_per_step: usize,
+    budget: &mut SearchBudget,
+) -> OptimizationState {
+    let initial_state = OptimizationState::new(ctx.inventory, ctx.req);
     let mut beam = vec![initial_state];
 
-    for (orig_idx, cut) in all_cuts {
+    for (step, (orig_idx, cut)) in all_cuts.iter().enumerate() {
+        if budget.cancelled() {
+            // The caller discards a cancelled run; stop without placing the rest.
+            for state in &mut beam {
+                state.unplaced_cuts.extend_from_slice(&all_cuts[step..]);
+            }
+            break;
+        }
+        if let Some(monitor) = &budget.monitor {
+            monitor.step(step + 1, all_cuts.len());
+        }
+        let (width, candidates_per_step) = if budget.expired() {
+            (1, 1)
+        } else {
+            (width, candidates_per_step)
+        };
         let mut next_generation_candidates = Vec::new();
 
         for state in beam {
-            let candidates = find_all_placements(&state, &cut, &inventory, &req);
+            let candidates = find_all_placements(&state, cut, ctx, candidates_per_step);
 
             if candidates.is_empty() {
                 let mut new_state = state.clone();
-                new_state.unplaced_cuts.push((orig_idx, cut.clone()));
+                new_state.unplaced_cuts.push((*orig_idx, cut.clone()));
                 next_generation_candidates.push(new_state);
             } else {
-                for (plank_id, rect_idx, rotated) in candidates {
+                for placement in candidates {
                     let mut new_state = state.clone();
-                    apply_cut(
-                        &mut new_state,
-                        &cut,
-                        plank_id,
-                        rect_idx,
-                        rotated,
-                        &req,
-                        &inventory,
-                        orig_idx,
-                    );
+                    apply_cut(&mut new_state, cut, placement, *orig_idx, ctx);
                     next_generation_candidates.push(new_state);
                 }
             }
         }
 
-        next_generation_candidates.sort_by(|a, b| {
-            let unplaced_cmp = a.unplaced_cuts.len().cmp(&b.unplaced_cuts.len());
-            if unplaced_cmp != Ordering::Equal {
-                return unplaced_cmp;
-            }
-            let eff_a = a.calculate_efficiency(1);
-            let eff_b = b.calculate_efficiency(1);
-            eff_b.partial_cmp(&eff_a).unwrap_or(Ordering::Equal)
-        });
+        budget.states_explored += next_generation_candidates.len() as u64;
+        next_generation_candidates.sort_by(|a, b| compare_states(a, b, ctx));
 
-        beam = next_generation_candidates
-            .into_iter()
-            .take(BEAM_WIDTH)
-            .collect();
+        beam = next_generation_candidates.into_iter().take(width).collect();
     }
 
-    let best_state = beam.first().unwrap().clone();
+    beam.into_iter().next().unwrap()
+}
+
+fn run_optimization_sync(
+    req: OptimizeCutsRequest,
+    inventory: Vec<Leftover>,
+    plank_costs: &HashMap<i64, f64>,
+    budget: &mut SearchBudget,
+) -> OptimizeCutsResponse {
+    let objective = req.objective.build(&inventory);
+    let ctx = SolverContext {
+        req: &req,
+        inventory: &inventory,
+        plank_costs,
+        objective: objective.as_ref(),
+    };
+    let mut all_cuts = Vec::new();
+    for (idx, cut_req) in req.cuts.iter().enumerate() {
+        for _ in 0..cut_req.quantity {
+            all_cuts.push((idx, cut_req.clone()));
+        }
+    }
+    all_cuts.sort_by_key(|(_, c)| std::cmp::Reverse(c.width_mm * c.height_mm));
+
+    // A greedy pass first gives a complete plan to fall back on, then the
+    // beam search tries to beat it within the remaining budget.
+    let mut best_state = beam_search(&all_cuts, &ctx, 1, 1, budget);
+    if (req.beam_width > 1 || req.candidates_per_step > 1) && !budget.expired() {
+        let beam_state = beam_search(
+            &all_cuts,
+            &ctx,
+            req.beam_width,
+            req.candidates_per_step,
+            budget,
+        );
+        if compare_states(&beam_state, &best_state, &ctx) != Ordering::Greater {
+            best_state = beam_state;
+        }
+    }
+    collect_remainders(&mut best_state, &req);
 
     let total_area_available: i64 = inventory
         .iter()
         .filter(|p| best_state.used_planks.iter().any(|u| u.resto_id == p.id))
-        .map(|p| p.width_mm * p.height_mm)
+        .map(|p| p.area_mm2())
         .sum();
 
     let unplaced_count = best_state.unplaced_cuts.len();
     let final_efficiency = best_state.calculate_efficiency(total_area_available);
+    if let Some(monitor) = &budget.monitor {
+        monitor.pass_done(
+            all_cuts.len() - unplaced_count,
+            all_cuts.len(),
+            final_efficiency,
+        );
+    }
 
     OptimizeCutsResponse {
         success: true,
@@ -653,5 +1565,299 @@ fn run_optimization_sync(
         total_cuts_placed: req.cuts.iter().map(|c| c.quantity as usize).sum::<usize>()
             - unplaced_count,
         total_cuts_requested: req.cuts.iter().map(|c| c.quantity as usize).sum(),
+        kerf_width_mm: req.kerf_width_mm,
+        sheets_to_buy: Vec::new(),
+        warnings: Vec::new(),
+        search_stats: SearchStats::default(),
+        cost: CostBreakdown::default(),
+        job_id: None,
+        reserved_until: None,
     }
 }
+
+fn collect_remainders(state: &mut OptimizationState, req: &OptimizeCutsRequest) {
+    let min_w = req.min_remainder_width_mm;
+    let min_h = req.min_remainder_height_mm;
+
+    for plank in &mut state.used_planks {
+        let Some(free_rects) = state.free_rects_by_plank.get(&plank.resto_id) else {
+            continue;
+        };
+        let mut kept: Vec<&FreeRect> = free_rects
+            .iter()
+            .filter(|r| {
+                (r.width >= min_w && r.height >= min_h) || (r.width >= min_h && r.height >= min_w)
+            })
+            .collect();
+
+        // MaxRects free rectangles overlap; keep the largest disjoint ones so
+        // every remainder can become its own leftover.
+        if req.strategy == PlacementStrategy::MaxRects {
+            kept.sort_by_key(|r| std::cmp::Reverse(r.width * r.height));
+            let mut disjoint: Vec<&FreeRect> = Vec::new();
+            for r in kept {
+                if !disjoint.iter().any(|d| d.intersects(r)) {
+                    disjoint.push(r);
+                }
+            }
+            kept = disjoint;
+        }
+
+        plank.remainders = kept
+            .into_iter()
+            .map(|r| Remainder {
+                x: r.x,
+                y: r.y,
+                width_mm: r.width,
+                height_mm: r.height,
+            })
+            .collect();
+    }
+}
+
+/// Remainders come back from the client, so each must lie on the usable part
+/// of the plank (inside its trim and, for a shaped leftover, off its notches),
+/// clear of the pieces and of the other remainders, and be large enough to
+/// keep. `leftover` is the inventory row; new sheets have none.
+fn check_remainders(
+    plank: &UsedPlank,
+    leftover: Option<&Leftover>,
+    min_w: i64,
+    min_h: i64,
+) -> Result<(), AppError> {
+    let trim = plank.trim_mm;
+    let overlaps = |a: &Remainder, x: i64, y: i64, w: i64, h: i64| {
+        a.x < x + w && x < a.x + a.width_mm && a.y < y + h && y < a.y + a.height_mm
+    };
+    for (i, rem) in plank.remainders.iter().enumerate() {
+        let at = format!(
+            "Remainder at ({}, {}) on plank {}",
+            rem.x, rem.y, plank.resto_id
+        );
+        let on_plank = match leftover {
+            Some(leftover) => leftover.covers(
+                &ShapeRect {
+                    x: rem.x,
+                    y: rem.y,
+                    width_mm: rem.width_mm,
+                    height_mm: rem.height_mm,
+                },
+                trim,
+            ),
+            None => {
+                rem.width_mm >= 1
+                    && rem.height_mm >= 1
+                    && rem.x >= trim
+                    && rem.y >= trim
+                    && rem.x + rem.width_mm <= plank.width_mm - trim
+                    && rem.y + rem.height_mm <= plank.height_mm - trim
+            }
+        };
+        if !on_plank {
+            return Err(AppError::Validation(format!(
+                "{} lies outside the usable part of the plank",
+                at
+            )));
+        }
+        let fits = (rem.width_mm >= min_w && rem.height_mm >= min_h)
+            || (rem.width_mm >= min_h && rem.height_mm >= min_w);
+        if !fits {
+            return Err(AppError::Validation(format!(
+                "{} is smaller than {}x{} mm",
+                at, min_w, min_h
+            )));
+        }
+        if plank
+            .cuts
+            .iter()
+            .any(|c| overlaps(rem, c.x, c.y, c.width, c.height))
+        {
+            return Err(AppError::Validation(format!("{} overlaps a piece", at)));
+        }
+        if plank.remainders[..i]
+            .iter()
+            .any(|o| overlaps(rem, o.x, o.y, o.width_mm, o.height_mm))
+        {
+            return Err(AppError::Validation(format!(
+                "{} overlaps another remainder",
+                at
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct CommitCutsRequest {
+    /// The plan to apply, or `cut_job_id` to apply a stored one.
+    #[serde(default)]
+    pub plan: Option<OptimizeCutsResponse>,
+    #[serde(default)]
+    pub cut_job_id: Option<i64>,
+    /// Smaller remainders are refused. Defaults to the stored job's limits,
+    /// or 100 for an inline plan.
+    #[serde(default)]
+    pub min_remainder_width_mm: Option<i64>,
+    #[serde(default)]
+    pub min_remainder_height_mm: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct CommitCutsResponse {
+    pub success: bool,
+    pub consumed_ids: Vec<i64>,
+    pub created_ids: Vec<i64>,
+}
+
+/// Applies a computed plan to the inventory: consumed leftovers are removed and
+/// the plan's remainders are stored as new leftovers, all in one transaction.
+/// Virtual sheets (negative ids) have no inventory row, only their offcuts are kept.
+/// Leftovers reserved by another plan are refused; the plan's own reservations
+/// are released.
+pub async fn commit_cuts(
+    State(state): State<AppState>,
+    Json(req): Json<CommitCutsRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let (plan, planned_with) = match (req.plan, req.cut_job_id) {
+        (Some(plan), None) => (plan, None),
+        (None, Some(id)) => {
+            let (request, plan) = fetch_plan(&state.db, id).await?;
+            (plan, Some(request))
+        }
+        _ => {
+            return Err(AppError::Validation(
+                "Send either plan or cut_job_id".to_string(),
+            ))
+        }
+    };
+    if plan.used_planks.is_empty() {
+        return Err(AppError::Validation("Plan has no used planks".to_string()));
+    }
+    let min_w = req
+        .min_remainder_width_mm
+        .or(planned_with.as_ref().map(|r| r.min_remainder_width_mm))
+        .unwrap_or_else(default_min_remainder);
+    let min_h = req
+        .min_remainder_height_mm
+        .or(planned_with.as_ref().map(|r| r.min_remainder_height_mm))
+        .unwrap_or_else(default_min_remainder);
+
+    for plank in plan.used_planks.iter().filter(|p| p.resto_id < 0) {
+        check_remainders(plank, None, min_w, min_h)?;
+    }
+
+    tracing::info!(planks = %plan.used_planks.len(), "Committing cut plan");
+
+    let created_at = OffsetDateTime::now_utc()
+        .format(&time::format_description::well_known::Rfc3339)
+        .unwrap_or_default();
+
+    let mut tx = state.db.begin().await.map_err(AppError::Database)?;
+    let mut consumed_ids = Vec::new();
+    let mut created_ids = Vec::new();
+
+    for plank in &plan.used_planks {
+        if plank.resto_id < 0 {
+            continue;
+        }
+
+        let current = sqlx::query_as::<_, Leftover>("SELECT * FROM leftovers WHERE id = ?")
+            .bind(plank.resto_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(AppError::Database)?
+            .ok_or_else(|| {
+                AppError::Conflict(format!("Resto {} no longer exists", plank.resto_id))
+            })?;
+
+        match &plank.resto_hash {
+            Some(hash) if *hash == resto_hash(&current) => {}
+            Some(_) => {
+                return Err(AppError::Conflict(format!(
+                    "Resto {} changed since the plan was computed",
+                    plank.resto_id
+                )))
+            }
+            None => {
+                return Err(AppError::Conflict(format!(
+                    "The plan for resto {} has no resto_hash; run the optimization again",
+                    plank.resto_id
+                )))
+            }
+        }
+        check_remainders(plank, Some(&current), min_w, min_h)?;
+
+        // A defect recorded after planning may sit under a piece.
+        let defects =
+            sqlx::query_as::<_, Defect>("SELECT * FROM leftover_defects WHERE leftover_id = ?")
+                .bind(plank.resto_id)
+                .fetch_all(&mut *tx)
+                .await
+                .map_err(AppError::Database)?;
+        if let Some(defect) = defects
+            .iter()
+            .find(|d| plank.cuts.iter().any(|c| d.overlaps(c)))
+        {
+            return Err(AppError::Conflict(format!(
+                "Resto {} has defect {} under a planned piece",
+                plank.resto_id, defect.id
+            )));
+        }
+
+        let held_by: Option<(i64,)> = sqlx::query_as("SELECT cut_job_id FROM leftover_reservations WHERE leftover_id = ?1 AND expires_at > datetime('now') AND (?2 IS NULL OR cut_job_id != ?2)")
+            .bind(plank.resto_id)
+            .bind(plan.job_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(AppError::Database)?;
+        if let Some((job_id,)) = held_by {
+            return Err(AppError::Conflict(format!(
+                "Resto {} is reserved by cut job {}",
+                plank.resto_id, job_id
+            )));
+        }
+
+        sqlx::query("DELETE FROM leftovers WHERE id = ?")
+            .bind(plank.resto_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(AppError::Database)?;
+        consumed_ids.push(plank.resto_id);
+    }
+
+    for plank in &plan.used_planks {
+        let notes = if plank.resto_id < 0 {
+            match plank.stock_sheet_id {
+                Some(sheet_id) => format!("Offcut from new sheet (catalog #{})", sheet_id),
+                None => "Offcut from new sheet".to_string(),
+            }
+        } else {
+            format!("Offcut from resto #{}", plank.resto_id)
+        };
+
+        for rem in &plank.remainders {
+            let result = sqlx::query("INSERT INTO leftovers (width_mm, height_mm, thickness_mm, material, notes, grain, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")
+                .bind(rem.width_mm).bind(rem.height_mm).bind(plank.thickness_mm).bind(&plank.material).bind(&notes).bind(plank.grain).bind(&created_at)
+                .execute(&mut *tx).await.map_err(AppError::Database)?;
+            created_ids.push(result.last_insert_rowid());
+        }
+    }
+
+    if let Some(job_id) = plan.job_id {
+        release_job(&mut tx, job_id).await?;
+    }
+
+    tx.commit().await.map_err(AppError::Database)?;
+
+    tracing::info!(
+        consumed = %consumed_ids.len(),
+        created = %created_ids.len(),
+        "Cut plan committed"
+    );
+
+    Ok(Json(CommitCutsResponse {
+        success: true,
+        consumed_ids,
+        created_ids,
+    }))
+}
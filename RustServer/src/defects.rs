@@ -0,0 +1,237 @@
+use crate::cutting_optimizer::PlacedCut;
+use crate::leftover_shape::{LeftoverShape, ShapeRect};
+use crate::{validate_notes, AppError, AppState, Leftover};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite, SqliteConnection};
+use std::collections::HashMap;
+
+const MAX_DEFECTS_PER_LEFTOVER: usize = 50;
+
+#[derive(Serialize, FromRow, Clone, Debug)]
+pub struct Defect {
+    pub id: i64,
+    pub leftover_id: i64,
+    pub x: i64,
+    pub y: i64,
+    pub width_mm: i64,
+    pub height_mm: i64,
+    pub description: Option<String>,
+    pub created_at: String,
+}
+
+impl Defect {
+    /// Whether a placed piece covers any part of the defect.
+    pub fn overlaps(&self, piece: &PlacedCut) -> bool {
+        piece.x < self.x + self.width_mm
+            && self.x < piece.x + piece.width
+            && piece.y < self.y + self.height_mm
+            && self.y < piece.y + piece.height
+    }
+
+    pub fn rect(&self) -> ShapeRect {
+        ShapeRect {
+            x: self.x,
+            y: self.y,
+            width_mm: self.width_mm,
+            height_mm: self.height_mm,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DefectInput {
+    pub x: i64,
+    pub y: i64,
+    pub width_mm: i64,
+    pub height_mm: i64,
+    pub description: Option<String>,
+}
+
+impl DefectInput {
+    fn rect(&self) -> ShapeRect {
+        ShapeRect {
+            x: self.x,
+            y: self.y,
+            width_mm: self.width_mm,
+            height_mm: self.height_mm,
+        }
+    }
+}
+
+/// Fills `defects` on each leftover with a single query.
+pub async fn attach_defects(db: &Pool<Sqlite>, leftovers: &mut [Leftover]) -> Result<(), AppError> {
+    if leftovers.is_empty() {
+        return Ok(());
+    }
+    let query = format!(
+        "SELECT * FROM leftover_defects WHERE leftover_id IN ({}) ORDER BY id",
+        leftovers.iter().map(|_| "?").collect::<Vec<_>>().join(", ")
+    );
+    let mut q = sqlx::query_as::<_, Defect>(&query);
+    for leftover in leftovers.iter() {
+        q = q.bind(leftover.id);
+    }
+    let rows = q.fetch_all(db).await.map_err(AppError::Database)?;
+
+    let mut by_leftover: HashMap<i64, Vec<Defect>> = HashMap::new();
+    for defect in rows {
+        by_leftover
+            .entry(defect.leftover_id)
+            .or_default()
+            .push(defect);
+    }
+    for leftover in leftovers.iter_mut() {
+        leftover.defects = by_leftover.remove(&leftover.id).unwrap_or_default();
+    }
+    Ok(())
+}
+
+/// Defects must lie on the leftover, clear of the notches of a shaped one,
+/// and have some area.
+pub fn validate_defects(
+    defects: &[DefectInput],
+    width_mm: i64,
+    height_mm: i64,
+    shape: Option<&LeftoverShape>,
+) -> Result<(), AppError> {
+    if defects.len() > MAX_DEFECTS_PER_LEFTOVER {
+        return Err(AppError::Validation(format!(
+            "At most {} defects per resto",
+            MAX_DEFECTS_PER_LEFTOVER
+        )));
+    }
+    for d in defects {
+        if d.width_mm < 1
+            || d.height_mm < 1
+            || d.x < 0
+            || d.y < 0
+            || d.x + d.width_mm > width_mm
+            || d.y + d.height_mm > height_mm
+            || shape.is_some_and(|s| !s.contains(&d.rect()))
+        {
+            return Err(AppError::Validation(format!(
+                "Defect {}x{} at {},{} must lie within the {}{}x{} resto",
+                d.width_mm,
+                d.height_mm,
+                d.x,
+                d.y,
+                if shape.is_some() { "shaped " } else { "" },
+                width_mm,
+                height_mm
+            )));
+        }
+        validate_notes(&d.description)?;
+    }
+    Ok(())
+}
+
+/// Replaces all defects of a leftover.
+pub async fn replace_defects(
+    conn: &mut SqliteConnection,
+    leftover_id: i64,
+    defects: &[DefectInput],
+) -> Result<(), AppError> {
+    sqlx::query("DELETE FROM leftover_defects WHERE leftover_id = ?")
+        .bind(leftover_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(AppError::Database)?;
+    for d in defects {
+        insert_defect(conn, leftover_id, d).await?;
+    }
+    Ok(())
+}
+
+async fn insert_defect(
+    conn: &mut SqliteConnection,
+    leftover_id: i64,
+    d: &DefectInput,
+) -> Result<i64, AppError> {
+    let result = sqlx::query("INSERT INTO leftover_defects (leftover_id, x, y, width_mm, height_mm, description) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(leftover_id).bind(d.x).bind(d.y).bind(d.width_mm).bind(d.height_mm).bind(&d.description)
+        .execute(&mut *conn).await.map_err(AppError::Database)?;
+    Ok(result.last_insert_rowid())
+}
+
+type LeftoverOutline = (i64, i64, Option<sqlx::types::Json<LeftoverShape>>);
+
+async fn fetch_leftover_outline(db: &Pool<Sqlite>, id: i64) -> Result<LeftoverOutline, AppError> {
+    sqlx::query_as("SELECT width_mm, height_mm, shape FROM leftovers WHERE id = ?")
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .map_err(AppError::Database)?
+        .ok_or_else(|| AppError::NotFound(format!("Resto {} not found", id)))
+}
+
+pub async fn list_defects(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    fetch_leftover_outline(&state.db, id).await?;
+    let defects = sqlx::query_as::<_, Defect>(
+        "SELECT * FROM leftover_defects WHERE leftover_id = ? ORDER BY id",
+    )
+    .bind(id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(AppError::Database)?;
+    Ok(Json(defects))
+}
+
+pub async fn add_defect(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<DefectInput>,
+) -> Result<impl IntoResponse, AppError> {
+    let (width_mm, height_mm, shape) = fetch_leftover_outline(&state.db, id).await?;
+    validate_defects(
+        std::slice::from_ref(&req),
+        width_mm,
+        height_mm,
+        shape.as_deref(),
+    )?;
+
+    let mut conn = state.db.acquire().await.map_err(AppError::Database)?;
+    let (count,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM leftover_defects WHERE leftover_id = ?")
+            .bind(id)
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(AppError::Database)?;
+    if count as usize >= MAX_DEFECTS_PER_LEFTOVER {
+        return Err(AppError::Validation(format!(
+            "At most {} defects per resto",
+            MAX_DEFECTS_PER_LEFTOVER
+        )));
+    }
+    let defect_id = insert_defect(&mut conn, id, &req).await?;
+
+    tracing::info!(resto = id, defect = defect_id, "Recorded defect");
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::json!({ "id": defect_id })),
+    ))
+}
+
+pub async fn delete_defect(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let rows = sqlx::query("DELETE FROM leftover_defects WHERE id = ?")
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(AppError::Database)?
+        .rows_affected();
+    if rows == 0 {
+        return Err(AppError::NotFound(format!("Defect {} not found", id)));
+    }
+    Ok(Json(serde_json::json!({ "success": true, "id": id })))
+}
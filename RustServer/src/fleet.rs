@@ -0,0 +1,314 @@
+use crate::load_balance::assess_balance;
+use crate::loading_plans::{store_plan, validate_plan_date};
+use crate::unloading::{plan_unloading, UnloadingStep};
+use crate::van_loader::pack;
+use crate::{
+    validate_cargo_weights, AppError, AppState, CargoItem, LoadingPlan, OptimizeResponse, Van,
+};
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Fleets up to this size try every combination of vans; larger ones are
+/// filled greedily.
+const MAX_ENUMERATED_VANS: usize = 12;
+/// Van combinations packed before falling back to the greedy fill.
+const MAX_SUBSET_TRIALS: usize = 200;
+const DEFAULT_TIME_LIMIT_MS: u64 = 10_000;
+const MAX_TIME_LIMIT_MS: u64 = 60_000;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FleetObjective {
+    /// Fewest vans, then the smallest total volume.
+    #[default]
+    FewestVans,
+    /// Smallest total van volume, then the fewest vans.
+    LowestVolume,
+}
+
+#[derive(Deserialize)]
+pub struct FleetRequest {
+    items: Vec<CargoItem>,
+    /// Vans to choose from; all active vans when omitted.
+    #[serde(default)]
+    van_ids: Option<Vec<i64>>,
+    #[serde(default)]
+    objective: FleetObjective,
+    /// Day the loads go out (`YYYY-MM-DD`); defaults to today.
+    #[serde(default)]
+    plan_date: Option<String>,
+    /// Time spent trying van combinations before filling greedily.
+    #[serde(default = "default_time_limit_ms")]
+    time_limit_ms: u64,
+}
+
+fn default_time_limit_ms() -> u64 {
+    DEFAULT_TIME_LIMIT_MS
+}
+
+#[derive(Serialize)]
+pub struct FleetVanPlan {
+    pub van_id: i64,
+    pub van_name: String,
+    pub plan: LoadingPlan,
+    /// Loading plan the van's load was stored as.
+    pub plan_id: Option<i64>,
+    pub unloading: Vec<UnloadingStep>,
+}
+
+#[derive(Serialize)]
+pub struct FleetResponse {
+    /// Every item found a van.
+    pub success: bool,
+    pub objective: FleetObjective,
+    pub vans: Vec<FleetVanPlan>,
+    pub unplaced_items: Vec<CargoItem>,
+    pub vans_used: usize,
+    pub total_van_volume: i64,
+    pub warnings: Vec<String>,
+}
+
+fn van_volume(van: &Van) -> i64 {
+    van.length_mm * van.width_mm * van.height_mm
+}
+
+fn item_volume(item: &CargoItem) -> i64 {
+    item.length_mm * item.width_mm * item.height_mm
+}
+
+/// Loads the vans in turn, largest first, each taking what the previous ones
+/// left over. Returns the non-empty loads and the items no van took.
+fn fill(vans: &[&Van], items: &[CargoItem]) -> (Vec<(Van, LoadingPlan)>, Vec<CargoItem>) {
+    let mut order: Vec<&Van> = vans.to_vec();
+    order.sort_by_key(|v| std::cmp::Reverse(van_volume(v)));
+
+    let mut loads = Vec::new();
+    let mut remaining = items.to_vec();
+    for van in order {
+        if remaining.is_empty() {
+            break;
+        }
+        let response = pack(van, &remaining);
+        if let Some(plan) = response.plan.filter(|p| !p.items.is_empty()) {
+            loads.push(((*van).clone(), plan));
+            remaining = response.unplaced_items;
+        }
+    }
+    (loads, remaining)
+}
+
+/// Adds the van that takes the most cargo volume of what is left, smaller
+/// vans winning ties, until nothing more fits.
+fn fill_greedy(vans: &[&Van], items: &[CargoItem]) -> (Vec<(Van, LoadingPlan)>, Vec<CargoItem>) {
+    let mut unused: Vec<&Van> = vans.to_vec();
+    let mut loads = Vec::new();
+    let mut remaining = items.to_vec();
+    while !remaining.is_empty() && !unused.is_empty() {
+        let best = unused
+            .iter()
+            .enumerate()
+            .map(|(idx, van)| (idx, pack(van, &remaining)))
+            .filter_map(|(idx, response)| {
+                let plan = response.plan?;
+                let volume: i64 = plan.items.iter().map(|p| item_volume(&p.item)).sum();
+                (volume > 0).then_some((idx, volume, plan, response.unplaced_items))
+            })
+            .max_by_key(|(idx, volume, _, _)| {
+                (*volume, std::cmp::Reverse(van_volume(unused[*idx])))
+            });
+        let Some((idx, _, plan, rest)) = best else {
+            break;
+        };
+        loads.push((unused.remove(idx).clone(), plan));
+        remaining = rest;
+    }
+    (loads, remaining)
+}
+
+/// Tries van combinations in objective order and keeps the first one that
+/// takes every item. Combinations too small by volume or weight are skipped
+/// without packing, and none is tried after `deadline`.
+fn fill_best_subset(
+    vans: &[&Van],
+    items: &[CargoItem],
+    objective: FleetObjective,
+    deadline: Instant,
+) -> Option<Vec<(Van, LoadingPlan)>> {
+    if vans.len() > MAX_ENUMERATED_VANS {
+        return None;
+    }
+    let cargo_volume: i64 = items.iter().map(item_volume).sum();
+    let cargo_weight: f64 = items.iter().map(|i| i.weight_kg).sum();
+
+    let mut subsets: Vec<(u32, usize, i64)> = (1u32..(1 << vans.len()))
+        .filter_map(|mask| {
+            let members: Vec<&Van> = (0..vans.len())
+                .filter(|i| mask & (1 << i) != 0)
+                .map(|i| vans[i])
+                .collect();
+            let volume: i64 = members.iter().map(|v| van_volume(v)).sum();
+            let capacity: Option<i64> = members.iter().map(|v| v.max_weight_kg).sum();
+            let heavy = capacity.is_some_and(|c| (c as f64) < cargo_weight);
+            (volume >= cargo_volume && !heavy).then_some((mask, members.len(), volume))
+        })
+        .collect();
+    subsets.sort_by_key(|&(mask, count, volume)| match objective {
+        FleetObjective::FewestVans => (count as i64, volume, mask),
+        FleetObjective::LowestVolume => (volume, count as i64, mask),
+    });
+
+    for (mask, _, _) in subsets.into_iter().take(MAX_SUBSET_TRIALS) {
+        if Instant::now() >= deadline {
+            return None;
+        }
+        let members: Vec<&Van> = (0..vans.len())
+            .filter(|i| mask & (1 << i) != 0)
+            .map(|i| vans[i])
+            .collect();
+        let (loads, rest) = fill(&members, items);
+        if rest.is_empty() {
+            return Some(loads);
+        }
+    }
+    None
+}
+
+async fn load_fleet(
+    db: &sqlx::Pool<sqlx::Sqlite>,
+    van_ids: &Option<Vec<i64>>,
+) -> Result<Vec<Van>, AppError> {
+    let vans = sqlx::query_as::<_, Van>("SELECT * FROM vans WHERE active = 1 ORDER BY id")
+        .fetch_all(db)
+        .await
+        .map_err(AppError::Database)?;
+    let Some(ids) = van_ids else {
+        if vans.is_empty() {
+            return Err(AppError::NotFound("No active vans".to_string()));
+        }
+        return Ok(vans);
+    };
+    if ids.is_empty() {
+        return Err(AppError::Validation(
+            "van_ids cannot be empty; omit it to use all active vans".to_string(),
+        ));
+    }
+    let missing: Vec<String> = ids
+        .iter()
+        .filter(|id| !vans.iter().any(|v| v.id == **id))
+        .map(|id| id.to_string())
+        .collect();
+    if !missing.is_empty() {
+        return Err(AppError::NotFound(format!(
+            "Van(s) {} not found or inactive",
+            missing.join(", ")
+        )));
+    }
+    Ok(vans.into_iter().filter(|v| ids.contains(&v.id)).collect())
+}
+
+/// Splits one cargo list across several vans and stores a loading plan per
+/// van used.
+pub async fn optimize_fleet(
+    State(state): State<AppState>,
+    Json(req): Json<FleetRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    validate_plan_date("plan_date", &req.plan_date)?;
+    validate_cargo_weights(&req.items)?;
+    if !(1..=MAX_TIME_LIMIT_MS).contains(&req.time_limit_ms) {
+        return Err(AppError::Validation(format!(
+            "time_limit_ms must be 1-{}",
+            MAX_TIME_LIMIT_MS
+        )));
+    }
+    let vans = load_fleet(&state.db, &req.van_ids).await?;
+    tracing::info!(vans = vans.len(), items = req.items.len(), objective = ?req.objective, "Optimizing fleet");
+
+    let objective = req.objective;
+    let items = req.items;
+    let deadline = Instant::now() + Duration::from_millis(req.time_limit_ms);
+    let (loads, unplaced, mut warnings) = tokio::task::spawn_blocking(move || {
+        let fleet: Vec<&Van> = vans.iter().collect();
+        let mut warnings = Vec::new();
+
+        // Items no van can take on its own would only defeat every combination.
+        let (placeable, mut unplaced): (Vec<CargoItem>, Vec<CargoItem>) =
+            items.into_iter().partition(|item| {
+                fleet.iter().any(|van| {
+                    pack(van, std::slice::from_ref(item))
+                        .plan
+                        .is_some_and(|p| !p.items.is_empty())
+                })
+            });
+        if !unplaced.is_empty() {
+            warnings.push(format!(
+                "{} item(s) fit in none of the vans",
+                unplaced.len()
+            ));
+        }
+
+        let (loads, rest) = match fill_best_subset(&fleet, &placeable, objective, deadline) {
+            Some(loads) => (loads, Vec::new()),
+            None => {
+                if Instant::now() >= deadline {
+                    warnings.push(
+                        "Time limit reached before the best van combination was found; vans were filled greedily"
+                            .to_string(),
+                    );
+                }
+                fill_greedy(&fleet, &placeable)
+            }
+        };
+        if !rest.is_empty() {
+            warnings.push(format!("{} item(s) did not fit in the fleet", rest.len()));
+        }
+        unplaced.extend(rest);
+        (loads, unplaced, warnings)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Fleet optimizer failed: {}", e)))?;
+
+    let total_van_volume = loads.iter().map(|(van, _)| van_volume(van)).sum();
+    let mut plans = Vec::with_capacity(loads.len());
+    // Either every van's plan is stored or none is.
+    let mut tx = state.db.begin().await.map_err(AppError::Database)?;
+    for (van, plan) in loads {
+        let mut response = OptimizeResponse {
+            success: true,
+            plan: Some(plan),
+            unplaced_items: Vec::new(),
+            warnings: Vec::new(),
+            plan_id: None,
+            unloading: Vec::new(),
+        };
+        plan_unloading(&mut response);
+        assess_balance(&van, &mut response);
+        warnings.extend(
+            response
+                .warnings
+                .iter()
+                .map(|w| format!("{}: {}", van.name, w)),
+        );
+        store_plan(&mut tx, van.id, req.plan_date.as_deref(), &mut response).await?;
+        if let Some(plan) = response.plan {
+            plans.push(FleetVanPlan {
+                van_id: van.id,
+                van_name: van.name,
+                plan,
+                plan_id: response.plan_id,
+                unloading: response.unloading,
+            });
+        }
+    }
+    tx.commit().await.map_err(AppError::Database)?;
+
+    Ok(Json(FleetResponse {
+        success: unplaced.is_empty(),
+        objective,
+        vans_used: plans.len(),
+        total_van_volume,
+        vans: plans,
+        unplaced_items: unplaced,
+        warnings,
+    }))
+}
@@ -0,0 +1,512 @@
+use crate::{AppError, Leftover, MAX_DIMENSION};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use std::collections::VecDeque;
+
+const MAX_SHAPE_VERTICES: usize = 32;
+const MAX_SHAPE_RECTS: usize = 16;
+
+/// Outline of a non-rectangular leftover, e.g. an L left after a notch was
+/// cut out. Either rectangles that together cover the shape or the corners of
+/// a rectilinear polygon in order. Stored normalized: disjoint rectangles with
+/// the bounding box at the origin.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum LeftoverShape {
+    Rects(Vec<ShapeRect>),
+    Polygon(Vec<[i64; 2]>),
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShapeRect {
+    pub x: i64,
+    pub y: i64,
+    pub width_mm: i64,
+    pub height_mm: i64,
+}
+
+impl ShapeRect {
+    pub fn area(&self) -> i64 {
+        self.width_mm * self.height_mm
+    }
+
+    fn overlap_area(&self, other: &ShapeRect) -> i64 {
+        let w = (self.x + self.width_mm).min(other.x + other.width_mm) - self.x.max(other.x);
+        let h = (self.y + self.height_mm).min(other.y + other.height_mm) - self.y.max(other.y);
+        w.max(0) * h.max(0)
+    }
+}
+
+/// A validated shape and its bounding box. `shape` is `None` when the
+/// outline turned out to be a plain rectangle.
+pub struct NormalizedShape {
+    pub width_mm: i64,
+    pub height_mm: i64,
+    pub shape: Option<LeftoverShape>,
+}
+
+impl LeftoverShape {
+    /// Checks that the shape is a single piece without holes or crossing
+    /// edges.
+    pub fn normalize(&self) -> Result<NormalizedShape, AppError> {
+        let in_range = |v: i64| (0..=MAX_DIMENSION).contains(&v);
+        let coords_ok = match self {
+            LeftoverShape::Rects(rects) => rects.iter().all(|r| {
+                in_range(r.x) && in_range(r.y) && in_range(r.width_mm) && in_range(r.height_mm)
+            }),
+            LeftoverShape::Polygon(points) => points.iter().flatten().all(|&v| in_range(v)),
+        };
+        if !coords_ok {
+            return Err(AppError::Validation(format!(
+                "Shape coordinates must be 0-{} mm",
+                MAX_DIMENSION
+            )));
+        }
+
+        let grid = match self {
+            LeftoverShape::Rects(rects) => Grid::from_rects(rects)?,
+            LeftoverShape::Polygon(points) => Grid::from_polygon(points)?,
+        };
+        grid.check_simple()?;
+
+        let rects = grid.disjoint_rects();
+        Ok(NormalizedShape {
+            width_mm: grid.xs.last().copied().unwrap_or(0),
+            height_mm: grid.ys.last().copied().unwrap_or(0),
+            shape: (rects.len() > 1).then_some(LeftoverShape::Rects(rects)),
+        })
+    }
+
+    /// Whether `rect` lies entirely on the material, clear of the notches.
+    pub fn contains(&self, rect: &ShapeRect) -> bool {
+        covered_by(&self.rects(), rect)
+    }
+
+    /// Disjoint rectangles of a stored shape.
+    pub fn rects(&self) -> Vec<ShapeRect> {
+        match self {
+            LeftoverShape::Rects(rects) => rects.clone(),
+            LeftoverShape::Polygon(_) => self
+                .normalize()
+                .ok()
+                .and_then(|n| n.shape)
+                .map(|shape| shape.rects())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Size and stored outline of a new or reshaped leftover. Without a shape
+/// the given size stands; with one, the size comes from its bounding box and
+/// must match it when also given.
+pub fn resolve_shape(
+    shape: Option<LeftoverShape>,
+    width_mm: i64,
+    height_mm: i64,
+) -> Result<(i64, i64, Option<Json<LeftoverShape>>), AppError> {
+    let Some(shape) = shape else {
+        return Ok((width_mm, height_mm, None));
+    };
+    let normalized = shape.normalize()?;
+    if (width_mm != 0 && width_mm != normalized.width_mm)
+        || (height_mm != 0 && height_mm != normalized.height_mm)
+    {
+        return Err(AppError::Validation(format!(
+            "width_mm/height_mm must match the shape's {}x{} bounding box",
+            normalized.width_mm, normalized.height_mm
+        )));
+    }
+    Ok((
+        normalized.width_mm,
+        normalized.height_mm,
+        normalized.shape.map(Json),
+    ))
+}
+
+impl Leftover {
+    fn outline(&self) -> Vec<ShapeRect> {
+        match &self.shape {
+            Some(shape) => shape.rects(),
+            None => vec![ShapeRect {
+                x: 0,
+                y: 0,
+                width_mm: self.width_mm,
+                height_mm: self.height_mm,
+            }],
+        }
+    }
+
+    /// Material area, less the notches of a shaped leftover.
+    pub fn area_mm2(&self) -> i64 {
+        match &self.shape {
+            Some(shape) => shape.rects().iter().map(ShapeRect::area).sum(),
+            None => self.width_mm * self.height_mm,
+        }
+    }
+
+    /// Whether a `width_mm` x `height_mm` rectangle fits inside the leftover
+    /// clear of defects, i.e. inside one of its maximal rectangles.
+    pub fn fits_rect(&self, width_mm: i64, height_mm: i64) -> bool {
+        if self.shape.is_none() && self.defects.is_empty() {
+            return self.width_mm >= width_mm && self.height_mm >= height_mm;
+        }
+        Grid::carved(&self.outline(), &self.defect_rects())
+            .maximal_rects()
+            .iter()
+            .any(|r| r.width_mm >= width_mm && r.height_mm >= height_mm)
+    }
+
+    /// Area pieces may be cut from: the outline less `trim` on every edge
+    /// and less the defects, as disjoint rectangles or, with `overlapping`,
+    /// as all maximal ones.
+    pub fn usable_rects(&self, trim: i64, overlapping: bool) -> Vec<ShapeRect> {
+        let trimmed = self.trimmed_outline(trim);
+        if trimmed.is_empty() || (self.shape.is_none() && self.defects.is_empty()) {
+            return trimmed;
+        }
+        let grid = Grid::carved(&trimmed, &self.defect_rects());
+        if overlapping {
+            grid.maximal_rects()
+        } else {
+            grid.disjoint_rects()
+        }
+    }
+
+    /// Whether `rect` lies inside the leftover less its trim. Defects are
+    /// checked separately.
+    pub fn covers(&self, rect: &ShapeRect, trim: i64) -> bool {
+        covered_by(&self.trimmed_outline(trim), rect)
+    }
+
+    fn defect_rects(&self) -> Vec<ShapeRect> {
+        self.defects.iter().map(|d| d.rect()).collect()
+    }
+
+    /// The outline less `trim`, as possibly overlapping rectangles.
+    fn trimmed_outline(&self, trim: i64) -> Vec<ShapeRect> {
+        let outline = self.outline();
+        let rects = if self.shape.is_none() {
+            outline
+        } else {
+            // A point is usable when the square of half-side `trim` around
+            // it is inside the shape, i.e. inside some maximal rectangle
+            // shrunk by trim.
+            Grid::covering(&outline).maximal_rects()
+        };
+        rects
+            .into_iter()
+            .map(|r| ShapeRect {
+                x: r.x + trim,
+                y: r.y + trim,
+                width_mm: r.width_mm - 2 * trim,
+                height_mm: r.height_mm - 2 * trim,
+            })
+            .filter(|r| r.width_mm > 0 && r.height_mm > 0)
+            .collect()
+    }
+}
+
+/// Whether the union of `rects` covers all of `rect`.
+fn covered_by(rects: &[ShapeRect], rect: &ShapeRect) -> bool {
+    let covered: i64 = Grid::covering(rects)
+        .disjoint_rects()
+        .iter()
+        .map(|r| r.overlap_area(rect))
+        .sum();
+    rect.area() > 0 && covered == rect.area()
+}
+
+/// The shape on a grid compressed to its own edge coordinates; cell (i, j)
+/// spans `xs[i]..xs[i + 1]` by `ys[j]..ys[j + 1]`.
+struct Grid {
+    xs: Vec<i64>,
+    ys: Vec<i64>,
+    filled: Vec<Vec<bool>>,
+}
+
+impl Grid {
+    fn new(mut xs: Vec<i64>, mut ys: Vec<i64>) -> Self {
+        xs.sort_unstable();
+        xs.dedup();
+        ys.sort_unstable();
+        ys.dedup();
+        let filled = vec![vec![false; xs.len().saturating_sub(1)]; ys.len().saturating_sub(1)];
+        Grid { xs, ys, filled }
+    }
+
+    fn covering(rects: &[ShapeRect]) -> Self {
+        Grid::carved(rects, &[])
+    }
+
+    /// Union of `rects` with `holes` cut out.
+    fn carved(rects: &[ShapeRect], holes: &[ShapeRect]) -> Self {
+        let all = rects.iter().chain(holes);
+        let mut grid = Grid::new(
+            all.clone().flat_map(|r| [r.x, r.x + r.width_mm]).collect(),
+            all.flat_map(|r| [r.y, r.y + r.height_mm]).collect(),
+        );
+        for r in rects {
+            grid.set(r, true);
+        }
+        for r in holes {
+            grid.set(r, false);
+        }
+        grid
+    }
+
+    fn from_rects(rects: &[ShapeRect]) -> Result<Self, AppError> {
+        if rects.is_empty() || rects.len() > MAX_SHAPE_RECTS {
+            return Err(AppError::Validation(format!(
+                "Shape must have 1-{} rectangles",
+                MAX_SHAPE_RECTS
+            )));
+        }
+        if rects.iter().any(|r| r.width_mm < 1 || r.height_mm < 1) {
+            return Err(AppError::Validation(
+                "Shape rectangles must be at least 1 mm wide and high".to_string(),
+            ));
+        }
+        for (i, a) in rects.iter().enumerate() {
+            if rects[i + 1..].iter().any(|b| a.overlap_area(b) > 0) {
+                return Err(AppError::Validation(
+                    "Shape rectangles must not overlap".to_string(),
+                ));
+            }
+        }
+
+        let min_x = rects.iter().map(|r| r.x).min().unwrap_or(0);
+        let min_y = rects.iter().map(|r| r.y).min().unwrap_or(0);
+        let moved: Vec<ShapeRect> = rects
+            .iter()
+            .map(|r| ShapeRect {
+                x: r.x - min_x,
+                y: r.y - min_y,
+                ..*r
+            })
+            .collect();
+        Ok(Grid::covering(&moved))
+    }
+
+    fn from_polygon(points: &[[i64; 2]]) -> Result<Self, AppError> {
+        let n = points.len();
+        if !(4..=MAX_SHAPE_VERTICES).contains(&n) {
+            return Err(AppError::Validation(format!(
+                "Shape polygon must have 4-{} corners",
+                MAX_SHAPE_VERTICES
+            )));
+        }
+
+        let min_x = points.iter().map(|p| p[0]).min().unwrap_or(0);
+        let min_y = points.iter().map(|p| p[1]).min().unwrap_or(0);
+        let points: Vec<[i64; 2]> = points
+            .iter()
+            .map(|p| [p[0] - min_x, p[1] - min_y])
+            .collect();
+        let edges: Vec<([i64; 2], [i64; 2])> =
+            (0..n).map(|i| (points[i], points[(i + 1) % n])).collect();
+        for (i, (a, b)) in edges.iter().enumerate() {
+            let (c, d) = edges[(i + 1) % n];
+            let horizontal = a[1] == b[1] && a[0] != b[0];
+            let vertical = a[0] == b[0] && a[1] != b[1];
+            let next_horizontal = c[1] == d[1];
+            if !(horizontal || vertical) || horizontal == next_horizontal {
+                return Err(AppError::Validation(
+                    "Shape polygon edges must alternate between horizontal and vertical"
+                        .to_string(),
+                ));
+            }
+        }
+        for i in 0..n {
+            for j in i + 2..n {
+                if i == 0 && j == n - 1 {
+                    continue;
+                }
+                if segments_touch(edges[i], edges[j]) {
+                    return Err(AppError::Validation(
+                        "Shape polygon must not cross or touch itself".to_string(),
+                    ));
+                }
+            }
+        }
+
+        let mut grid = Grid::new(
+            points.iter().map(|p| p[0]).collect(),
+            points.iter().map(|p| p[1]).collect(),
+        );
+        // Even-odd rule on cell centres, in doubled coordinates.
+        for j in 0..grid.filled.len() {
+            let cy = grid.ys[j] + grid.ys[j + 1];
+            for i in 0..grid.filled[j].len() {
+                let cx = grid.xs[i] + grid.xs[i + 1];
+                let crossings = edges
+                    .iter()
+                    .filter(|(a, b)| a[0] == b[0] && 2 * a[0] > cx)
+                    .filter(|(a, b)| {
+                        let (lo, hi) = (a[1].min(b[1]), a[1].max(b[1]));
+                        2 * lo < cy && cy < 2 * hi
+                    })
+                    .count();
+                grid.filled[j][i] = crossings % 2 == 1;
+            }
+        }
+        Ok(grid)
+    }
+
+    fn set(&mut self, r: &ShapeRect, value: bool) {
+        for j in 0..self.filled.len() {
+            for i in 0..self.filled[j].len() {
+                if self.xs[i] >= r.x
+                    && self.xs[i + 1] <= r.x + r.width_mm
+                    && self.ys[j] >= r.y
+                    && self.ys[j + 1] <= r.y + r.height_mm
+                {
+                    self.filled[j][i] = value;
+                }
+            }
+        }
+    }
+
+    /// One connected piece, and every empty cell reaches the outside.
+    fn check_simple(&self) -> Result<(), AppError> {
+        let rows = self.filled.len();
+        let cols = self.filled.first().map_or(0, Vec::len);
+        let flood = |want: bool, seeds: Vec<(usize, usize)>| {
+            let mut seen = vec![vec![false; cols]; rows];
+            let mut queue: VecDeque<(usize, usize)> = VecDeque::new();
+            for (j, i) in seeds {
+                if self.filled[j][i] == want && !seen[j][i] {
+                    seen[j][i] = true;
+                    queue.push_back((j, i));
+                }
+            }
+            while let Some((j, i)) = queue.pop_front() {
+                let neighbours = [
+                    (j.wrapping_sub(1), i),
+                    (j + 1, i),
+                    (j, i.wrapping_sub(1)),
+                    (j, i + 1),
+                ];
+                for (nj, ni) in neighbours {
+                    if nj < rows && ni < cols && !seen[nj][ni] && self.filled[nj][ni] == want {
+                        seen[nj][ni] = true;
+                        queue.push_back((nj, ni));
+                    }
+                }
+            }
+            seen
+        };
+
+        let cells: Vec<(usize, usize)> = (0..rows)
+            .flat_map(|j| (0..cols).map(move |i| (j, i)))
+            .collect();
+        let Some(&start) = cells.iter().find(|&&(j, i)| self.filled[j][i]) else {
+            return Err(AppError::Validation("Shape is empty".to_string()));
+        };
+        let piece = flood(true, vec![start]);
+        if cells
+            .iter()
+            .any(|&(j, i)| self.filled[j][i] && !piece[j][i])
+        {
+            return Err(AppError::Validation(
+                "Shape must be one piece; rectangles touching only at a corner do not count"
+                    .to_string(),
+            ));
+        }
+
+        let border: Vec<(usize, usize)> = cells
+            .iter()
+            .copied()
+            .filter(|&(j, i)| j == 0 || i == 0 || j + 1 == rows || i + 1 == cols)
+            .collect();
+        let outside = flood(false, border);
+        if cells
+            .iter()
+            .any(|&(j, i)| !self.filled[j][i] && !outside[j][i])
+        {
+            return Err(AppError::Validation(
+                "Shape must not have holes".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn cell_rect(&self, i0: usize, i1: usize, j0: usize, j1: usize) -> ShapeRect {
+        ShapeRect {
+            x: self.xs[i0],
+            y: self.ys[j0],
+            width_mm: self.xs[i1] - self.xs[i0],
+            height_mm: self.ys[j1] - self.ys[j0],
+        }
+    }
+
+    /// Splits the shape into disjoint rectangles, largest first, so the
+    /// biggest piece of material stays whole.
+    fn disjoint_rects(&self) -> Vec<ShapeRect> {
+        let mut rest = Grid {
+            xs: self.xs.clone(),
+            ys: self.ys.clone(),
+            filled: self.filled.clone(),
+        };
+        let mut done = Vec::new();
+        while let Some(largest) = rest.maximal_rects().into_iter().max_by_key(ShapeRect::area) {
+            rest.set(&largest, false);
+            done.push(largest);
+        }
+        done
+    }
+
+    /// Every rectangle of filled cells that cannot grow in any direction.
+    fn maximal_rects(&self) -> Vec<ShapeRect> {
+        let rows = self.filled.len();
+        let full = |j: usize, i0: usize, i1: usize| (i0..i1).all(|i| self.filled[j][i]);
+
+        let mut found = Vec::new();
+        for j0 in 0..rows {
+            let mut band = self.filled[j0].clone();
+            for j1 in j0..rows {
+                if j1 > j0 {
+                    for (i, cell) in band.iter_mut().enumerate() {
+                        *cell &= self.filled[j1][i];
+                    }
+                }
+                for (i0, i1) in runs(&band) {
+                    let grows_up = j0 > 0 && full(j0 - 1, i0, i1);
+                    let grows_down = j1 + 1 < rows && full(j1 + 1, i0, i1);
+                    if !grows_up && !grows_down {
+                        found.push(self.cell_rect(i0, i1, j0, j1 + 1));
+                    }
+                }
+                if band.iter().all(|c| !c) {
+                    break;
+                }
+            }
+        }
+        found
+    }
+}
+
+/// (start, end) column ranges of consecutive filled cells.
+fn runs(row: &[bool]) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut start = None;
+    for (i, &cell) in row.iter().chain(std::iter::once(&false)).enumerate() {
+        match (cell, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                runs.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    runs
+}
+
+/// Closed axis-aligned segments share at least one point.
+fn segments_touch(a: ([i64; 2], [i64; 2]), b: ([i64; 2], [i64; 2])) -> bool {
+    let (ax0, ax1) = (a.0[0].min(a.1[0]), a.0[0].max(a.1[0]));
+    let (ay0, ay1) = (a.0[1].min(a.1[1]), a.0[1].max(a.1[1]));
+    let (bx0, bx1) = (b.0[0].min(b.1[0]), b.0[0].max(b.1[0]));
+    let (by0, by1) = (b.0[1].min(b.1[1]), b.0[1].max(b.1[1]));
+    ax0 <= bx1 && bx0 <= ax1 && ay0 <= by1 && by0 <= ay1
+}
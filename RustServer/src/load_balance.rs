@@ -0,0 +1,188 @@
+use crate::van_loader::MIN_SUPPORT_PERCENT;
+use crate::{OptimizeResponse, PositionedItem, Van};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CentreOfGravity {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LoadBalance {
+    /// Combined centre of gravity of the load in the van frame; `None` when
+    /// the load weighs nothing.
+    pub centre_of_gravity: Option<CentreOfGravity>,
+    /// Share of the load carried by each axle; `None` without axle positions
+    /// on the van. A negative front share means the load lifts the front axle.
+    pub front_axle_kg: Option<f64>,
+    pub rear_axle_kg: Option<f64>,
+    /// Weight on the `z = 0` side and on the far side.
+    pub left_kg: f64,
+    pub right_kg: f64,
+    /// Difference between the two sides as a share of the load.
+    pub side_imbalance_percent: f64,
+}
+
+/// Footprint seen from above as (x, z, x_end, z_end).
+pub type Footprint = (i64, i64, i64, i64);
+
+/// Footprints and heights of the wheel wells, which carry items like any
+/// other surface.
+pub fn wheel_wells(van: &Van) -> Vec<(Footprint, i64)> {
+    let height = van.wheel_well_height_mm.unwrap_or(0);
+    let width = van.wheel_well_width_mm.unwrap_or(0);
+    let start = van.wheel_well_start_x_mm.unwrap_or(van.length_mm);
+    if height <= 0 || width <= 0 || start >= van.length_mm {
+        return Vec::new();
+    }
+    [0, van.width_mm - width]
+        .into_iter()
+        .map(|z| ((start, z, van.length_mm, z + width), height))
+        .collect()
+}
+
+pub fn footprint(p: &PositionedItem) -> Footprint {
+    (
+        p.position.x,
+        p.position.z,
+        p.position.x + p.placed_length,
+        p.position.z + p.placed_width,
+    )
+}
+
+/// Shared part of two footprints, if any.
+pub fn footprint_overlap(a: Footprint, b: Footprint) -> Option<Footprint> {
+    let overlap = (a.0.max(b.0), a.1.max(b.1), a.2.min(b.2), a.3.min(b.3));
+    (overlap.0 < overlap.2 && overlap.1 < overlap.3).then_some(overlap)
+}
+
+fn overlap_area(a: Footprint, b: Footprint) -> i64 {
+    footprint_overlap(a, b).map_or(0, |(x0, z0, x1, z1)| (x1 - x0) * (z1 - z0))
+}
+
+/// Share of the base of `items[idx]` resting directly on other items or the
+/// wheel wells; `None` for items on the floor.
+fn supported_percent(
+    items: &[PositionedItem],
+    wells: &[(Footprint, i64)],
+    idx: usize,
+) -> Option<f64> {
+    let item = &items[idx];
+    let y = item.position.y;
+    if y == 0 {
+        return None;
+    }
+    let base = footprint(item);
+    let mut area: i64 = items
+        .iter()
+        .enumerate()
+        .filter(|(i, p)| *i != idx && p.position.y + p.placed_height == y)
+        .map(|(_, p)| overlap_area(base, footprint(p)))
+        .sum();
+    area += wells
+        .iter()
+        .filter(|(_, height)| *height == y)
+        .map(|(well, _)| overlap_area(base, *well))
+        .sum::<i64>();
+    let base_area = item.placed_length * item.placed_width;
+    Some(if base_area > 0 {
+        (area as f64 / base_area as f64 * 100.0).min(100.0)
+    } else {
+        0.0
+    })
+}
+
+/// Adds the centre of gravity, axle loads, side balance and per-item support
+/// to the plan and warns where the van's limits are exceeded.
+pub fn assess_balance(van: &Van, response: &mut OptimizeResponse) {
+    let Some(plan) = response.plan.as_mut() else {
+        return;
+    };
+    let warnings = &mut response.warnings;
+
+    let wells = wheel_wells(van);
+    for idx in 0..plan.items.len() {
+        let supported = supported_percent(&plan.items, &wells, idx);
+        let item = &mut plan.items[idx];
+        if let Some(percent) = supported {
+            if percent < MIN_SUPPORT_PERCENT as f64 {
+                warnings.push(format!(
+                    "Item '{}' is only {:.0}% supported",
+                    item.item.description, percent
+                ));
+            }
+        }
+        item.supported_percent = supported;
+    }
+
+    let weight: f64 = plan.items.iter().map(|p| p.item.weight_kg).sum();
+    let centre_of_gravity = (weight > 0.0).then(|| {
+        let moment = |offset: fn(&PositionedItem) -> f64| {
+            plan.items
+                .iter()
+                .map(|p| p.item.weight_kg * offset(p))
+                .sum::<f64>()
+                / weight
+        };
+        CentreOfGravity {
+            x: moment(|p| p.position.x as f64 + p.placed_length as f64 / 2.0),
+            y: moment(|p| p.position.y as f64 + p.placed_height as f64 / 2.0),
+            z: moment(|p| p.position.z as f64 + p.placed_width as f64 / 2.0),
+        }
+    });
+
+    let (mut front_axle_kg, mut rear_axle_kg) = (None, None);
+    let (mut left_kg, mut right_kg) = (0.0, 0.0);
+    if let Some(cog) = &centre_of_gravity {
+        if let (Some(rear), Some(front)) = (van.rear_axle_x_mm, van.front_axle_x_mm) {
+            let front_kg = weight * (cog.x - rear as f64) / (front - rear) as f64;
+            front_axle_kg = Some(front_kg);
+            rear_axle_kg = Some(weight - front_kg);
+        }
+        right_kg = weight * (cog.z / van.width_mm as f64).clamp(0.0, 1.0);
+        left_kg = weight - right_kg;
+    }
+    let side_imbalance_percent = if weight > 0.0 {
+        (left_kg - right_kg).abs() / weight * 100.0
+    } else {
+        0.0
+    };
+
+    for (name, load, limit) in [
+        ("Front", front_axle_kg, van.max_front_axle_kg),
+        ("Rear", rear_axle_kg, van.max_rear_axle_kg),
+    ] {
+        if let (Some(load), Some(limit)) = (load, limit) {
+            if load > limit as f64 {
+                warnings.push(format!(
+                    "{} axle carries {:.0} kg of load, over its {} kg limit",
+                    name, load, limit
+                ));
+            }
+        }
+    }
+    if front_axle_kg.is_some_and(|kg| kg < 0.0) {
+        warnings.push("Load centre is behind the rear axle and lifts the front axle".to_string());
+    }
+    if let Some(max_imbalance) = van.max_side_imbalance_percent {
+        if side_imbalance_percent > max_imbalance {
+            warnings.push(format!(
+                "Load is {:.1}% heavier on the {} side, over the {}% limit",
+                side_imbalance_percent,
+                if left_kg > right_kg { "left" } else { "right" },
+                max_imbalance
+            ));
+        }
+    }
+
+    plan.balance = Some(LoadBalance {
+        centre_of_gravity,
+        front_axle_kg,
+        rear_axle_kg,
+        left_kg,
+        right_kg,
+        side_imbalance_percent,
+    });
+}
@@ -0,0 +1,183 @@
+use crate::{validate_notes, AppError, AppState, OptimizeResponse};
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite, SqliteConnection};
+use time::macros::format_description;
+
+const DEFAULT_LIST_LIMIT: i64 = 50;
+const MAX_LIST_LIMIT: i64 = 500;
+
+#[derive(Serialize, FromRow)]
+pub struct LoadingPlanSummary {
+    pub id: i64,
+    pub van_id: i64,
+    pub van_name: String,
+    pub plan_date: String,
+    /// Items placed in the van.
+    pub total_items: i64,
+    pub total_weight_kg: f64,
+    pub utilization_percent: Option<f64>,
+    pub notes: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Serialize)]
+pub struct LoadingPlanDetail {
+    #[serde(flatten)]
+    pub plan: LoadingPlanSummary,
+    pub result: OptimizeResponse,
+}
+
+#[derive(Deserialize)]
+pub struct LoadingPlanQuery {
+    van_id: Option<i64>,
+    /// Plans for this day (`YYYY-MM-DD`).
+    date: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateLoadingPlanNotesRequest {
+    notes: Option<String>,
+}
+
+const SUMMARY_SELECT: &str = "SELECT p.id, p.van_id, v.name AS van_name, p.plan_date, p.total_items, p.total_weight_kg, p.utilization_percent, p.notes, p.created_at FROM loading_plans p JOIN vans v ON v.id = p.van_id";
+
+/// Checks a `YYYY-MM-DD` date.
+pub fn validate_plan_date(field: &str, date: &Option<String>) -> Result<(), AppError> {
+    if let Some(d) = date {
+        time::Date::parse(d, format_description!("[year]-[month]-[day]"))
+            .map_err(|_| AppError::Validation(format!("{} must be a YYYY-MM-DD date", field)))?;
+    }
+    Ok(())
+}
+
+/// Stores a successful van load and fills in its `plan_id`. Without a
+/// `plan_date` the plan is filed under today.
+pub async fn store_plan(
+    conn: &mut SqliteConnection,
+    van_id: i64,
+    plan_date: Option<&str>,
+    response: &mut OptimizeResponse,
+) -> Result<(), AppError> {
+    let Some(plan) = response.plan.as_ref().filter(|_| response.success) else {
+        return Ok(());
+    };
+    let total_items = plan.items.len() as i64;
+    let total_weight = plan.total_weight;
+    let utilization = plan.utilization_percent;
+    let plan_json = serde_json::to_string(response)
+        .map_err(|e| AppError::Internal(format!("Failed to encode loading plan: {}", e)))?;
+
+    let id = sqlx::query("INSERT INTO loading_plans (van_id, plan_date, total_items, total_weight_kg, utilization_percent, plan_json) VALUES (?1, COALESCE(?2, CURRENT_DATE), ?3, ?4, ?5, ?6)")
+        .bind(van_id).bind(plan_date).bind(total_items).bind(total_weight).bind(utilization).bind(&plan_json)
+        .execute(conn).await.map_err(AppError::Database)?
+        .last_insert_rowid();
+
+    tracing::info!(id = id, van_id = van_id, "Stored loading plan");
+    response.plan_id = Some(id);
+    Ok(())
+}
+
+async fn fetch_summary(db: &Pool<Sqlite>, id: i64) -> Result<LoadingPlanSummary, AppError> {
+    sqlx::query_as::<_, LoadingPlanSummary>(&format!("{} WHERE p.id = ?", SUMMARY_SELECT))
+        .bind(id)
+        .fetch_optional(db)
+        .await
+        .map_err(AppError::Database)?
+        .ok_or_else(|| AppError::NotFound(format!("Loading plan {} not found", id)))
+}
+
+/// The stored response of a plan.
+pub async fn fetch_result(db: &Pool<Sqlite>, id: i64) -> Result<OptimizeResponse, AppError> {
+    let (plan_json,): (String,) =
+        sqlx::query_as("SELECT plan_json FROM loading_plans WHERE id = ?")
+            .bind(id)
+            .fetch_optional(db)
+            .await
+            .map_err(AppError::Database)?
+            .ok_or_else(|| AppError::NotFound(format!("Loading plan {} not found", id)))?;
+    let mut result: OptimizeResponse = serde_json::from_str(&plan_json)
+        .map_err(|e| AppError::Internal(format!("Loading plan {} is corrupt: {}", id, e)))?;
+    result.plan_id = Some(id);
+    Ok(result)
+}
+
+pub async fn list_loading_plans(
+    State(state): State<AppState>,
+    Query(params): Query<LoadingPlanQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    validate_plan_date("date", &params.date)?;
+    validate_plan_date("from", &params.from)?;
+    validate_plan_date("to", &params.to)?;
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+
+    let plans = sqlx::query_as::<_, LoadingPlanSummary>(&format!(
+        "{} WHERE (?1 IS NULL OR p.van_id = ?1) AND (?2 IS NULL OR p.plan_date = ?2) AND (?3 IS NULL OR p.plan_date >= ?3) AND (?4 IS NULL OR p.plan_date <= ?4) ORDER BY p.plan_date DESC, p.id DESC LIMIT ?5",
+        SUMMARY_SELECT
+    ))
+    .bind(params.van_id)
+    .bind(&params.date)
+    .bind(&params.from)
+    .bind(&params.to)
+    .bind(limit)
+    .fetch_all(&state.db)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(Json(plans))
+}
+
+pub async fn get_loading_plan(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let plan = fetch_summary(&state.db, id).await?;
+    let result = fetch_result(&state.db, id).await?;
+    Ok(Json(LoadingPlanDetail { plan, result }))
+}
+
+pub async fn update_loading_plan_notes(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<UpdateLoadingPlanNotesRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    validate_notes(&req.notes)?;
+    let rows = sqlx::query("UPDATE loading_plans SET notes = ? WHERE id = ?")
+        .bind(&req.notes)
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(AppError::Database)?
+        .rows_affected();
+    if rows == 0 {
+        return Err(AppError::NotFound(format!("Loading plan {} not found", id)));
+    }
+    Ok(Json(serde_json::json!({ "success": true, "id": id })))
+}
+
+pub async fn delete_loading_plan(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let rows = sqlx::query("DELETE FROM loading_plans WHERE id = ?")
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(AppError::Database)?
+        .rows_affected();
+    if rows == 0 {
+        return Err(AppError::NotFound(format!("Loading plan {} not found", id)));
+    }
+    tracing::info!(id = id, "Deleted loading plan");
+    Ok(Json(serde_json::json!({ "success": true, "id": id })))
+}
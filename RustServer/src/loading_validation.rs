@@ -0,0 +1,294 @@
+use crate::load_balance::{assess_balance, footprint, footprint_overlap, wheel_wells, Footprint};
+use crate::unloading::{plan_unloading, UnloadingStep};
+use crate::van_loader::{carries_load, MIN_SUPPORT_PERCENT};
+use crate::{
+    validate_cargo_weights, AppError, AppState, LoadingPlan, OptimizeResponse, PositionedItem, Van,
+};
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct ValidateLoadingRequest {
+    van_id: i64,
+    items: Vec<PositionedItem>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadingIssueKind {
+    /// Placed size is not a permitted orientation of the item.
+    Orientation,
+    OutOfBounds,
+    Overlap,
+    WheelWell,
+    Unsupported,
+    /// Something rests on an item that is not stackable.
+    NotStackable,
+    /// Something rests on a fragile item.
+    FragileLoaded,
+    OverWeight,
+}
+
+#[derive(Serialize)]
+pub struct LoadingIssue {
+    pub kind: LoadingIssueKind,
+    /// Index of the item at fault, if any.
+    pub item: Option<usize>,
+    /// The other item involved, for overlaps and loads resting on an item.
+    pub other: Option<usize>,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct ValidateLoadingResponse {
+    pub valid: bool,
+    pub issues: Vec<LoadingIssue>,
+    /// The items with their support and the load balance filled in.
+    pub plan: Option<LoadingPlan>,
+    pub unloading: Vec<UnloadingStep>,
+    /// Balance and unloading warnings; these do not make the plan invalid.
+    pub warnings: Vec<String>,
+}
+
+fn label(items: &[PositionedItem], idx: usize) -> String {
+    format!("Item {} ('{}')", idx, items[idx].item.description)
+}
+
+fn bounds(p: &PositionedItem) -> (i64, i64, i64, i64, i64, i64) {
+    (
+        p.position.x,
+        p.position.y,
+        p.position.z,
+        p.position.x + p.placed_length,
+        p.position.y + p.placed_height,
+        p.position.z + p.placed_width,
+    )
+}
+
+fn orientation_issue(p: &PositionedItem) -> Option<String> {
+    let item = &p.item;
+    let mut placed = [p.placed_length, p.placed_width, p.placed_height];
+    let mut size = [item.length_mm, item.width_mm, item.height_mm];
+    if placed.iter().any(|d| *d <= 0) {
+        return Some("has no placed size".to_string());
+    }
+    placed.sort_unstable();
+    size.sort_unstable();
+    if placed != size {
+        return Some(format!(
+            "is placed as {}x{}x{} mm but measures {}x{}x{} mm",
+            p.placed_length,
+            p.placed_width,
+            p.placed_height,
+            item.length_mm,
+            item.width_mm,
+            item.height_mm
+        ));
+    }
+    if !item.rotation_allowed && p.placed_height != item.height_mm {
+        return Some("may not be tipped over".to_string());
+    }
+    None
+}
+
+/// Checks a loading plan as placed, without moving anything: orientation,
+/// van bounds, overlaps, wheel wells, support, nothing resting on
+/// non-stackable or fragile items, and the van's weight limit.
+fn check_plan(van: &Van, items: &[PositionedItem]) -> Vec<LoadingIssue> {
+    let mut issues = Vec::new();
+    let mut issue = |kind, item: Option<usize>, other: Option<usize>, message: String| {
+        issues.push(LoadingIssue {
+            kind,
+            item,
+            other,
+            message,
+        })
+    };
+    let wells = wheel_wells(van);
+
+    for (i, p) in items.iter().enumerate() {
+        if let Some(msg) = orientation_issue(p) {
+            issue(
+                LoadingIssueKind::Orientation,
+                Some(i),
+                None,
+                format!("{} {}", label(items, i), msg),
+            );
+        }
+        let (x0, y0, z0, x1, y1, z1) = bounds(p);
+        if x0 < 0
+            || y0 < 0
+            || z0 < 0
+            || x1 > van.length_mm
+            || y1 > van.height_mm
+            || z1 > van.width_mm
+        {
+            issue(
+                LoadingIssueKind::OutOfBounds,
+                Some(i),
+                None,
+                format!("{} sticks out of the van", label(items, i)),
+            );
+        }
+        if wells
+            .iter()
+            .any(|(well, height)| y0 < *height && footprint_overlap(footprint(p), *well).is_some())
+        {
+            issue(
+                LoadingIssueKind::WheelWell,
+                Some(i),
+                None,
+                format!("{} sits in a wheel well", label(items, i)),
+            );
+        }
+        for (j, q) in items.iter().enumerate().take(i) {
+            let (a0, b0, c0, a1, b1, c1) = bounds(q);
+            if x0 < a1 && a0 < x1 && y0 < b1 && b0 < y1 && z0 < c1 && c0 < z1 {
+                issue(
+                    LoadingIssueKind::Overlap,
+                    Some(i),
+                    Some(j),
+                    format!("{} overlaps {}", label(items, i), label(items, j)),
+                );
+            }
+        }
+    }
+
+    for (i, p) in items.iter().enumerate() {
+        let y = p.position.y;
+        if y == 0 {
+            continue;
+        }
+        let base = footprint(p);
+        let mut contacts: Vec<Footprint> = Vec::new();
+        for (j, q) in items.iter().enumerate() {
+            if j == i || q.position.y + q.placed_height != y {
+                continue;
+            }
+            let Some(contact) = footprint_overlap(base, footprint(q)) else {
+                continue;
+            };
+            contacts.push(contact);
+            // Same rule as the packer.
+            if !carries_load(&q.item) {
+                let (kind, what) = if q.item.fragile {
+                    (LoadingIssueKind::FragileLoaded, "fragile")
+                } else {
+                    (LoadingIssueKind::NotStackable, "not stackable")
+                };
+                issue(
+                    kind,
+                    Some(i),
+                    Some(j),
+                    format!(
+                        "{} rests on {}, which is {}",
+                        label(items, i),
+                        label(items, j),
+                        what
+                    ),
+                );
+            }
+        }
+        contacts.extend(
+            wells
+                .iter()
+                .filter(|(_, height)| *height == y)
+                .filter_map(|(well, _)| footprint_overlap(base, *well)),
+        );
+
+        let area: i64 = contacts
+            .iter()
+            .map(|(x0, z0, x1, z1)| (x1 - x0) * (z1 - z0))
+            .sum();
+        let base_area = p.placed_length * p.placed_width;
+        // The centre of the base has to lie over the supported region.
+        let centred = contacts
+            .iter()
+            .copied()
+            .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)))
+            .is_some_and(|(x0, z0, x1, z1)| {
+                let (cx, cz) = (2 * base.0 + p.placed_length, 2 * base.1 + p.placed_width);
+                cx >= 2 * x0 && cx <= 2 * x1 && cz >= 2 * z0 && cz <= 2 * z1
+            });
+        if area * 100 < base_area * MIN_SUPPORT_PERCENT || !centred {
+            issue(
+                LoadingIssueKind::Unsupported,
+                Some(i),
+                None,
+                format!(
+                    "{} needs at least {}% of its base supported with its centre over the support",
+                    label(items, i),
+                    MIN_SUPPORT_PERCENT
+                ),
+            );
+        }
+    }
+
+    let total_weight: f64 = items.iter().map(|p| p.item.weight_kg).sum();
+    if let Some(max) = van.max_weight_kg {
+        if total_weight > max as f64 {
+            issue(
+                LoadingIssueKind::OverWeight,
+                None,
+                None,
+                format!(
+                    "Load weighs {:.1} kg, over the van's {} kg limit",
+                    total_weight, max
+                ),
+            );
+        }
+    }
+    issues
+}
+
+/// Checks a plan rearranged by hand against the van without re-optimizing.
+pub async fn validate_loading(
+    State(state): State<AppState>,
+    Json(req): Json<ValidateLoadingRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    validate_cargo_weights(req.items.iter().map(|p| &p.item))?;
+    let van = sqlx::query_as::<_, Van>("SELECT * FROM vans WHERE id = ? AND active = 1")
+        .bind(req.van_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(AppError::Database)?
+        .ok_or_else(|| AppError::NotFound(format!("Van {} not found or inactive", req.van_id)))?;
+
+    let issues = check_plan(&van, &req.items);
+
+    let van_volume = van.length_mm * van.width_mm * van.height_mm;
+    let used_volume: i64 = req
+        .items
+        .iter()
+        .map(|p| p.placed_length * p.placed_width * p.placed_height)
+        .sum();
+    let mut response = OptimizeResponse {
+        success: issues.is_empty(),
+        plan: Some(LoadingPlan {
+            total_weight: req.items.iter().map(|p| p.item.weight_kg).sum(),
+            items: req.items,
+            utilization_percent: if van_volume > 0 {
+                used_volume as f64 / van_volume as f64 * 100.0
+            } else {
+                0.0
+            },
+            van_volume,
+            used_volume,
+            balance: None,
+        }),
+        unplaced_items: Vec::new(),
+        warnings: Vec::new(),
+        plan_id: None,
+        unloading: Vec::new(),
+    };
+    plan_unloading(&mut response);
+    assess_balance(&van, &mut response);
+
+    Ok(Json(ValidateLoadingResponse {
+        valid: issues.is_empty(),
+        issues,
+        plan: response.plan,
+        unloading: response.unloading,
+        warnings: response.warnings,
+    }))
+}
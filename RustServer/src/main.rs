@@ -7,11 +7,10 @@ use axum::{
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
-use sqlx::{sqlite::SqlitePoolOptions, FromRow, Pool, Sqlite};
+use sqlx::{sqlite::SqlitePoolOptions, types::Json as SqlJson, FromRow, Pool, Sqlite};
 use std::fs;
-use std::io::Write;
 use std::net::SocketAddr;
-use std::process::Command;
+use std::sync::Arc;
 use std::time::Duration;
 use time::OffsetDateTime;
 use tower::ServiceBuilder;
@@ -21,8 +20,53 @@ use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
 use tower_http::LatencyUnit;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod background_jobs;
+use background_jobs::{
+    cancel_job, get_job, job_events, list_jobs, start_cut_job, start_loading_job, JobRegistry,
+};
+mod cut_export;
+use cut_export::export_cuts;
+mod cut_import;
+use cut_import::import_cuts;
+mod cut_jobs;
+use cut_jobs::{diff_cut_jobs, get_cut_job, list_cut_jobs, rerun_cut_job, update_cut_job_status};
+mod cut_labels;
+use cut_labels::{cut_labels, print_cut_labels};
+mod cut_sequence;
+use cut_sequence::cut_sequence;
+mod cut_verify;
+use cut_verify::verify_cuts;
 mod cutting_optimizer;
-use cutting_optimizer::optimize_cuts;
+use cutting_optimizer::{commit_cuts, optimize_cuts, Grain};
+mod defects;
+use defects::{
+    add_defect, attach_defects, delete_defect, list_defects, replace_defects, validate_defects,
+    Defect, DefectInput,
+};
+mod fleet;
+use fleet::optimize_fleet;
+mod leftover_shape;
+mod load_balance;
+use load_balance::{assess_balance, LoadBalance};
+mod loading_plans;
+mod loading_validation;
+use leftover_shape::{resolve_shape, LeftoverShape};
+use loading_plans::{
+    delete_loading_plan, get_loading_plan, list_loading_plans, store_plan,
+    update_loading_plan_notes, validate_plan_date,
+};
+use loading_validation::validate_loading;
+mod reservations;
+use reservations::{exclude_reserved, list_reservations};
+#[cfg(feature = "python-sidecar")]
+mod sidecar;
+mod stock_sheets;
+use stock_sheets::{
+    add_stock_sheet, delete_stock_sheet, get_stock_sheet, list_stock_sheets, update_stock_sheet,
+};
+mod unloading;
+use unloading::{plan_unloading, UnloadingStep};
+mod van_loader;
 
 const MIN_DIMENSION: i64 = 1;
 const MAX_DIMENSION: i64 = 10000;
@@ -30,12 +74,16 @@ const MAX_THICKNESS: i64 = 1000;
 const MIN_MATERIAL_LEN: usize = 1;
 const MAX_MATERIAL_LEN: usize = 64;
 const MAX_NOTES_LEN: usize = 256;
+const MAX_TRIM_MM: i64 = 100;
 const SCHEMA_VERSION: i64 = 1;
+/// Area of a leftover row; shaped leftovers count only their rectangles.
+const LEFTOVER_AREA_SQL: &str = "COALESCE((SELECT SUM(json_extract(r.value, '$.width_mm') * json_extract(r.value, '$.height_mm')) FROM json_each(leftovers.shape, '$.rects') r), width_mm * height_mm)";
 
 #[derive(Clone)]
 struct AppState {
     db: Pool<Sqlite>,
     auth_token: Option<String>,
+    jobs: Arc<JobRegistry>,
 }
 
 #[derive(Serialize)]
@@ -48,8 +96,25 @@ struct ErrorResponse {
 enum AppError {
     Validation(String),
     NotFound(String),
+    Conflict(String),
     Database(sqlx::Error),
     Internal(String),
+    #[cfg(feature = "python-sidecar")]
+    Solver(sidecar::SolverError),
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::Validation(msg)
+            | AppError::NotFound(msg)
+            | AppError::Conflict(msg)
+            | AppError::Internal(msg) => write!(f, "{}", msg),
+            AppError::Database(err) => write!(f, "Database error: {}", err),
+            #[cfg(feature = "python-sidecar")]
+            AppError::Solver(err) => write!(f, "Solver error ({:?}): {}", err.kind, err.message),
+        }
+    }
 }
 
 impl IntoResponse for AppError {
@@ -67,6 +132,10 @@ impl IntoResponse for AppError {
                 tracing::debug!(error = %msg, "Resource not found");
                 (StatusCode::NOT_FOUND, "Not found".to_string(), Some(msg))
             }
+            AppError::Conflict(msg) => {
+                tracing::warn!(error = %msg, "Conflict");
+                (StatusCode::CONFLICT, "Conflict".to_string(), Some(msg))
+            }
             AppError::Database(err) => {
                 tracing::error!("Database error: {}", err);
                 (
@@ -83,6 +152,8 @@ impl IntoResponse for AppError {
                     Some(msg),
                 )
             }
+            #[cfg(feature = "python-sidecar")]
+            AppError::Solver(err) => return err.into_response(),
         };
 
         let body = Json(ErrorResponse { error, details });
@@ -91,19 +162,19 @@ impl IntoResponse for AppError {
 }
 
 fn validate_dimensions(width: i64, height: i64, thickness: i64) -> Result<(), AppError> {
-    if width < MIN_DIMENSION || width > MAX_DIMENSION {
+    if !(MIN_DIMENSION..=MAX_DIMENSION).contains(&width) {
         return Err(AppError::Validation(format!(
             "Width must be {}-{} mm",
             MIN_DIMENSION, MAX_DIMENSION
         )));
     }
-    if height < MIN_DIMENSION || height > MAX_DIMENSION {
+    if !(MIN_DIMENSION..=MAX_DIMENSION).contains(&height) {
         return Err(AppError::Validation(format!(
             "Height must be {}-{} mm",
             MIN_DIMENSION, MAX_DIMENSION
         )));
     }
-    if thickness < MIN_DIMENSION || thickness > MAX_THICKNESS {
+    if !(MIN_DIMENSION..=MAX_THICKNESS).contains(&thickness) {
         return Err(AppError::Validation(format!(
             "Thickness must be {}-{} mm",
             MIN_DIMENSION, MAX_THICKNESS
@@ -113,19 +184,19 @@ fn validate_dimensions(width: i64, height: i64, thickness: i64) -> Result<(), Ap
 }
 
 fn validate_van_dimensions(length: i64, width: i64, height: i64) -> Result<(), AppError> {
-    if length < MIN_DIMENSION || length > MAX_DIMENSION {
+    if !(MIN_DIMENSION..=MAX_DIMENSION).contains(&length) {
         return Err(AppError::Validation(format!(
             "Length must be {}-{} mm",
             MIN_DIMENSION, MAX_DIMENSION
         )));
     }
-    if width < MIN_DIMENSION || width > MAX_DIMENSION {
+    if !(MIN_DIMENSION..=MAX_DIMENSION).contains(&width) {
         return Err(AppError::Validation(format!(
             "Width must be {}-{} mm",
             MIN_DIMENSION, MAX_DIMENSION
         )));
     }
-    if height < MIN_DIMENSION || height > MAX_DIMENSION {
+    if !(MIN_DIMENSION..=MAX_DIMENSION).contains(&height) {
         return Err(AppError::Validation(format!(
             "Height must be {}-{} mm",
             MIN_DIMENSION, MAX_DIMENSION
@@ -134,9 +205,59 @@ fn validate_van_dimensions(length: i64, width: i64, height: i64) -> Result<(), A
     Ok(())
 }
 
+/// Checks axle positions (front must be ahead of rear) and load limits.
+fn validate_van_axles(
+    rear_x: Option<i64>,
+    front_x: Option<i64>,
+    max_axle_kg: [Option<i64>; 2],
+    max_side_imbalance_percent: Option<f64>,
+) -> Result<(), AppError> {
+    for x in [rear_x, front_x].into_iter().flatten() {
+        if !(0..=MAX_DIMENSION).contains(&x) {
+            return Err(AppError::Validation(format!(
+                "Axle positions must be 0-{} mm",
+                MAX_DIMENSION
+            )));
+        }
+    }
+    if let (Some(rear), Some(front)) = (rear_x, front_x) {
+        if front <= rear {
+            return Err(AppError::Validation(
+                "front_axle_x_mm must be greater than rear_axle_x_mm".into(),
+            ));
+        }
+    }
+    if max_axle_kg.into_iter().flatten().any(|kg| kg <= 0) {
+        return Err(AppError::Validation("Axle limits must be positive".into()));
+    }
+    if let Some(p) = max_side_imbalance_percent {
+        if !(p > 0.0 && p <= 100.0) {
+            return Err(AppError::Validation(
+                "max_side_imbalance_percent must be 0-100".into(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Rejects cargo with a negative weight.
+fn validate_cargo_weights<'a>(
+    items: impl IntoIterator<Item = &'a CargoItem>,
+) -> Result<(), AppError> {
+    for (idx, item) in items.into_iter().enumerate() {
+        if !item.weight_kg.is_finite() || item.weight_kg < 0.0 {
+            return Err(AppError::Validation(format!(
+                "items[{}].weight_kg must be 0 or more",
+                idx
+            )));
+        }
+    }
+    Ok(())
+}
+
 fn validate_material(material: &str) -> Result<(), AppError> {
     let len = material.len();
-    if len < MIN_MATERIAL_LEN || len > MAX_MATERIAL_LEN {
+    if !(MIN_MATERIAL_LEN..=MAX_MATERIAL_LEN).contains(&len) {
         return Err(AppError::Validation(format!(
             "Material must be {}-{} chars",
             MIN_MATERIAL_LEN, MAX_MATERIAL_LEN
@@ -148,6 +269,18 @@ fn validate_material(material: &str) -> Result<(), AppError> {
     Ok(())
 }
 
+fn validate_trim(trim_mm: Option<i64>) -> Result<(), AppError> {
+    if let Some(t) = trim_mm {
+        if !(0..=MAX_TRIM_MM).contains(&t) {
+            return Err(AppError::Validation(format!(
+                "Trim must be 0-{} mm",
+                MAX_TRIM_MM
+            )));
+        }
+    }
+    Ok(())
+}
+
 fn validate_notes(notes: &Option<String>) -> Result<(), AppError> {
     if let Some(n) = notes {
         if n.len() > MAX_NOTES_LEN {
@@ -193,16 +326,32 @@ struct Leftover {
     thickness_mm: i64,
     material: String,
     notes: Option<String>,
+    grain: Option<Grain>,
+    trim_mm: Option<i64>,
     created_at: String,
+    /// Outline when the leftover is not a plain rectangle; `width_mm` and
+    /// `height_mm` are then its bounding box.
+    shape: Option<SqlJson<LeftoverShape>>,
+    /// Loaded separately, see `attach_defects`.
+    #[sqlx(skip)]
+    defects: Vec<Defect>,
 }
 
 #[derive(Deserialize)]
 struct AddLeftoverRequest {
+    /// May be left out when `shape` is given.
+    #[serde(default)]
     width_mm: i64,
+    #[serde(default)]
     height_mm: i64,
     thickness_mm: i64,
     material: String,
     notes: Option<String>,
+    grain: Option<Grain>,
+    trim_mm: Option<i64>,
+    shape: Option<LeftoverShape>,
+    #[serde(default)]
+    defects: Vec<DefectInput>,
 }
 
 #[derive(Deserialize)]
@@ -212,6 +361,12 @@ struct UpdateLeftoverRequest {
     thickness_mm: Option<i64>,
     material: Option<String>,
     notes: Option<String>,
+    grain: Option<Grain>,
+    trim_mm: Option<i64>,
+    /// Replaces the outline; a plain rectangle clears it.
+    shape: Option<LeftoverShape>,
+    /// Replaces all defects; an empty list clears them.
+    defects: Option<Vec<DefectInput>>,
 }
 
 #[derive(Serialize)]
@@ -230,6 +385,12 @@ struct Van {
     wheel_well_height_mm: Option<i64>,
     wheel_well_width_mm: Option<i64>,
     wheel_well_start_x_mm: Option<i64>,
+    /// Axle positions, measured from the back door like the load.
+    rear_axle_x_mm: Option<i64>,
+    front_axle_x_mm: Option<i64>,
+    max_rear_axle_kg: Option<i64>,
+    max_front_axle_kg: Option<i64>,
+    max_side_imbalance_percent: Option<f64>,
     active: bool,
     notes: Option<String>,
     created_at: String,
@@ -245,6 +406,11 @@ struct AddVanRequest {
     wheel_well_height_mm: Option<i64>,
     wheel_well_width_mm: Option<i64>,
     wheel_well_start_x_mm: Option<i64>,
+    rear_axle_x_mm: Option<i64>,
+    front_axle_x_mm: Option<i64>,
+    max_rear_axle_kg: Option<i64>,
+    max_front_axle_kg: Option<i64>,
+    max_side_imbalance_percent: Option<f64>,
     notes: Option<String>,
 }
 
@@ -258,6 +424,11 @@ struct UpdateVanRequest {
     wheel_well_height_mm: Option<i64>,
     wheel_well_width_mm: Option<i64>,
     wheel_well_start_x_mm: Option<i64>,
+    rear_axle_x_mm: Option<i64>,
+    front_axle_x_mm: Option<i64>,
+    max_rear_axle_kg: Option<i64>,
+    max_front_axle_kg: Option<i64>,
+    max_side_imbalance_percent: Option<f64>,
     notes: Option<String>,
     active: Option<bool>,
 }
@@ -278,6 +449,9 @@ struct CargoItem {
     stackable: bool,
     #[serde(default)]
     color: Option<String>,
+    /// Delivery stop the item comes off at; stop 1 sits nearest the rear doors.
+    #[serde(default)]
+    stop_sequence: Option<u32>,
 }
 
 fn default_weight() -> f64 {
@@ -291,6 +465,9 @@ fn default_true() -> bool {
 struct OptimizeRequest {
     van_id: i64,
     items: Vec<CargoItem>,
+    /// Day the load goes out (`YYYY-MM-DD`); defaults to today.
+    #[serde(default)]
+    plan_date: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -302,6 +479,12 @@ struct PositionedItem {
     placed_length: i64,
     placed_width: i64,
     placed_height: i64,
+    /// Stands in the way of an item for an earlier stop.
+    #[serde(default)]
+    blocks_access: bool,
+    /// Share of the base resting on something, for stacked items.
+    #[serde(default)]
+    supported_percent: Option<f64>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -325,6 +508,12 @@ struct OptimizeResponse {
     #[serde(default)]
     unplaced_items: Vec<CargoItem>,
     warnings: Vec<String>,
+    /// Loading plan the result was stored as.
+    #[serde(default)]
+    plan_id: Option<i64>,
+    /// Unloading order per delivery stop, when items have a `stop_sequence`.
+    #[serde(default)]
+    unloading: Vec<UnloadingStep>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -334,6 +523,8 @@ struct LoadingPlan {
     utilization_percent: f64,
     van_volume: i64,
     used_volume: i64,
+    #[serde(default)]
+    balance: Option<LoadBalance>,
 }
 
 #[derive(Serialize, FromRow)]
@@ -363,6 +554,8 @@ struct SearchQuery {
     height_mm: i64,
     thickness_mm: i64,
     material: String,
+    #[serde(default)]
+    include_reserved: bool,
 }
 
 #[tokio::main]
@@ -416,8 +609,21 @@ async fn main() -> anyhow::Result<()> {
         .ok();
     }
 
+    #[cfg(feature = "python-sidecar")]
+    sidecar::sidecar();
+    #[cfg(not(feature = "python-sidecar"))]
+    if std::env::var("VAN_LOADER").is_ok_and(|v| v.eq_ignore_ascii_case("python")) {
+        tracing::warn!(
+            "VAN_LOADER=python needs the python-sidecar feature; using the native loader"
+        );
+    }
+
     let auth_token = std::env::var("AUTH_TOKEN").ok();
-    let state = AppState { db, auth_token };
+    let state = AppState {
+        db,
+        auth_token,
+        jobs: Arc::new(JobRegistry::default()),
+    };
 
     let app = Router::new()
         .route("/health", get(health_check))
@@ -429,13 +635,52 @@ async fn main() -> anyhow::Result<()> {
         .route("/search", get(search_resto))
         .route("/list", get(list_restos))
         .route("/stats", get(get_stats))
+        .route("/restos/:id/defects", get(list_defects).post(add_defect))
+        .route("/defects/:id", delete(delete_defect))
         .route("/vans", get(list_vans).post(add_van))
         .route(
             "/vans/:id",
             get(get_van).post(update_van).delete(delete_van),
         )
+        .route(
+            "/stock_sheets",
+            get(list_stock_sheets).post(add_stock_sheet),
+        )
+        .route(
+            "/stock_sheets/:id",
+            get(get_stock_sheet)
+                .post(update_stock_sheet)
+                .delete(delete_stock_sheet),
+        )
         .route("/optimize", post(optimize_loading))
+        .route("/optimize_fleet", post(optimize_fleet))
+        .route("/validate_loading", post(validate_loading))
+        .route("/loading_plans", get(list_loading_plans))
+        .route(
+            "/loading_plans/:id",
+            get(get_loading_plan).delete(delete_loading_plan),
+        )
+        .route("/loading_plans/:id/notes", post(update_loading_plan_notes))
         .route("/optimize_cuts", post(optimize_cuts))
+        .route("/import_cuts", post(import_cuts))
+        .route("/jobs", get(list_jobs))
+        .route("/jobs/optimize", post(start_loading_job))
+        .route("/jobs/optimize_cuts", post(start_cut_job))
+        .route("/jobs/:id", get(get_job))
+        .route("/jobs/:id/events", get(job_events))
+        .route("/jobs/:id/cancel", post(cancel_job))
+        .route("/commit_cuts", post(commit_cuts))
+        .route("/verify_cuts", post(verify_cuts))
+        .route("/cut_jobs", get(list_cut_jobs))
+        .route("/reservations", get(list_reservations))
+        .route("/cut_jobs/:id", get(get_cut_job))
+        .route("/cut_jobs/:id/status", post(update_cut_job_status))
+        .route("/cut_jobs/:id/rerun", post(rerun_cut_job))
+        .route("/cut_jobs/:id/diff/:other_id", get(diff_cut_jobs))
+        .route("/export_cuts", post(export_cuts))
+        .route("/cut_labels", post(cut_labels))
+        .route("/cut_labels/print", post(print_cut_labels))
+        .route("/cut_sequence", post(cut_sequence))
         .route_layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
@@ -472,17 +717,24 @@ async fn add_resto(
     State(state): State<AppState>,
     Json(payload): Json<AddLeftoverRequest>,
 ) -> Result<impl IntoResponse, AppError> {
-    validate_dimensions(payload.width_mm, payload.height_mm, payload.thickness_mm)?;
+    let (width_mm, height_mm, shape) =
+        resolve_shape(payload.shape, payload.width_mm, payload.height_mm)?;
+    validate_dimensions(width_mm, height_mm, payload.thickness_mm)?;
     validate_material(&payload.material)?;
     validate_notes(&payload.notes)?;
+    validate_trim(payload.trim_mm)?;
+    validate_defects(&payload.defects, width_mm, height_mm, shape.as_deref())?;
 
     let created_at = OffsetDateTime::now_utc()
         .format(&time::format_description::well_known::Rfc3339)
         .unwrap_or_default();
 
-    let result = sqlx::query("INSERT INTO leftovers (width_mm, height_mm, thickness_mm, material, notes, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)")
-        .bind(payload.width_mm).bind(payload.height_mm).bind(payload.thickness_mm).bind(&payload.material).bind(&payload.notes).bind(created_at)
-        .execute(&state.db).await.map_err(AppError::Database)?;
+    let mut tx = state.db.begin().await.map_err(AppError::Database)?;
+    let result = sqlx::query("INSERT INTO leftovers (width_mm, height_mm, thickness_mm, material, notes, grain, trim_mm, created_at, shape) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)")
+        .bind(width_mm).bind(height_mm).bind(payload.thickness_mm).bind(&payload.material).bind(&payload.notes).bind(payload.grain).bind(payload.trim_mm).bind(created_at).bind(shape)
+        .execute(&mut *tx).await.map_err(AppError::Database)?;
+    replace_defects(&mut tx, result.last_insert_rowid(), &payload.defects).await?;
+    tx.commit().await.map_err(AppError::Database)?;
 
     Ok((
         StatusCode::CREATED,
@@ -541,25 +793,33 @@ async fn search_resto(
     validate_dimensions(params.width_mm, params.height_mm, params.thickness_mm)?;
     validate_material(&params.material)?;
     let required_area = params.width_mm * params.height_mm;
-    let candidates: Vec<Leftover> = sqlx::query_as(
-        "SELECT id, width_mm, height_mm, thickness_mm, material, notes, created_at FROM leftovers WHERE width_mm >= ?1 AND height_mm >= ?2 AND thickness_mm = ?3 AND LOWER(material) = LOWER(?4)"
+    let mut candidates: Vec<Leftover> = sqlx::query_as(
+        "SELECT id, width_mm, height_mm, thickness_mm, material, notes, grain, trim_mm, created_at, shape FROM leftovers WHERE width_mm >= ?1 AND height_mm >= ?2 AND thickness_mm = ?3 AND LOWER(material) = LOWER(?4)"
     )
     .bind(params.width_mm).bind(params.height_mm).bind(params.thickness_mm).bind(&params.material)
     .fetch_all(&state.db).await.map_err(AppError::Database)?;
+    attach_defects(&state.db, &mut candidates).await?;
+    // The bounding box of a shaped or damaged leftover can fit where the
+    // usable area does not.
+    candidates.retain(|l| l.fits_rect(params.width_mm, params.height_mm));
+    if !params.include_reserved {
+        exclude_reserved(&state.db, &mut candidates, None).await?;
+    }
 
     if candidates.is_empty() {
-        return Err(AppError::NotFound(format!("No matching restos found")));
+        return Err(AppError::NotFound("No matching restos found".to_string()));
     }
     let best_match = candidates
         .into_iter()
-        .min_by_key(|l| (l.width_mm * l.height_mm) - required_area)
+        .min_by_key(|l| l.area_mm2() - required_area)
         .unwrap();
     Ok(Json(best_match))
 }
 
 async fn list_restos(State(state): State<AppState>) -> Result<impl IntoResponse, AppError> {
-    let restos: Vec<Leftover> = sqlx::query_as("SELECT id, width_mm, height_mm, thickness_mm, material, notes, created_at FROM leftovers ORDER BY created_at DESC")
+    let mut restos: Vec<Leftover> = sqlx::query_as("SELECT id, width_mm, height_mm, thickness_mm, material, notes, grain, trim_mm, created_at, shape FROM leftovers ORDER BY created_at DESC")
         .fetch_all(&state.db).await.map_err(AppError::Database)?;
+    attach_defects(&state.db, &mut restos).await?;
     Ok(Json(restos))
 }
 
@@ -573,21 +833,62 @@ async fn update_resto(
         .fetch_optional(&state.db)
         .await
         .map_err(AppError::Database)?;
-    let resto = existing.ok_or_else(|| AppError::NotFound(format!("Resto {} not found", id)))?;
-
-    let new_width = payload.width_mm.unwrap_or(resto.width_mm);
-    let new_height = payload.height_mm.unwrap_or(resto.height_mm);
+    let mut resto =
+        existing.ok_or_else(|| AppError::NotFound(format!("Resto {} not found", id)))?;
+    attach_defects(&state.db, std::slice::from_mut(&mut resto)).await?;
+
+    let (new_width, new_height, new_shape) = match payload.shape {
+        Some(shape) => resolve_shape(
+            Some(shape),
+            payload.width_mm.unwrap_or(0),
+            payload.height_mm.unwrap_or(0),
+        )?,
+        None => {
+            let width = payload.width_mm.unwrap_or(resto.width_mm);
+            let height = payload.height_mm.unwrap_or(resto.height_mm);
+            if resto.shape.is_some() && (width, height) != (resto.width_mm, resto.height_mm) {
+                return Err(AppError::Validation(format!(
+                    "Resto {} is shaped; send a new shape to resize it",
+                    id
+                )));
+            }
+            (width, height, resto.shape)
+        }
+    };
     let new_thickness = payload.thickness_mm.unwrap_or(resto.thickness_mm);
     let new_material = payload.material.unwrap_or(resto.material);
     let new_notes = payload.notes.or(resto.notes);
+    let new_grain = payload.grain.or(resto.grain);
+    let new_trim = payload.trim_mm.or(resto.trim_mm);
 
     validate_dimensions(new_width, new_height, new_thickness)?;
     validate_material(&new_material)?;
     validate_notes(&new_notes)?;
+    validate_trim(new_trim)?;
+    match &payload.defects {
+        Some(defects) => validate_defects(defects, new_width, new_height, new_shape.as_deref())?,
+        None => {
+            if let Some(d) = resto.defects.iter().find(|d| {
+                d.x + d.width_mm > new_width
+                    || d.y + d.height_mm > new_height
+                    || new_shape.as_ref().is_some_and(|s| !s.contains(&d.rect()))
+            }) {
+                return Err(AppError::Validation(format!(
+                    "Defect {} lies outside the reshaped resto; send the defects too",
+                    d.id
+                )));
+            }
+        }
+    }
 
-    sqlx::query("UPDATE leftovers SET width_mm = ?1, height_mm = ?2, thickness_mm = ?3, material = ?4, notes = ?5 WHERE id = ?6")
-        .bind(new_width).bind(new_height).bind(new_thickness).bind(&new_material).bind(&new_notes).bind(id)
-        .execute(&state.db).await.map_err(AppError::Database)?;
+    let mut tx = state.db.begin().await.map_err(AppError::Database)?;
+    sqlx::query("UPDATE leftovers SET width_mm = ?1, height_mm = ?2, thickness_mm = ?3, material = ?4, notes = ?5, grain = ?6, trim_mm = ?7, shape = ?8 WHERE id = ?9")
+        .bind(new_width).bind(new_height).bind(new_thickness).bind(&new_material).bind(&new_notes).bind(new_grain).bind(new_trim).bind(new_shape).bind(id)
+        .execute(&mut *tx).await.map_err(AppError::Database)?;
+    if let Some(defects) = &payload.defects {
+        replace_defects(&mut tx, id, defects).await?;
+    }
+    tx.commit().await.map_err(AppError::Database)?;
 
     Ok(Json(
         serde_json::json!({ "success": true, "id": id, "message": "Updated successfully" }),
@@ -595,12 +896,14 @@ async fn update_resto(
 }
 
 async fn get_stats(State(state): State<AppState>) -> Result<impl IntoResponse, AppError> {
-    let (total_count, total_area_mm2): (i64, i64) =
-        sqlx::query_as("SELECT COUNT(*), COALESCE(SUM(width_mm * height_mm), 0) FROM leftovers")
-            .fetch_one(&state.db)
-            .await
-            .map_err(AppError::Database)?;
-    let by_material: Vec<MaterialStats> = sqlx::query_as("SELECT material, COUNT(*) as count, SUM(width_mm * height_mm) as total_area_mm2 FROM leftovers GROUP BY material ORDER BY count DESC")
+    let (total_count, total_area_mm2): (i64, i64) = sqlx::query_as(&format!(
+        "SELECT COUNT(*), COALESCE(SUM({}), 0) FROM leftovers",
+        LEFTOVER_AREA_SQL
+    ))
+    .fetch_one(&state.db)
+    .await
+    .map_err(AppError::Database)?;
+    let by_material: Vec<MaterialStats> = sqlx::query_as(&format!("SELECT material, COUNT(*) as count, SUM({}) as total_area_mm2 FROM leftovers GROUP BY material ORDER BY count DESC", LEFTOVER_AREA_SQL))
         .fetch_all(&state.db).await.map_err(AppError::Database)?;
     let by_thickness: Vec<ThicknessStats> = sqlx::query_as("SELECT thickness_mm, COUNT(*) as count FROM leftovers GROUP BY thickness_mm ORDER BY thickness_mm")
         .fetch_all(&state.db).await.map_err(AppError::Database)?;
@@ -642,11 +945,19 @@ async fn add_van(
         return Err(AppError::Validation("Invalid name".into()));
     }
     validate_van_dimensions(req.length_mm, req.width_mm, req.height_mm)?;
+    validate_van_axles(
+        req.rear_axle_x_mm,
+        req.front_axle_x_mm,
+        [req.max_rear_axle_kg, req.max_front_axle_kg],
+        req.max_side_imbalance_percent,
+    )?;
     validate_notes(&req.notes)?;
 
-    let result = sqlx::query("INSERT INTO vans (name, length_mm, width_mm, height_mm, max_weight_kg, wheel_well_height_mm, wheel_well_width_mm, wheel_well_start_x_mm, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")
+    let result = sqlx::query("INSERT INTO vans (name, length_mm, width_mm, height_mm, max_weight_kg, wheel_well_height_mm, wheel_well_width_mm, wheel_well_start_x_mm, rear_axle_x_mm, front_axle_x_mm, max_rear_axle_kg, max_front_axle_kg, max_side_imbalance_percent, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
         .bind(&req.name).bind(req.length_mm).bind(req.width_mm).bind(req.height_mm).bind(req.max_weight_kg)
-        .bind(req.wheel_well_height_mm).bind(req.wheel_well_width_mm).bind(req.wheel_well_start_x_mm).bind(&req.notes)
+        .bind(req.wheel_well_height_mm).bind(req.wheel_well_width_mm).bind(req.wheel_well_start_x_mm)
+        .bind(req.rear_axle_x_mm).bind(req.front_axle_x_mm).bind(req.max_rear_axle_kg).bind(req.max_front_axle_kg).bind(req.max_side_imbalance_percent)
+        .bind(&req.notes)
         .execute(&state.db).await.map_err(AppError::Database)?;
 
     Ok(Json(serde_json::json!({"id": result.last_insert_rowid()})))
@@ -657,16 +968,23 @@ async fn update_van(
     Path(id): Path<i64>,
     Json(req): Json<UpdateVanRequest>,
 ) -> Result<impl IntoResponse, AppError> {
-    let existing = sqlx::query_as::<_, Van>("SELECT * FROM vans WHERE id = ?")
+    let van = sqlx::query_as::<_, Van>("SELECT * FROM vans WHERE id = ?")
         .bind(id)
         .fetch_optional(&state.db)
         .await
         .map_err(AppError::Database)?
         .ok_or_else(|| AppError::NotFound(format!("Van {} not found", id)))?;
-
-    sqlx::query("UPDATE vans SET name = COALESCE(?, name), length_mm = COALESCE(?, length_mm), width_mm = COALESCE(?, width_mm), height_mm = COALESCE(?, height_mm), max_weight_kg = COALESCE(?, max_weight_kg), wheel_well_height_mm = COALESCE(?, wheel_well_height_mm), wheel_well_width_mm = COALESCE(?, wheel_well_width_mm), wheel_well_start_x_mm = COALESCE(?, wheel_well_start_x_mm), notes = COALESCE(?, notes), active = COALESCE(?, active) WHERE id = ?")
+    validate_van_axles(
+        req.rear_axle_x_mm.or(van.rear_axle_x_mm),
+        req.front_axle_x_mm.or(van.front_axle_x_mm),
+        [req.max_rear_axle_kg, req.max_front_axle_kg],
+        req.max_side_imbalance_percent,
+    )?;
+
+    sqlx::query("UPDATE vans SET name = COALESCE(?, name), length_mm = COALESCE(?, length_mm), width_mm = COALESCE(?, width_mm), height_mm = COALESCE(?, height_mm), max_weight_kg = COALESCE(?, max_weight_kg), wheel_well_height_mm = COALESCE(?, wheel_well_height_mm), wheel_well_width_mm = COALESCE(?, wheel_well_width_mm), wheel_well_start_x_mm = COALESCE(?, wheel_well_start_x_mm), rear_axle_x_mm = COALESCE(?, rear_axle_x_mm), front_axle_x_mm = COALESCE(?, front_axle_x_mm), max_rear_axle_kg = COALESCE(?, max_rear_axle_kg), max_front_axle_kg = COALESCE(?, max_front_axle_kg), max_side_imbalance_percent = COALESCE(?, max_side_imbalance_percent), notes = COALESCE(?, notes), active = COALESCE(?, active) WHERE id = ?")
         .bind(&req.name).bind(req.length_mm).bind(req.width_mm).bind(req.height_mm).bind(req.max_weight_kg)
         .bind(req.wheel_well_height_mm).bind(req.wheel_well_width_mm).bind(req.wheel_well_start_x_mm)
+        .bind(req.rear_axle_x_mm).bind(req.front_axle_x_mm).bind(req.max_rear_axle_kg).bind(req.max_front_axle_kg).bind(req.max_side_imbalance_percent)
         .bind(&req.notes).bind(req.active).bind(id)
         .execute(&state.db).await.map_err(AppError::Database)?;
 
@@ -693,68 +1011,55 @@ async fn optimize_loading(
     State(state): State<AppState>,
     Json(req): Json<OptimizeRequest>,
 ) -> Result<impl IntoResponse, AppError> {
-    tracing::info!(van_id = %req.van_id, items = %req.items.len(), "Optimizing load via Python Sidecar");
+    Ok(Json(plan_van_load(&state.db, req).await?))
+}
 
+/// Plans one van load with the native loader, or with the Python sidecar
+/// when built with `python-sidecar` and `VAN_LOADER=python` is set, and
+/// stores a successful plan.
+async fn plan_van_load(
+    db: &Pool<Sqlite>,
+    req: OptimizeRequest,
+) -> Result<OptimizeResponse, AppError> {
+    validate_plan_date("plan_date", &req.plan_date)?;
+    validate_cargo_weights(&req.items)?;
     let van = sqlx::query_as::<_, Van>("SELECT * FROM vans WHERE id = ? AND active = 1")
         .bind(req.van_id)
-        .fetch_optional(&state.db)
+        .fetch_optional(db)
         .await
         .map_err(AppError::Database)?
         .ok_or_else(|| AppError::NotFound(format!("Van {} not found or inactive", req.van_id)))?;
 
-    let input_data = serde_json::json!({
-        "van": van,
-        "items": req.items
-    });
-
-    let input_str = serde_json::to_string(&input_data)
-        .map_err(|e| AppError::Internal(format!("JSON serialization error: {}", e)))?;
-
-    let python_result = tokio::task::spawn_blocking(move || {
-        let (cmd, args) = if cfg!(target_os = "windows") {
-            ("py", vec!["-3.12", "optimizer.py"])
-        } else {
-            ("python3", vec!["optimizer.py"])
-        };
-        let mut child = std::process::Command::new(cmd)
-            .args(&args)
-            .stdin(std::process::Stdio::piped())
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped())
-            .spawn()
-            .map_err(|e| format!("Failed to spawn python process: {}", e))?;
-
-        if let Some(mut stdin) = child.stdin.take() {
-            stdin
-                .write_all(input_str.as_bytes())
-                .map_err(|e| format!("Failed to write to python stdin: {}", e))?;
-        }
-
-        let output = child
-            .wait_with_output()
-            .map_err(|e| format!("Failed to read python output: {}", e))?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(format!("Python optimizer failed: {}", stderr));
+    #[cfg(feature = "python-sidecar")]
+    let sidecar_response = if sidecar::enabled() {
+        Some(sidecar::run(&van, &req.items).await?)
+    } else {
+        None
+    };
+    #[cfg(not(feature = "python-sidecar"))]
+    let sidecar_response = None;
+
+    let mut response = match sidecar_response {
+        Some(response) => response,
+        None => {
+            tracing::info!(van_id = %req.van_id, items = %req.items.len(), "Optimizing load");
+            let (van, items) = (van.clone(), req.items);
+            tokio::task::spawn_blocking(move || van_loader::pack(&van, &items))
+                .await
+                .map_err(|e| AppError::Internal(format!("Van loader failed: {}", e)))?
         }
-
-        Ok(output.stdout)
-    })
-    .await
-    .map_err(|e| AppError::Internal(format!("Task spawn error: {}", e)))?;
-
-    let output_bytes = python_result.map_err(AppError::Internal)?;
-
-    let response: OptimizeResponse = serde_json::from_slice(&output_bytes).map_err(|e| {
-        AppError::Internal(format!(
-            "Failed to parse Python response: {}. Output was: {:?}",
-            e,
-            String::from_utf8_lossy(&output_bytes)
-        ))
-    })?;
-
-    Ok(Json(response))
+    };
+    plan_unloading(&mut response);
+    assess_balance(&van, &mut response);
+    let mut conn = db.acquire().await.map_err(AppError::Database)?;
+    store_plan(
+        &mut conn,
+        req.van_id,
+        req.plan_date.as_deref(),
+        &mut response,
+    )
+    .await?;
+    Ok(response)
 }
 
 #[derive(Serialize)]
@@ -765,6 +1070,9 @@ struct HealthResponse {
 struct ReadinessResponse {
     status: String,
     database: String,
+    van_loader: String,
+    #[cfg(feature = "python-sidecar")]
+    sidecar: sidecar::SidecarStatus,
 }
 
 async fn health_check() -> impl IntoResponse {
@@ -777,8 +1085,35 @@ async fn readiness_check(State(state): State<AppState>) -> Result<impl IntoRespo
         .execute(&state.db)
         .await
         .map_err(AppError::Database)?;
-    Ok(Json(ReadinessResponse {
-        status: "ready".to_string(),
-        database: "connected".to_string(),
-    }))
+
+    #[cfg(feature = "python-sidecar")]
+    {
+        let sidecar = sidecar::sidecar().status().await;
+        let uses_sidecar = sidecar::enabled();
+        // Only a sidecar that serves the loads can make the server unready.
+        let ready = sidecar.ok || !uses_sidecar;
+        Ok((
+            if ready {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            },
+            Json(ReadinessResponse {
+                status: if ready { "ready" } else { "degraded" }.to_string(),
+                database: "connected".to_string(),
+                van_loader: if uses_sidecar { "python" } else { "native" }.to_string(),
+                sidecar,
+            }),
+        ))
+    }
+
+    #[cfg(not(feature = "python-sidecar"))]
+    Ok((
+        StatusCode::OK,
+        Json(ReadinessResponse {
+            status: "ready".to_string(),
+            database: "connected".to_string(),
+            van_loader: "native".to_string(),
+        }),
+    ))
 }
@@ -0,0 +1,118 @@
+use crate::{AppError, AppState, Leftover};
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::Serialize;
+use sqlx::{FromRow, Pool, Sqlite, SqliteConnection};
+use std::collections::HashSet;
+
+pub const DEFAULT_RESERVATION_MINUTES: i64 = 30;
+pub const MAX_RESERVATION_MINUTES: i64 = 7 * 24 * 60;
+
+#[derive(Serialize, FromRow)]
+pub struct Reservation {
+    pub leftover_id: i64,
+    pub cut_job_id: i64,
+    pub reserved_by: Option<String>,
+    pub expires_at: String,
+    pub created_at: String,
+}
+
+/// Leftovers held by an unexpired reservation of any job but `except_job`.
+pub async fn reserved_leftover_ids(
+    db: &Pool<Sqlite>,
+    except_job: Option<i64>,
+) -> Result<HashSet<i64>, AppError> {
+    let rows: Vec<(i64,)> = sqlx::query_as("SELECT leftover_id FROM leftover_reservations WHERE expires_at > datetime('now') AND (?1 IS NULL OR cut_job_id != ?1)")
+        .bind(except_job)
+        .fetch_all(db)
+        .await
+        .map_err(AppError::Database)?;
+    Ok(rows.into_iter().map(|(id,)| id).collect())
+}
+
+/// Drops leftovers other pending plans hold from `inventory`.
+pub async fn exclude_reserved(
+    db: &Pool<Sqlite>,
+    inventory: &mut Vec<Leftover>,
+    except_job: Option<i64>,
+) -> Result<(), AppError> {
+    let reserved = reserved_leftover_ids(db, except_job).await?;
+    inventory.retain(|p| !reserved.contains(&p.id));
+    Ok(())
+}
+
+/// Outcome of `reserve_leftovers`.
+pub struct Reserved {
+    /// Leftovers another job still holds.
+    pub held_elsewhere: Vec<i64>,
+    /// Leftovers taken over from the job being re-run.
+    pub taken_over: Vec<i64>,
+    pub expires_at: String,
+}
+
+/// Reserves `leftover_ids` for `job_id` for `minutes`, taking over expired
+/// reservations and those of `takeover_from` (the job being re-run).
+pub async fn reserve_leftovers(
+    conn: &mut SqliteConnection,
+    job_id: i64,
+    leftover_ids: &[i64],
+    minutes: i64,
+    reserved_by: Option<&str>,
+    takeover_from: Option<i64>,
+) -> Result<Reserved, AppError> {
+    let (expires_at,): (String,) = sqlx::query_as("SELECT datetime('now', ?1)")
+        .bind(format!("+{} minutes", minutes))
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(AppError::Database)?;
+
+    let mut held_elsewhere = Vec::new();
+    let mut taken_over = Vec::new();
+    for &leftover_id in leftover_ids {
+        let holder: Option<(i64,)> = sqlx::query_as("SELECT cut_job_id FROM leftover_reservations WHERE leftover_id = ? AND expires_at > datetime('now')")
+            .bind(leftover_id)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(AppError::Database)?;
+        let res = sqlx::query("INSERT INTO leftover_reservations (leftover_id, cut_job_id, reserved_by, expires_at) VALUES (?1, ?2, ?3, ?4) ON CONFLICT(leftover_id) DO UPDATE SET cut_job_id = excluded.cut_job_id, reserved_by = excluded.reserved_by, expires_at = excluded.expires_at, created_at = CURRENT_TIMESTAMP WHERE leftover_reservations.expires_at <= datetime('now') OR leftover_reservations.cut_job_id = ?2 OR leftover_reservations.cut_job_id = ?5")
+            .bind(leftover_id).bind(job_id).bind(reserved_by).bind(&expires_at).bind(takeover_from)
+            .execute(&mut *conn).await.map_err(AppError::Database)?;
+        if res.rows_affected() == 0 {
+            held_elsewhere.push(leftover_id);
+        } else if holder.is_some_and(|(holder,)| Some(holder) == takeover_from) {
+            taken_over.push(leftover_id);
+        }
+    }
+    Ok(Reserved {
+        held_elsewhere,
+        taken_over,
+        expires_at,
+    })
+}
+
+pub async fn release_job(conn: &mut SqliteConnection, job_id: i64) -> Result<u64, AppError> {
+    let res = sqlx::query("DELETE FROM leftover_reservations WHERE cut_job_id = ?")
+        .bind(job_id)
+        .execute(conn)
+        .await
+        .map_err(AppError::Database)?;
+    Ok(res.rows_affected())
+}
+
+/// Active reservations; expired ones are purged on the way.
+pub async fn list_reservations(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    sqlx::query("DELETE FROM leftover_reservations WHERE expires_at <= datetime('now')")
+        .execute(&state.db)
+        .await
+        .map_err(AppError::Database)?;
+
+    let reservations = sqlx::query_as::<_, Reservation>(
+        "SELECT leftover_id, cut_job_id, reserved_by, expires_at, created_at FROM leftover_reservations ORDER BY expires_at",
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(Json(reservations))
+}
@@ -0,0 +1,408 @@
+use crate::{AppError, CargoItem, OptimizeResponse, Van};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::process::{Output, Stdio};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+// Stays below the 120 s request timeout so the error makes it back.
+const MAX_TIMEOUT_SECS: u64 = 110;
+const DEFAULT_MAX_PROCESSES: usize = 2;
+/// Part of the timeout kept for interpreter start-up and writing the answer.
+const STARTUP_MARGIN_SECS: u64 = 5;
+const CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+const CHECK_TTL: Duration = Duration::from_secs(60);
+const OUTPUT_EXCERPT_CHARS: usize = 1000;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SolverErrorKind {
+    /// The solver ran out of time, or waited too long for a free slot.
+    Timeout,
+    /// Python, OR-Tools or the script is missing.
+    MissingDependency,
+    /// The process answered with something that is not a plan.
+    BadOutput,
+    /// The model has no solution.
+    Infeasible,
+    /// The script failed for any other reason.
+    Crashed,
+}
+
+#[derive(Debug)]
+pub struct SolverError {
+    pub kind: SolverErrorKind,
+    pub message: String,
+}
+
+impl SolverError {
+    fn new(kind: SolverErrorKind, message: impl Into<String>) -> Self {
+        SolverError {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<SolverError> for AppError {
+    fn from(err: SolverError) -> Self {
+        AppError::Solver(err)
+    }
+}
+
+#[derive(Serialize)]
+struct SolverErrorResponse {
+    error: String,
+    kind: SolverErrorKind,
+    details: String,
+}
+
+impl IntoResponse for SolverError {
+    fn into_response(self) -> Response {
+        let status = match self.kind {
+            SolverErrorKind::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            SolverErrorKind::MissingDependency => StatusCode::SERVICE_UNAVAILABLE,
+            SolverErrorKind::BadOutput | SolverErrorKind::Crashed => StatusCode::BAD_GATEWAY,
+            SolverErrorKind::Infeasible => StatusCode::UNPROCESSABLE_ENTITY,
+        };
+        tracing::error!(kind = ?self.kind, "Solver error: {}", self.message);
+        let body = Json(SolverErrorResponse {
+            error: "Solver error".to_string(),
+            kind: self.kind,
+            details: self.message,
+        });
+        (status, body).into_response()
+    }
+}
+
+/// Result of the readiness probe of the sidecar.
+#[derive(Serialize, Clone)]
+pub struct SidecarStatus {
+    pub ok: bool,
+    pub interpreter: String,
+    pub script: String,
+    pub timeout_secs: u64,
+    pub max_processes: usize,
+    pub running: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Interpreter, script and limits, read once from the environment:
+/// `SIDECAR_PYTHON` and `SIDECAR_PYTHON_ARGS`, `SIDECAR_SCRIPT`,
+/// `SIDECAR_TIMEOUT_SECS` and `SIDECAR_MAX_PROCESSES`.
+pub struct Sidecar {
+    program: String,
+    args: Vec<String>,
+    script: PathBuf,
+    timeout: Duration,
+    max_processes: usize,
+    permits: Semaphore,
+    last_check: Mutex<Option<(Instant, SidecarStatus)>>,
+}
+
+/// Whether `VAN_LOADER=python` picks the OR-Tools sidecar over the native
+/// loader.
+pub fn enabled() -> bool {
+    std::env::var("VAN_LOADER").is_ok_and(|v| v.eq_ignore_ascii_case("python"))
+}
+
+pub fn sidecar() -> &'static Sidecar {
+    static SIDECAR: OnceLock<Sidecar> = OnceLock::new();
+    SIDECAR.get_or_init(Sidecar::from_env)
+}
+
+fn env_number<T: FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(raw) => raw.trim().parse().unwrap_or_else(|_| {
+            tracing::warn!("Ignoring {}={:?}; it is not a number", name, raw);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+/// `optimizer.py` next to the executable, else in the working directory.
+fn default_script() -> PathBuf {
+    let beside_exe = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("optimizer.py")));
+    match beside_exe {
+        Some(path) if path.is_file() => path,
+        _ => std::env::current_dir()
+            .map(|dir| dir.join("optimizer.py"))
+            .unwrap_or_else(|_| PathBuf::from("optimizer.py")),
+    }
+}
+
+fn excerpt(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    let text = text.trim();
+    let count = text.chars().count();
+    if count <= OUTPUT_EXCERPT_CHARS {
+        return text.to_string();
+    }
+    let tail: String = text.chars().skip(count - OUTPUT_EXCERPT_CHARS).collect();
+    format!("...{}", tail)
+}
+
+impl Sidecar {
+    fn from_env() -> Self {
+        let (default_program, default_args) = if cfg!(target_os = "windows") {
+            ("py", "-3.12")
+        } else {
+            ("python3", "")
+        };
+        let program = std::env::var("SIDECAR_PYTHON")
+            .ok()
+            .filter(|p| !p.trim().is_empty())
+            .unwrap_or_else(|| default_program.to_string());
+        let args = std::env::var("SIDECAR_PYTHON_ARGS")
+            .unwrap_or_else(|_| default_args.to_string())
+            .split_whitespace()
+            .map(String::from)
+            .collect();
+        let script = std::env::var("SIDECAR_SCRIPT")
+            .ok()
+            .filter(|p| !p.trim().is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(default_script);
+        let timeout_secs =
+            env_number("SIDECAR_TIMEOUT_SECS", DEFAULT_TIMEOUT_SECS).clamp(1, MAX_TIMEOUT_SECS);
+        let max_processes = env_number("SIDECAR_MAX_PROCESSES", DEFAULT_MAX_PROCESSES).max(1);
+
+        let sidecar = Sidecar {
+            program,
+            args,
+            script,
+            timeout: Duration::from_secs(timeout_secs),
+            max_processes,
+            permits: Semaphore::new(max_processes),
+            last_check: Mutex::new(None),
+        };
+        tracing::info!(
+            interpreter = %sidecar.interpreter(),
+            script = %sidecar.script.display(),
+            timeout_secs,
+            max_processes,
+            "Python sidecar configured"
+        );
+        sidecar
+    }
+
+    fn interpreter(&self) -> String {
+        std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn command(&self) -> tokio::process::Command {
+        let mut cmd = tokio::process::Command::new(&self.program);
+        cmd.args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        cmd
+    }
+
+    fn spawn_error(&self, e: std::io::Error) -> SolverError {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            SolverError::new(
+                SolverErrorKind::MissingDependency,
+                format!("Python interpreter '{}' not found", self.program),
+            )
+        } else {
+            SolverError::new(
+                SolverErrorKind::Crashed,
+                format!("Failed to start '{}': {}", self.interpreter(), e),
+            )
+        }
+    }
+
+    /// Feeds `input` to a fresh process and collects its output. The process
+    /// is killed when `limit` runs out or the future is dropped.
+    async fn exec(
+        &self,
+        mut cmd: tokio::process::Command,
+        input: &[u8],
+        limit: Duration,
+    ) -> Result<Output, SolverError> {
+        let mut child = cmd.spawn().map_err(|e| self.spawn_error(e))?;
+        let io = async {
+            if let Some(mut stdin) = child.stdin.take() {
+                // A script that exits early closes stdin; its exit status
+                // explains why better than the broken pipe does.
+                let _ = stdin.write_all(input).await;
+            }
+            child.wait_with_output().await
+        };
+        match tokio::time::timeout(limit, io).await {
+            Ok(Ok(output)) => Ok(output),
+            Ok(Err(e)) => Err(SolverError::new(
+                SolverErrorKind::Crashed,
+                format!("Failed to read solver output: {}", e),
+            )),
+            Err(_) => Err(SolverError::new(
+                SolverErrorKind::Timeout,
+                format!("Solver killed after {:.0} s", limit.as_secs_f64()),
+            )),
+        }
+    }
+
+    /// Checks that the interpreter starts, OR-Tools imports and the script
+    /// exists. The result is cached for a minute.
+    pub async fn status(&self) -> SidecarStatus {
+        if let Some((at, status)) = self.last_check.lock().unwrap().as_ref() {
+            if at.elapsed() < CHECK_TTL {
+                return SidecarStatus {
+                    running: self.running(),
+                    ..status.clone()
+                };
+            }
+        }
+
+        let error = if !self.script.is_file() {
+            Some(format!("Solver script {} not found", self.script.display()))
+        } else {
+            let mut cmd = self.command();
+            cmd.arg("-c").arg("import ortools.sat.python.cp_model");
+            match self.exec(cmd, b"", CHECK_TIMEOUT).await {
+                Ok(output) if output.status.success() => None,
+                Ok(output) => Some(format!(
+                    "OR-Tools does not import: {}",
+                    excerpt(&output.stderr)
+                )),
+                Err(e) => Some(e.message),
+            }
+        };
+        let status = SidecarStatus {
+            ok: error.is_none(),
+            interpreter: self.interpreter(),
+            script: self.script.display().to_string(),
+            timeout_secs: self.timeout.as_secs(),
+            max_processes: self.max_processes,
+            running: self.running(),
+            error,
+        };
+        *self.last_check.lock().unwrap() = Some((Instant::now(), status.clone()));
+        status
+    }
+
+    fn running(&self) -> usize {
+        self.max_processes - self.permits.available_permits()
+    }
+}
+
+/// Runs `optimizer.py` for one van. Waiting for a free process slot counts
+/// against the timeout; the child is killed when the timeout runs out or the
+/// returned future is dropped, e.g. when a background job is cancelled.
+pub async fn run(van: &Van, items: &[CargoItem]) -> Result<OptimizeResponse, AppError> {
+    let sidecar = sidecar();
+    let started = Instant::now();
+    tracing::info!(van_id = %van.id, items = %items.len(), "Optimizing load via Python Sidecar");
+
+    if !sidecar.script.is_file() {
+        return Err(SolverError::new(
+            SolverErrorKind::MissingDependency,
+            format!("Solver script {} not found", sidecar.script.display()),
+        )
+        .into());
+    }
+
+    let _permit = tokio::time::timeout(sidecar.timeout, sidecar.permits.acquire())
+        .await
+        .map_err(|_| {
+            SolverError::new(
+                SolverErrorKind::Timeout,
+                format!(
+                    "All {} solver processes stayed busy for {} s",
+                    sidecar.max_processes,
+                    sidecar.timeout.as_secs()
+                ),
+            )
+        })?
+        .map_err(|_| AppError::Internal("Solver slots are closed".to_string()))?;
+
+    let remaining = sidecar.timeout.saturating_sub(started.elapsed());
+    let input_data = serde_json::json!({
+        "van": van,
+        "items": items,
+        "time_limit_s": remaining.as_secs().saturating_sub(STARTUP_MARGIN_SECS).max(1),
+    });
+    let input_str = serde_json::to_string(&input_data)
+        .map_err(|e| AppError::Internal(format!("JSON serialization error: {}", e)))?;
+
+    let mut cmd = sidecar.command();
+    cmd.arg(&sidecar.script);
+    let output = sidecar.exec(cmd, input_str.as_bytes(), remaining).await?;
+    tracing::debug!(
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "Python sidecar finished"
+    );
+    Ok(read_output(output)?)
+}
+
+fn read_output(output: Output) -> Result<OptimizeResponse, SolverError> {
+    let stderr = excerpt(&output.stderr);
+    if !output.status.success() {
+        let kind =
+            if stderr.contains("OR-Tools not installed") || stderr.contains("No module named") {
+                SolverErrorKind::MissingDependency
+            } else {
+                SolverErrorKind::Crashed
+            };
+        return Err(SolverError::new(
+            kind,
+            format!("Python optimizer failed ({}): {}", output.status, stderr),
+        ));
+    }
+
+    let response: OptimizeResponse = serde_json::from_slice(&output.stdout).map_err(|e| {
+        SolverError::new(
+            SolverErrorKind::BadOutput,
+            format!(
+                "Failed to parse Python response: {}. Output was: {:?}",
+                e,
+                excerpt(&output.stdout)
+            ),
+        )
+    })?;
+    if response.success {
+        if response.plan.is_none() {
+            return Err(SolverError::new(
+                SolverErrorKind::BadOutput,
+                "Python response has no plan",
+            ));
+        }
+        return Ok(response);
+    }
+
+    let reason = response.warnings.join("; ");
+    let kind = match reason.strip_prefix("Optimization failed: ") {
+        Some(s) if s.starts_with("INFEASIBLE") || s.starts_with("MODEL_INVALID") => {
+            SolverErrorKind::Infeasible
+        }
+        // CP-SAT reports UNKNOWN when the time limit ends the search first.
+        Some(s) if s.starts_with("UNKNOWN") => SolverErrorKind::Timeout,
+        _ => SolverErrorKind::Crashed,
+    };
+    Err(SolverError::new(
+        kind,
+        if stderr.is_empty() {
+            reason
+        } else {
+            format!("{} ({})", reason, stderr)
+        },
+    ))
+}
@@ -0,0 +1,167 @@
+use crate::{
+    validate_dimensions, validate_material, validate_notes, validate_trim, AppError, AppState,
+    Grain,
+};
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+#[derive(Serialize, FromRow, Clone, Debug)]
+pub struct StockSheet {
+    pub id: i64,
+    pub material: String,
+    pub thickness_mm: i64,
+    pub width_mm: i64,
+    pub height_mm: i64,
+    pub price: Option<f64>,
+    pub in_stock: bool,
+    pub grain: Option<Grain>,
+    pub trim_mm: Option<i64>,
+    pub notes: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct AddStockSheetRequest {
+    material: String,
+    thickness_mm: i64,
+    width_mm: i64,
+    height_mm: i64,
+    price: Option<f64>,
+    #[serde(default = "crate::default_true")]
+    in_stock: bool,
+    grain: Option<Grain>,
+    trim_mm: Option<i64>,
+    notes: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateStockSheetRequest {
+    material: Option<String>,
+    thickness_mm: Option<i64>,
+    width_mm: Option<i64>,
+    height_mm: Option<i64>,
+    price: Option<f64>,
+    in_stock: Option<bool>,
+    grain: Option<Grain>,
+    trim_mm: Option<i64>,
+    notes: Option<String>,
+}
+
+fn validate_price(price: Option<f64>) -> Result<(), AppError> {
+    if let Some(p) = price {
+        if !p.is_finite() || p < 0.0 {
+            return Err(AppError::Validation(
+                "Price must be a non-negative number".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// In-stock catalog entries, used by the cutting optimizer to open new sheets.
+pub async fn load_catalog(db: &sqlx::SqlitePool) -> Result<Vec<StockSheet>, AppError> {
+    sqlx::query_as::<_, StockSheet>(
+        "SELECT * FROM stock_sheets WHERE in_stock = 1 ORDER BY width_mm * height_mm ASC",
+    )
+    .fetch_all(db)
+    .await
+    .map_err(AppError::Database)
+}
+
+pub async fn list_stock_sheets(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let sheets = sqlx::query_as::<_, StockSheet>(
+        "SELECT * FROM stock_sheets ORDER BY material, thickness_mm, width_mm * height_mm",
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(AppError::Database)?;
+    Ok(Json(sheets))
+}
+
+pub async fn get_stock_sheet(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let sheet = sqlx::query_as::<_, StockSheet>("SELECT * FROM stock_sheets WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(AppError::Database)?
+        .ok_or_else(|| AppError::NotFound(format!("Stock sheet {} not found", id)))?;
+    Ok(Json(sheet))
+}
+
+pub async fn add_stock_sheet(
+    State(state): State<AppState>,
+    Json(req): Json<AddStockSheetRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    validate_dimensions(req.width_mm, req.height_mm, req.thickness_mm)?;
+    validate_material(&req.material)?;
+    validate_price(req.price)?;
+    validate_trim(req.trim_mm)?;
+    validate_notes(&req.notes)?;
+
+    let result = sqlx::query("INSERT INTO stock_sheets (material, thickness_mm, width_mm, height_mm, price, in_stock, grain, trim_mm, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(&req.material).bind(req.thickness_mm).bind(req.width_mm).bind(req.height_mm)
+        .bind(req.price).bind(req.in_stock).bind(req.grain).bind(req.trim_mm).bind(&req.notes)
+        .execute(&state.db).await.map_err(AppError::Database)?;
+
+    Ok(Json(serde_json::json!({"id": result.last_insert_rowid()})))
+}
+
+pub async fn update_stock_sheet(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<UpdateStockSheetRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let existing = sqlx::query_as::<_, StockSheet>("SELECT * FROM stock_sheets WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(AppError::Database)?
+        .ok_or_else(|| AppError::NotFound(format!("Stock sheet {} not found", id)))?;
+
+    let material = req.material.unwrap_or(existing.material);
+    let thickness = req.thickness_mm.unwrap_or(existing.thickness_mm);
+    let width = req.width_mm.unwrap_or(existing.width_mm);
+    let height = req.height_mm.unwrap_or(existing.height_mm);
+    let price = req.price.or(existing.price);
+    let trim = req.trim_mm.or(existing.trim_mm);
+    let notes = req.notes.or(existing.notes);
+
+    validate_dimensions(width, height, thickness)?;
+    validate_material(&material)?;
+    validate_price(price)?;
+    validate_trim(trim)?;
+    validate_notes(&notes)?;
+
+    sqlx::query("UPDATE stock_sheets SET material = ?, thickness_mm = ?, width_mm = ?, height_mm = ?, price = ?, in_stock = ?, grain = ?, trim_mm = ?, notes = ? WHERE id = ?")
+        .bind(&material).bind(thickness).bind(width).bind(height).bind(price)
+        .bind(req.in_stock.unwrap_or(existing.in_stock)).bind(req.grain.or(existing.grain)).bind(trim).bind(&notes).bind(id)
+        .execute(&state.db).await.map_err(AppError::Database)?;
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+pub async fn delete_stock_sheet(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let rows = sqlx::query("DELETE FROM stock_sheets WHERE id = ?")
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(AppError::Database)?
+        .rows_affected();
+    if rows == 0 {
+        return Err(AppError::NotFound(format!("Stock sheet {} not found", id)));
+    }
+    Ok(Json(serde_json::json!({ "success": true, "id": id })))
+}
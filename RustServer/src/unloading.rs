@@ -0,0 +1,97 @@
+use crate::{OptimizeResponse, PositionedItem};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct UnloadingStep {
+    /// `None` for items without a stop, which come off after the last stop.
+    pub stop_sequence: Option<u32>,
+    /// Indexes into the plan's items, in the order they come off.
+    pub items: Vec<usize>,
+    pub total_weight_kg: f64,
+    /// Items for later stops that are in the way of this stop's items.
+    pub blocked_by: Vec<usize>,
+}
+
+/// Stop rank for ordering; items without a stop travel to the end of the trip.
+pub fn stop_rank(stop: Option<u32>) -> u32 {
+    stop.unwrap_or(u32::MAX)
+}
+
+/// Whether `a` is in the way of `b`: resting anywhere above it, or standing
+/// between it and the rear doors at `x = 0` where it would be slid out.
+fn in_the_way(a: &PositionedItem, b: &PositionedItem) -> bool {
+    let (ax, ay, az) = (a.position.x, a.position.y, a.position.z);
+    let (bx, by, bz) = (b.position.x, b.position.y, b.position.z);
+    let overlap_x = ax < bx + b.placed_length && bx < ax + a.placed_length;
+    let overlap_y = ay < by + b.placed_height && by < ay + a.placed_height;
+    let overlap_z = az < bz + b.placed_width && bz < az + a.placed_width;
+    let above = ay >= by + b.placed_height && overlap_x && overlap_z;
+    let in_front = ax + a.placed_length <= bx && overlap_y && overlap_z;
+    above || in_front
+}
+
+/// Lists the unloading steps per stop and flags the items that block an
+/// earlier stop. Plans without any `stop_sequence` are left alone.
+pub fn plan_unloading(response: &mut OptimizeResponse) {
+    let Some(plan) = response.plan.as_mut() else {
+        return;
+    };
+    if plan.items.iter().all(|p| p.item.stop_sequence.is_none()) {
+        return;
+    }
+
+    let mut stops: Vec<Option<u32>> = plan.items.iter().map(|p| p.item.stop_sequence).collect();
+    stops.sort_by_key(|s| stop_rank(*s));
+    stops.dedup();
+
+    let mut steps = Vec::with_capacity(stops.len());
+    let mut blockers = vec![false; plan.items.len()];
+    for stop in stops {
+        let rank = stop_rank(stop);
+        let mut items: Vec<usize> = (0..plan.items.len())
+            .filter(|&i| plan.items[i].item.stop_sequence == stop)
+            .collect();
+        // Nearest the doors first, then from the top down.
+        items.sort_by_key(|&i| {
+            let p = &plan.items[i];
+            (
+                p.position.x,
+                std::cmp::Reverse(p.position.y + p.placed_height),
+            )
+        });
+
+        let mut blocked_by = Vec::new();
+        for (i, other) in plan.items.iter().enumerate() {
+            if stop_rank(other.item.stop_sequence) <= rank {
+                continue;
+            }
+            let blocked: Vec<usize> = items
+                .iter()
+                .copied()
+                .filter(|&j| in_the_way(other, &plan.items[j]))
+                .collect();
+            if let Some(&first) = blocked.first() {
+                blockers[i] = true;
+                blocked_by.push(i);
+                response.warnings.push(format!(
+                    "Item '{}' blocks access to '{}'{}",
+                    other.item.description,
+                    plan.items[first].item.description,
+                    stop.map(|s| format!(" for stop {}", s)).unwrap_or_default()
+                ));
+            }
+        }
+
+        steps.push(UnloadingStep {
+            stop_sequence: stop,
+            total_weight_kg: items.iter().map(|&i| plan.items[i].item.weight_kg).sum(),
+            items,
+            blocked_by,
+        });
+    }
+
+    for (item, blocks) in plan.items.iter_mut().zip(blockers) {
+        item.blocks_access = blocks;
+    }
+    response.unloading = steps;
+}
@@ -0,0 +1,588 @@
+use crate::unloading::stop_rank;
+use crate::{
+    CargoItem, LoadingPlan, OptimizeResponse, Position3D, PositionedItem, Rotation3D, Van,
+};
+use std::collections::{BTreeSet, HashSet};
+
+/// Share of an item's base that must rest on something below it when it is
+/// not on the floor.
+pub const MIN_SUPPORT_PERCENT: i64 = 50;
+
+/// Whether other items may rest on `item`. Fragile items carry nothing, so
+/// they always end up on top.
+pub fn carries_load(item: &CargoItem) -> bool {
+    item.stackable && !item.fragile
+}
+
+/// Box in the packing frame: `x` runs along the length from the cab, `y` up
+/// from the floor and `z` across the width. Positions are mirrored into the
+/// van frame, which measures `x` from the rear doors, when the plan is built.
+#[derive(Clone, Copy, Debug)]
+struct Cuboid {
+    x: i64,
+    y: i64,
+    z: i64,
+    length: i64,
+    height: i64,
+    width: i64,
+}
+
+impl Cuboid {
+    fn x_end(&self) -> i64 {
+        self.x + self.length
+    }
+
+    fn top(&self) -> i64 {
+        self.y + self.height
+    }
+
+    fn z_end(&self) -> i64 {
+        self.z + self.width
+    }
+
+    fn intersects(&self, other: &Cuboid) -> bool {
+        self.x < other.x_end()
+            && other.x < self.x_end()
+            && self.y < other.top()
+            && other.y < self.top()
+            && self.z < other.z_end()
+            && other.z < self.z_end()
+    }
+
+    /// Overlap of the two footprints seen from above, as (x, z, x_end, z_end).
+    fn footprint_overlap(&self, other: &Cuboid) -> Option<(i64, i64, i64, i64)> {
+        let x0 = self.x.max(other.x);
+        let z0 = self.z.max(other.z);
+        let x1 = self.x_end().min(other.x_end());
+        let z1 = self.z_end().min(other.z_end());
+        (x0 < x1 && z0 < z1).then_some((x0, z0, x1, z1))
+    }
+
+    fn covers_point(&self, (x, y, z): (i64, i64, i64)) -> bool {
+        (self.x..self.x_end()).contains(&x)
+            && (self.y..self.top()).contains(&y)
+            && (self.z..self.z_end()).contains(&z)
+    }
+}
+
+/// Something an item can rest on or collide with.
+struct Solid {
+    bounds: Cuboid,
+    /// Whether other items may rest on it.
+    carries: bool,
+    /// Stack level of an item resting on it.
+    level_above: i32,
+    /// Stop rank of the item; wheel wells never block anything.
+    stop: u32,
+}
+
+struct Hold {
+    length: i64,
+    width: i64,
+    height: i64,
+    solids: Vec<Solid>,
+    /// Candidate corners as (y, x, z), so iteration fills the floor first,
+    /// then from the cab, then across.
+    points: BTreeSet<(i64, i64, i64)>,
+}
+
+impl Hold {
+    fn new(van: &Van) -> Self {
+        let mut hold = Hold {
+            length: van.length_mm,
+            width: van.width_mm,
+            height: van.height_mm,
+            solids: Vec::new(),
+            points: BTreeSet::new(),
+        };
+        hold.points.insert((0, 0, 0));
+
+        let well_height = van.wheel_well_height_mm.unwrap_or(0);
+        let well_width = van.wheel_well_width_mm.unwrap_or(0);
+        let well_start = van.wheel_well_start_x_mm.unwrap_or(van.length_mm);
+        if well_height > 0 && well_width > 0 && well_start < van.length_mm {
+            let well_length = van.length_mm - well_start;
+            for z in [0, van.width_mm - well_width] {
+                hold.solids.push(Solid {
+                    bounds: Cuboid {
+                        x: 0,
+                        y: 0,
+                        z,
+                        length: well_length,
+                        height: well_height,
+                        width: well_width,
+                    },
+                    carries: true,
+                    level_above: 0,
+                    stop: 0,
+                });
+                hold.add_point((0, well_height, z));
+            }
+            hold.add_point((0, 0, well_width));
+            hold.add_point((well_length, 0, 0));
+        }
+        hold
+    }
+
+    fn inside(&self, c: &Cuboid) -> bool {
+        c.x >= 0
+            && c.y >= 0
+            && c.z >= 0
+            && c.x_end() <= self.length
+            && c.top() <= self.height
+            && c.z_end() <= self.width
+    }
+
+    fn fits(&self, c: &Cuboid) -> bool {
+        self.inside(c) && !self.solids.iter().any(|s| s.bounds.intersects(c))
+    }
+
+    /// Whether something already rests where the top of `c` would be.
+    fn loads_top(&self, c: &Cuboid) -> bool {
+        self.solids
+            .iter()
+            .any(|s| s.bounds.y == c.top() && s.bounds.footprint_overlap(c).is_some())
+    }
+
+    /// Whether an item for a later stop would rest on `c` or stand between it
+    /// and the rear doors, which are at the far end of the frame.
+    fn blocked(&self, c: &Cuboid, stop: u32) -> bool {
+        self.solids.iter().filter(|s| s.stop > stop).any(|s| {
+            let b = &s.bounds;
+            let above = b.y >= c.top() && b.footprint_overlap(c).is_some();
+            let in_front = b.x >= c.x_end()
+                && b.y < c.top()
+                && c.y < b.top()
+                && b.z < c.z_end()
+                && c.z < b.z_end();
+            above || in_front
+        })
+    }
+
+    /// Stack level of `c` if it stands on the floor or is carried well
+    /// enough by what is directly below it.
+    fn support(&self, c: &Cuboid) -> Option<i32> {
+        if c.y == 0 {
+            return Some(0);
+        }
+        let mut area = 0;
+        let mut level = 0;
+        let mut bounds: Option<(i64, i64, i64, i64)> = None;
+        for solid in self.solids.iter().filter(|s| s.bounds.top() == c.y) {
+            let Some((x0, z0, x1, z1)) = solid.bounds.footprint_overlap(c) else {
+                continue;
+            };
+            if !solid.carries {
+                return None;
+            }
+            area += (x1 - x0) * (z1 - z0);
+            level = level.max(solid.level_above);
+            bounds = Some(match bounds {
+                None => (x0, z0, x1, z1),
+                Some((a, b, c, d)) => (a.min(x0), b.min(z0), c.max(x1), d.max(z1)),
+            });
+        }
+        let (x0, z0, x1, z1) = bounds?;
+        if area * 100 < c.length * c.width * MIN_SUPPORT_PERCENT {
+            return None;
+        }
+        // The centre of the base has to lie over the supported region.
+        let (cx, cz) = (2 * c.x + c.length, 2 * c.z + c.width);
+        if cx < 2 * x0 || cx > 2 * x1 || cz < 2 * z0 || cz > 2 * z1 {
+            return None;
+        }
+        Some(level)
+    }
+
+    fn add_point(&mut self, (x, y, z): (i64, i64, i64)) {
+        if x >= self.length || y >= self.height || z >= self.width {
+            return;
+        }
+        if self.solids.iter().any(|s| s.bounds.covers_point((x, y, z))) {
+            return;
+        }
+        self.points.insert((y, x, z));
+    }
+
+    /// Height of the highest surface at or below `y` under (`x`, `z`).
+    fn surface_below(&self, x: i64, y: i64, z: i64) -> i64 {
+        self.solids
+            .iter()
+            .filter(|s| {
+                s.bounds.top() <= y
+                    && (s.bounds.x..s.bounds.x_end()).contains(&x)
+                    && (s.bounds.z..s.bounds.z_end()).contains(&z)
+            })
+            .map(|s| s.bounds.top())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn place(&mut self, c: Cuboid, carries: bool, level: i32, stop: u32) {
+        self.solids.push(Solid {
+            bounds: c,
+            carries,
+            level_above: level + 1,
+            stop,
+        });
+        self.points.retain(|&(y, x, z)| !c.covers_point((x, y, z)));
+
+        for (x, y, z) in [(c.x_end(), c.y, c.z), (c.x, c.y, c.z_end())] {
+            self.add_point((x, y, z));
+            if y > 0 {
+                let dropped = self.surface_below(x, y, z);
+                self.add_point((x, dropped, z));
+            }
+        }
+        self.add_point((c.x, c.top(), c.z));
+    }
+}
+
+/// Distinct (length, width, height) orientations. Items that may not be
+/// rotated keep their height and can only turn on the floor.
+fn orientations(item: &CargoItem) -> Vec<(i64, i64, i64)> {
+    let (l, w, h) = (item.length_mm, item.width_mm, item.height_mm);
+    let mut all = if item.rotation_allowed {
+        vec![
+            (l, w, h),
+            (w, l, h),
+            (l, h, w),
+            (h, l, w),
+            (w, h, l),
+            (h, w, l),
+        ]
+    } else {
+        vec![(l, w, h), (w, l, h)]
+    };
+    let mut seen = HashSet::new();
+    all.retain(|o| seen.insert(*o));
+    all
+}
+
+/// Rotation for the viewer, with `y` pointing up: turned on the floor is a
+/// turn about `y`, tipped onto the side about `x`, tipped onto the end
+/// about `z`.
+fn rotation_of(item: &CargoItem, length: i64, height: i64) -> Rotation3D {
+    let (x, y, z) = if height == item.height_mm {
+        (0, if length == item.length_mm { 0 } else { 90 }, 0)
+    } else if height == item.width_mm {
+        (90, 0, 0)
+    } else {
+        (0, 0, 90)
+    };
+    Rotation3D { x, y, z }
+}
+
+/// Loads the items into the van greedily: large bases first so they can
+/// carry the rest, fragile items last so they end up on top. The van is
+/// filled from the cab, which keeps the weight ahead of the rear axle; each
+/// item goes to the lowest free corner nearest the cab, then leftmost, where
+/// it is carried, lying as flat as it may.
+///
+/// With delivery stops the last stop is loaded first, and no item may end up
+/// under or behind one for a later stop.
+pub fn pack(van: &Van, items: &[CargoItem]) -> OptimizeResponse {
+    let mut hold = Hold::new(van);
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    order.sort_by_key(|&i| {
+        let it = &items[i];
+        let (l, w, h) = (it.length_mm, it.width_mm, it.height_mm);
+        (
+            std::cmp::Reverse(stop_rank(it.stop_sequence)),
+            it.fragile,
+            std::cmp::Reverse((l * w).max(w * h).max(l * h)),
+            std::cmp::Reverse(l * w * h),
+        )
+    });
+
+    let mut placed = Vec::new();
+    let mut unplaced = Vec::new();
+    let mut warnings = Vec::new();
+    let mut total_weight = 0.0;
+    let mut used_volume = 0;
+    let mut over_weight = 0;
+    // Shapes that found no room since the last placement; stacking rules
+    // and the stop change where an item may go, so they are part of it.
+    let mut no_room: HashSet<(i64, i64, i64, bool, bool, bool, u32)> = HashSet::new();
+
+    for idx in order {
+        let item = &items[idx];
+        if item.length_mm <= 0 || item.width_mm <= 0 || item.height_mm <= 0 {
+            warnings.push(format!("Item '{}' has no size", item.description));
+            unplaced.push(item.clone());
+            continue;
+        }
+        if let Some(max) = van.max_weight_kg {
+            if total_weight + item.weight_kg > max as f64 {
+                over_weight += 1;
+                unplaced.push(item.clone());
+                continue;
+            }
+        }
+        let stop = stop_rank(item.stop_sequence);
+        let key = (
+            item.length_mm,
+            item.width_mm,
+            item.height_mm,
+            item.rotation_allowed,
+            item.stackable,
+            item.fragile,
+            stop,
+        );
+        if no_room.contains(&key) {
+            unplaced.push(item.clone());
+            continue;
+        }
+
+        let carries = carries_load(item);
+        let mut best: Option<(Cuboid, i32)> = None;
+        'points: for &(y, x, z) in &hold.points {
+            for (length, width, height) in orientations(item) {
+                let c = Cuboid {
+                    x,
+                    y,
+                    z,
+                    length,
+                    height,
+                    width,
+                };
+                if !hold.fits(&c) || (!carries && hold.loads_top(&c)) || hold.blocked(&c, stop) {
+                    continue;
+                }
+                let Some(level) = hold.support(&c) else {
+                    continue;
+                };
+                // Keep the load low, then the wall short along the van.
+                let better =
+                    best.map_or(true, |(b, _)| (c.height, c.length) < (b.height, b.length));
+                if better {
+                    best = Some((c, level));
+                }
+            }
+            if best.is_some() {
+                break 'points;
+            }
+        }
+
+        let Some((c, level)) = best else {
+            no_room.insert(key);
+            unplaced.push(item.clone());
+            continue;
+        };
+        hold.place(c, carries, level, stop);
+        no_room.clear();
+        total_weight += item.weight_kg;
+        used_volume += c.length * c.width * c.height;
+        placed.push(PositionedItem {
+            item: item.clone(),
+            position: Position3D {
+                x: van.length_mm - c.x_end(),
+                y: c.y,
+                z: c.z,
+            },
+            rotation: rotation_of(item, c.length, c.height),
+            level,
+            placed_length: c.length,
+            placed_width: c.width,
+            placed_height: c.height,
+            blocks_access: false,
+            supported_percent: None,
+        });
+    }
+
+    if over_weight > 0 {
+        warnings.push(format!(
+            "{} item(s) left out to stay under {} kg",
+            over_weight,
+            van.max_weight_kg.unwrap_or(0)
+        ));
+    }
+    if !unplaced.is_empty() {
+        warnings.push(format!("Items not packed: {}", unplaced.len()));
+    }
+
+    let van_volume = van.length_mm * van.width_mm * van.height_mm;
+    OptimizeResponse {
+        success: true,
+        plan: Some(LoadingPlan {
+            items: placed,
+            total_weight,
+            utilization_percent: if van_volume > 0 {
+                used_volume as f64 / van_volume as f64 * 100.0
+            } else {
+                0.0
+            },
+            van_volume,
+            used_volume,
+            balance: None,
+        }),
+        unplaced_items: unplaced,
+        warnings,
+        plan_id: None,
+        unloading: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn van(length_mm: i64, width_mm: i64, height_mm: i64) -> Van {
+        Van {
+            id: 1,
+            name: "Test".to_string(),
+            length_mm,
+            width_mm,
+            height_mm,
+            max_weight_kg: None,
+            wheel_well_height_mm: None,
+            wheel_well_width_mm: None,
+            wheel_well_start_x_mm: None,
+            rear_axle_x_mm: None,
+            front_axle_x_mm: None,
+            max_rear_axle_kg: None,
+            max_front_axle_kg: None,
+            max_side_imbalance_percent: None,
+            active: true,
+            notes: None,
+            created_at: String::new(),
+        }
+    }
+
+    fn item(length_mm: i64, width_mm: i64, height_mm: i64) -> CargoItem {
+        CargoItem {
+            description: format!("{}x{}x{}", length_mm, width_mm, height_mm),
+            length_mm,
+            width_mm,
+            height_mm,
+            weight_kg: 10.0,
+            fragile: false,
+            rotation_allowed: true,
+            stackable: true,
+            color: None,
+            stop_sequence: None,
+        }
+    }
+
+    fn placed(response: &OptimizeResponse) -> &[PositionedItem] {
+        &response.plan.as_ref().expect("plan").items
+    }
+
+    #[test]
+    fn boxes_lie_flat_from_the_cab() {
+        let response = pack(&van(3000, 1700, 1800), &[item(1000, 800, 600)]);
+        let p = &placed(&response)[0];
+        assert_eq!(p.placed_height, 600);
+        assert_eq!((p.rotation.x, p.rotation.z), (0, 0));
+        assert_eq!(p.position.x + p.placed_length, 3000);
+    }
+
+    #[test]
+    fn wheel_wells_are_kept_clear() {
+        let mut v = van(3000, 1700, 1800);
+        v.wheel_well_height_mm = Some(300);
+        v.wheel_well_width_mm = Some(250);
+        v.wheel_well_start_x_mm = Some(1000);
+        let items = vec![item(800, 600, 400); 12];
+        let response = pack(&v, &items);
+        let wells = [0, 1700 - 250].map(|z| Cuboid {
+            x: 1000,
+            y: 0,
+            z,
+            length: 2000,
+            height: 300,
+            width: 250,
+        });
+        assert!(!placed(&response).is_empty());
+        for p in placed(&response) {
+            let c = Cuboid {
+                x: p.position.x,
+                y: p.position.y,
+                z: p.position.z,
+                length: p.placed_length,
+                height: p.placed_height,
+                width: p.placed_width,
+            };
+            assert!(wells.iter().all(|w| !w.intersects(&c)), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn nothing_rests_on_fragile_or_unstackable_items() {
+        // The van takes exactly one box on the floor and one on top of it.
+        let v = van(1000, 1000, 2000);
+        let mut base = item(1000, 1000, 500);
+        base.rotation_allowed = false;
+
+        let mut fragile = base.clone();
+        fragile.fragile = true;
+        let response = pack(&v, &[fragile.clone(), base.clone()]);
+        assert_eq!(placed(&response).len(), 2);
+        assert!(placed(&response)[1].item.fragile);
+        assert_eq!(placed(&response)[1].position.y, 500);
+
+        let response = pack(&v, &[fragile.clone(), fragile]);
+        assert_eq!(placed(&response).len(), 1);
+
+        let mut loose = base;
+        loose.stackable = false;
+        let response = pack(&v, &[loose.clone(), loose]);
+        assert_eq!(placed(&response).len(), 1);
+    }
+
+    #[test]
+    fn stacked_boxes_need_enough_support() {
+        let mut hold = Hold::new(&van(1000, 2000, 2000));
+        let base = Cuboid {
+            x: 0,
+            y: 0,
+            z: 0,
+            length: 1000,
+            height: 500,
+            width: 1000,
+        };
+        hold.place(base, true, 0, stop_rank(None));
+        let on_top = |z| Cuboid {
+            x: 0,
+            y: 500,
+            z,
+            length: 1000,
+            height: 500,
+            width: 1000,
+        };
+        assert_eq!(hold.support(&on_top(0)), Some(1));
+        assert_eq!(
+            hold.support(&on_top(1000 * (100 - MIN_SUPPORT_PERCENT) / 100)),
+            Some(1)
+        );
+        assert_eq!(hold.support(&on_top(600)), None);
+    }
+
+    #[test]
+    fn max_weight_leaves_items_out() {
+        let mut v = van(3000, 1700, 1800);
+        v.max_weight_kg = Some(25);
+        let response = pack(&v, &vec![item(500, 500, 500); 3]);
+        assert_eq!(placed(&response).len(), 2);
+        assert_eq!(response.unplaced_items.len(), 1);
+        assert!(response.warnings.iter().any(|w| w.contains("under 25 kg")));
+    }
+
+    #[test]
+    fn earlier_stops_sit_nearer_the_rear_doors() {
+        let items: Vec<CargoItem> = [2, 1, 3]
+            .into_iter()
+            .map(|stop| CargoItem {
+                stop_sequence: Some(stop),
+                ..item(1000, 1000, 1000)
+            })
+            .collect();
+        let response = pack(&van(3000, 1000, 1000), &items);
+        let mut by_stop: Vec<(u32, i64)> = placed(&response)
+            .iter()
+            .map(|p| (p.item.stop_sequence.unwrap(), p.position.x))
+            .collect();
+        by_stop.sort();
+        assert_eq!(by_stop, vec![(1, 0), (2, 1000), (3, 2000)]);
+    }
+}
@@ -609,7 +609,7 @@ function generatePrintableLoadingOrder(van, multiTripPlans, allCargoItems) {
                 <td>${idx + 1}</td>
                 <td>
                   <strong>${item.item.description}</strong>
-                  ${item.rotation && (item.rotation.x !== 0 || item.rotation.y !== 0) ? '<br><span class="muted small">(Rodado)</span>' : ''}
+                  ${item.rotation && (item.rotation.x !== 0 || item.rotation.y !== 0 || item.rotation.z !== 0) ? '<br><span class="muted small">(Rodado)</span>' : ''}
                 </td>
                 <td>${item.placed_length} x ${item.placed_width} x ${item.placed_height}</td>
                 <td style="font-weight: 500; color: #333;">
@@ -2088,6 +2088,10 @@ function handleExportLoadingOrder() {
               const wheelHeight = formData.get('wheel_well_height_mm');
               const wheelWidth = formData.get('wheel_well_width_mm');
               const wheelStart = formData.get('wheel_well_start_x_mm');
+              const optional = (name) => {
+                const value = formData.get(name);
+                return value !== '' && value !== null ? Number(value) : null;
+              };
               handleSaveVan({
                 name: formData.get('name'),
                 length_mm: Number(formData.get('length_mm')),
@@ -2097,6 +2101,11 @@ function handleExportLoadingOrder() {
                 wheel_well_height_mm: wheelHeight && Number(wheelHeight) > 0 ? Number(wheelHeight) : null,
                 wheel_well_width_mm: wheelWidth && Number(wheelWidth) > 0 ? Number(wheelWidth) : null,
                 wheel_well_start_x_mm: wheelStart && Number(wheelStart) > 0 ? Number(wheelStart) : null,
+                rear_axle_x_mm: optional('rear_axle_x_mm'),
+                front_axle_x_mm: optional('front_axle_x_mm'),
+                max_rear_axle_kg: optional('max_rear_axle_kg'),
+                max_front_axle_kg: optional('max_front_axle_kg'),
+                max_side_imbalance_percent: optional('max_side_imbalance_percent'),
                 notes: formData.get('notes') || null
               });
             }}>
@@ -2195,6 +2204,67 @@ function handleExportLoadingOrder() {
                     title="Distância da traseira onde as rodas começam"
                   />
                 </label>
+                <label>
+                  Eixo Traseiro (mm):
+                  <input 
+                    type="number" 
+                    name="rear_axle_x_mm" 
+                    defaultValue={editingVan?.rear_axle_x_mm ?? ''} 
+                    min="0" 
+                    max="10000" 
+                    placeholder="900 (opcional)" 
+                    title="Distância da porta traseira ao eixo traseiro"
+                  />
+                </label>
+                <label>
+                  Eixo Dianteiro (mm):
+                  <input 
+                    type="number" 
+                    name="front_axle_x_mm" 
+                    defaultValue={editingVan?.front_axle_x_mm ?? ''} 
+                    min="0" 
+                    max="10000" 
+                    placeholder="4500 (opcional)" 
+                    title="Distância da porta traseira ao eixo dianteiro"
+                  />
+                </label>
+                <label>
+                  Máx. Eixo Traseiro (kg):
+                  <input 
+                    type="number" 
+                    name="max_rear_axle_kg" 
+                    defaultValue={editingVan?.max_rear_axle_kg ?? ''} 
+                    min="1" 
+                    max="5000" 
+                    placeholder="800 (opcional)" 
+                    title="Carga máxima da mercadoria sobre o eixo traseiro"
+                  />
+                </label>
+                <label>
+                  Máx. Eixo Dianteiro (kg):
+                  <input 
+                    type="number" 
+                    name="max_front_axle_kg" 
+                    defaultValue={editingVan?.max_front_axle_kg ?? ''} 
+                    min="1" 
+                    max="5000" 
+                    placeholder="500 (opcional)" 
+                    title="Carga máxima da mercadoria sobre o eixo dianteiro"
+                  />
+                </label>
+                <label>
+                  Desequilíbrio Lateral Máx. (%):
+                  <input 
+                    type="number" 
+                    name="max_side_imbalance_percent" 
+                    defaultValue={editingVan?.max_side_imbalance_percent ?? ''} 
+                    min="1" 
+                    max="100" 
+                    step="0.1" 
+                    placeholder="10 (opcional)" 
+                    title="Diferença máxima de peso entre os lados esquerdo e direito"
+                  />
+                </label>
                 <label style={{gridColumn: '1 / -1'}}>
                   Notas:
                   <textarea 
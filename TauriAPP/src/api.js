@@ -49,6 +49,18 @@ export async function updateResto(id, payload) {
   return apiCall("POST", `/update/${id}`, JSON.stringify(payload));
 }
 
+export async function listDefects(restoId) {
+  return apiCall("GET", `/restos/${restoId}/defects`);
+}
+
+export async function addDefect(restoId, payload) {
+  return apiCall("POST", `/restos/${restoId}/defects`, JSON.stringify(payload));
+}
+
+export async function deleteDefect(id) {
+  return apiCall("DELETE", `/defects/${id}`);
+}
+
 export async function getStats() {
   return apiCall("GET", "/stats");
 }
@@ -76,17 +88,69 @@ export async function deleteVan(id) {
   return apiCall("DELETE", `/vans/${id}`);
 }
 
+// ===== STOCK SHEET API =====
+
+export async function listStockSheets() {
+  return apiCall("GET", "/stock_sheets");
+}
+
+export async function addStockSheet(payload) {
+  return apiCall("POST", "/stock_sheets", JSON.stringify(payload));
+}
+
+export async function updateStockSheet(id, payload) {
+  return apiCall("POST", `/stock_sheets/${id}`, JSON.stringify(payload));
+}
+
+export async function deleteStockSheet(id) {
+  return apiCall("DELETE", `/stock_sheets/${id}`);
+}
+
 // ===== OPTIMIZE API =====
 
-export async function optimizeLoading(van_id, items) {
-  return apiCall("POST", "/optimize", JSON.stringify({ van_id, items }));
+export async function optimizeLoading(van_id, items, plan_date) {
+  return apiCall(
+    "POST",
+    "/optimize",
+    JSON.stringify({ van_id, items, plan_date })
+  );
+}
+
+export async function optimizeFleet(items, options = {}) {
+  return apiCall("POST", "/optimize_fleet", JSON.stringify({ items, ...options }));
+}
+
+export async function validateLoading(van_id, items) {
+  return apiCall("POST", "/validate_loading", JSON.stringify({ van_id, items }));
+}
+
+export async function listLoadingPlans(filters = {}) {
+  const params = new URLSearchParams(filters).toString();
+  return apiCall("GET", params ? `/loading_plans?${params}` : "/loading_plans");
+}
+
+export async function getLoadingPlan(id) {
+  return apiCall("GET", `/loading_plans/${id}`);
+}
+
+export async function updateLoadingPlanNotes(id, notes) {
+  return apiCall(
+    "POST",
+    `/loading_plans/${id}/notes`,
+    JSON.stringify({ notes })
+  );
+}
+
+export async function deleteLoadingPlan(id) {
+  return apiCall("DELETE", `/loading_plans/${id}`);
 }
 
 export async function optimizeCuts(
   cuts,
   kerf_width_mm = 3,
   min_remainder_width_mm = 100,
-  min_remainder_height_mm = 100
+  min_remainder_height_mm = 100,
+  options = {}
 ) {
   console.log("API Call - optimizeCuts with:", {
     cuts,
@@ -100,6 +164,7 @@ export async function optimizeCuts(
     kerf_width_mm,
     min_remainder_width_mm,
     min_remainder_height_mm,
+    ...options,
   };
 
   const result = await apiCall(
@@ -110,3 +175,80 @@ export async function optimizeCuts(
   console.log("API Response data:", result);
   return result;
 }
+
+export async function importCuts(csv, options = {}) {
+  return apiCall("POST", "/import_cuts", JSON.stringify({ csv, ...options }));
+}
+
+export async function commitCuts(plan, options = {}) {
+  return apiCall("POST", "/commit_cuts", JSON.stringify({ plan, ...options }));
+}
+
+export async function commitCutJob(cut_job_id, options = {}) {
+  return apiCall(
+    "POST",
+    "/commit_cuts",
+    JSON.stringify({ cut_job_id, ...options })
+  );
+}
+
+export async function verifyCuts(plan, cuts = []) {
+  return apiCall("POST", "/verify_cuts", JSON.stringify({ plan, cuts }));
+}
+
+export async function cutSequence(plan, resto_id = null) {
+  return apiCall("POST", "/cut_sequence", JSON.stringify({ plan, resto_id }));
+}
+
+export async function listReservations() {
+  return apiCall("GET", "/reservations");
+}
+
+export async function listCutJobs(filters = {}) {
+  const params = new URLSearchParams(filters).toString();
+  return apiCall("GET", params ? `/cut_jobs?${params}` : "/cut_jobs");
+}
+
+export async function getCutJob(id) {
+  return apiCall("GET", `/cut_jobs/${id}`);
+}
+
+export async function setCutJobStatus(id, status, changedBy = null, note = null) {
+  return apiCall(
+    "POST",
+    `/cut_jobs/${id}/status`,
+    JSON.stringify({ status, changed_by: changedBy, note })
+  );
+}
+
+export async function rerunCutJob(id) {
+  return apiCall("POST", `/cut_jobs/${id}/rerun`, JSON.stringify({}));
+}
+
+export async function diffCutJobs(id, otherId) {
+  return apiCall("GET", `/cut_jobs/${id}/diff/${otherId}`);
+}
+
+export async function startCutJob(cuts, options = {}) {
+  return apiCall(
+    "POST",
+    "/jobs/optimize_cuts",
+    JSON.stringify({ cuts, ...options })
+  );
+}
+
+export async function startLoadingJob(van_id, items, plan_date) {
+  return apiCall(
+    "POST",
+    "/jobs/optimize",
+    JSON.stringify({ van_id, items, plan_date })
+  );
+}
+
+export async function getJob(id) {
+  return apiCall("GET", `/jobs/${id}`);
+}
+
+export async function cancelJob(id) {
+  return apiCall("POST", `/jobs/${id}/cancel`);
+}